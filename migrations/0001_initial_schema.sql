@@ -0,0 +1,34 @@
+-- Users, their accounts and the transactions posted against them.
+CREATE TABLE USERS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    username TEXT NOT NULL UNIQUE,
+    password TEXT NOT NULL, -- Argon2id PHC string
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP, -- SQLite uses TEXT for TIMESTAMP and DATETIME
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP -- ON UPDATE CURRENT_TIMESTAMP is not directly supported by SQLite
+);
+
+CREATE TABLE ACCOUNTS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    account_number TEXT NOT NULL UNIQUE,
+    user_id INTEGER NOT NULL,
+    balance INTEGER NOT NULL, -- minor units (e.g. cents), never a float
+    currency TEXT NOT NULL DEFAULT 'USD', -- ISO 4217 code
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_account_user FOREIGN KEY(user_id) REFERENCES USERS(id)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE
+);
+
+CREATE TABLE TRANSACTIONS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    account_number TEXT NOT NULL,
+    seller TEXT NOT NULL,
+    amount INTEGER NOT NULL, -- minor units (e.g. cents), never a float
+    currency TEXT NOT NULL, -- ISO 4217 code
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_tx_account FOREIGN KEY(account_number) REFERENCES ACCOUNTS(account_number)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE
+);
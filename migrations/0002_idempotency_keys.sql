@@ -0,0 +1,14 @@
+-- Remembers the outcome of POSTs sent with an Idempotency-Key so retries replay it.
+CREATE TABLE IDEMPOTENCY_KEYS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    user_id INTEGER NOT NULL,
+    idempotency_key TEXT NOT NULL,
+    fingerprint TEXT NOT NULL, -- hex SHA-256 of the request body
+    response_status INTEGER, -- NULL while the original request is still in flight
+    response_body TEXT,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT uq_idempotency_user_key UNIQUE (user_id, idempotency_key),
+    CONSTRAINT fk_idempotency_user FOREIGN KEY(user_id) REFERENCES USERS(id)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE
+);
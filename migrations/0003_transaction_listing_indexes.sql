@@ -0,0 +1,4 @@
+-- Keyset pagination orders by (sort column, id) within an account.
+CREATE INDEX idx_transactions_account_created ON TRANSACTIONS (account_number, created_at, id);
+CREATE INDEX idx_transactions_account_amount ON TRANSACTIONS (account_number, amount, id);
+CREATE INDEX idx_accounts_user ON ACCOUNTS (user_id);
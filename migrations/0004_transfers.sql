@@ -0,0 +1,21 @@
+-- Account-to-account transfers; each one links a debit and a credit leg in TRANSACTIONS.
+CREATE TABLE TRANSFERS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    source_account_number TEXT NOT NULL,
+    destination_account_number TEXT NOT NULL,
+    amount INTEGER NOT NULL, -- debited from the source, minor units
+    currency TEXT NOT NULL,
+    credited_amount INTEGER NOT NULL, -- credited to the destination, minor units
+    credited_currency TEXT NOT NULL,
+    rate TEXT, -- destination units per source unit; NULL when currencies match
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_transfer_source FOREIGN KEY(source_account_number) REFERENCES ACCOUNTS(account_number)
+        ON UPDATE CASCADE,
+    CONSTRAINT fk_transfer_destination FOREIGN KEY(destination_account_number) REFERENCES ACCOUNTS(account_number)
+        ON UPDATE CASCADE,
+    CONSTRAINT ck_transfer_distinct CHECK (source_account_number <> destination_account_number),
+    CONSTRAINT ck_transfer_positive CHECK (amount > 0 AND credited_amount > 0)
+);
+
+ALTER TABLE TRANSACTIONS ADD COLUMN transfer_id INTEGER REFERENCES TRANSFERS(id);
+CREATE INDEX idx_transactions_transfer ON TRANSACTIONS (transfer_id);
@@ -0,0 +1,65 @@
+-- Double-entry ledger. Every balance movement is a journal entry whose postings
+-- sum to zero per currency. Posting amounts are positive for debits and negative
+-- for credits; customer accounts are credit-normal, so an account's balance is
+-- minus the sum of its postings.
+CREATE TABLE JOURNAL_ENTRIES (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    description TEXT NOT NULL,
+    transaction_id INTEGER REFERENCES TRANSACTIONS(id),
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE POSTINGS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    journal_entry_id INTEGER NOT NULL REFERENCES JOURNAL_ENTRIES(id),
+    ledger_account TEXT NOT NULL, -- an ACCOUNTS.account_number or a SYS: system account
+    amount INTEGER NOT NULL, -- minor units, debit positive
+    currency TEXT NOT NULL
+);
+CREATE INDEX idx_postings_ledger_account ON POSTINGS (ledger_account, currency);
+CREATE INDEX idx_postings_journal_entry ON POSTINGS (journal_entry_id);
+CREATE INDEX idx_journal_entries_transaction ON JOURNAL_ENTRIES (transaction_id);
+
+-- the ledger is append-only; corrections are new entries
+CREATE TRIGGER postings_no_update BEFORE UPDATE ON POSTINGS
+BEGIN
+    SELECT RAISE(ABORT, 'postings are append-only');
+END;
+CREATE TRIGGER postings_no_delete BEFORE DELETE ON POSTINGS
+BEGIN
+    SELECT RAISE(ABORT, 'postings are append-only');
+END;
+
+-- backfill one entry per existing transaction, against the same system
+-- accounts the application uses for new ones
+INSERT INTO JOURNAL_ENTRIES (id, description, transaction_id, created_at)
+SELECT id, seller, id, created_at FROM TRANSACTIONS;
+INSERT INTO POSTINGS (journal_entry_id, ledger_account, amount, currency)
+SELECT id, account_number, amount, currency FROM TRANSACTIONS;
+INSERT INTO POSTINGS (journal_entry_id, ledger_account, amount, currency)
+SELECT id,
+    CASE
+        WHEN transfer_id IS NOT NULL THEN 'SYS:TRANSFER_CLEARING'
+        WHEN amount > 0 THEN 'SYS:EXTERNAL_SELLERS'
+        ELSE 'SYS:DEPOSITS'
+    END,
+    -amount, currency
+FROM TRANSACTIONS;
+
+-- whatever balance the transactions do not explain becomes an opening deposit
+CREATE TEMP TABLE OPENING_BALANCES AS
+SELECT (SELECT COALESCE(MAX(id), 0) FROM JOURNAL_ENTRIES) + ROW_NUMBER() OVER (ORDER BY id) AS entry_id,
+    account_number, currency, opening
+FROM (
+    SELECT a.id, a.account_number, a.currency,
+        a.balance + COALESCE((SELECT SUM(t.amount) FROM TRANSACTIONS t WHERE t.account_number = a.account_number), 0) AS opening
+    FROM ACCOUNTS a
+)
+WHERE opening <> 0;
+INSERT INTO JOURNAL_ENTRIES (id, description)
+SELECT entry_id, 'Opening balance' FROM OPENING_BALANCES;
+INSERT INTO POSTINGS (journal_entry_id, ledger_account, amount, currency)
+SELECT entry_id, account_number, -opening, currency FROM OPENING_BALANCES;
+INSERT INTO POSTINGS (journal_entry_id, ledger_account, amount, currency)
+SELECT entry_id, 'SYS:DEPOSITS', opening, currency FROM OPENING_BALANCES;
+DROP TABLE OPENING_BALANCES;
@@ -0,0 +1,3 @@
+-- Bumped on every balance change, so writers can detect that an account moved
+-- under them.
+ALTER TABLE ACCOUNTS ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,4 @@
+-- IBANs are derived from the account number when the account is opened.
+-- Accounts that predate this column get theirs at the next startup.
+ALTER TABLE ACCOUNTS ADD COLUMN iban TEXT;
+CREATE UNIQUE INDEX idx_accounts_iban ON ACCOUNTS (iban);
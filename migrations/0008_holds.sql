@@ -0,0 +1,23 @@
+-- Card authorizations. A pending hold reserves funds: it lowers the available
+-- balance (balance - held) but not the ledger balance until it is captured.
+CREATE TABLE HOLDS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    account_number TEXT NOT NULL,
+    seller TEXT NOT NULL,
+    amount INTEGER NOT NULL, -- authorized, minor units
+    currency TEXT NOT NULL,
+    captured_amount INTEGER NOT NULL DEFAULT 0, -- minor units
+    status TEXT NOT NULL DEFAULT 'pending', -- pending, captured, voided or expired
+    transaction_id INTEGER REFERENCES TRANSACTIONS(id), -- set on capture
+    expires_at TEXT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_hold_account FOREIGN KEY(account_number) REFERENCES ACCOUNTS(account_number)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE,
+    CONSTRAINT ck_hold_amounts CHECK (amount > 0 AND captured_amount BETWEEN 0 AND amount)
+);
+CREATE INDEX idx_holds_pending_expiry ON HOLDS (status, expires_at);
+
+-- sum of the account's pending holds
+ALTER TABLE ACCOUNTS ADD COLUMN held INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- Refunds are compensating transactions linked to the one they undo.
+ALTER TABLE TRANSACTIONS ADD COLUMN original_transaction_id INTEGER REFERENCES TRANSACTIONS(id);
+-- on the original: how much of it has been refunded so far, minor units
+ALTER TABLE TRANSACTIONS ADD COLUMN refunded_amount INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_transactions_original ON TRANSACTIONS (original_transaction_id);
@@ -0,0 +1,40 @@
+-- Scheduled and recurring payments, executed by a background worker.
+CREATE TABLE SCHEDULES (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    user_id INTEGER NOT NULL,
+    account_number TEXT NOT NULL,
+    seller TEXT NOT NULL,
+    amount INTEGER NOT NULL, -- minor units, posted as a debit
+    currency TEXT NOT NULL,
+    frequency TEXT NOT NULL, -- once, daily, weekly, monthly or cron
+    cron TEXT, -- only for frequency = 'cron'
+    start_at TEXT NOT NULL, -- first occurrence, UTC
+    end_at TEXT, -- no occurrences after this
+    max_runs INTEGER, -- no more occurrences than this
+    on_insufficient_funds TEXT NOT NULL DEFAULT 'retry', -- retry or skip
+    occurrences INTEGER NOT NULL DEFAULT 0, -- occurrences dealt with so far
+    retry_count INTEGER NOT NULL DEFAULT 0, -- retries of the current occurrence
+    next_run_at TEXT, -- the current occurrence; NULL once finished
+    due_at TEXT, -- when the worker next attempts it; later than next_run_at while retrying
+    status TEXT NOT NULL DEFAULT 'active', -- active, completed or cancelled
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_schedule_user FOREIGN KEY(user_id) REFERENCES USERS(id)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE,
+    CONSTRAINT fk_schedule_account FOREIGN KEY(account_number) REFERENCES ACCOUNTS(account_number)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE
+);
+CREATE INDEX idx_schedules_due ON SCHEDULES (status, due_at);
+
+CREATE TABLE SCHEDULE_RUNS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    schedule_id INTEGER NOT NULL REFERENCES SCHEDULES(id) ON DELETE CASCADE,
+    occurrence INTEGER NOT NULL, -- 0 for the first
+    scheduled_for TEXT NOT NULL,
+    outcome TEXT NOT NULL, -- succeeded, retrying, skipped or failed
+    message TEXT,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_schedule_runs_schedule ON SCHEDULE_RUNS (schedule_id, id);
@@ -0,0 +1,8 @@
+-- Overdrafts: how far below zero an account may go, and what each debit that
+-- leaves it below zero costs. Both are minor units in the account's currency.
+ALTER TABLE ACCOUNTS ADD COLUMN overdraft_limit INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE ACCOUNTS ADD COLUMN overdraft_fee INTEGER NOT NULL DEFAULT 0;
+CREATE INDEX idx_accounts_balance ON ACCOUNTS (balance);
+
+-- customer or admin; admins are named in ADMIN_USERNAMES at startup
+ALTER TABLE USERS ADD COLUMN role TEXT NOT NULL DEFAULT 'customer';
@@ -0,0 +1,34 @@
+-- Account products and the interest they earn.
+CREATE TABLE PRODUCTS (
+    code TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    annual_rate TEXT NOT NULL DEFAULT '0', -- decimal fraction, '0.0425' for 4.25%
+    day_count TEXT NOT NULL DEFAULT 'act_365', -- act_365, act_360 or act_act
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+INSERT INTO PRODUCTS (code, name, annual_rate, day_count) VALUES
+    ('checking', 'Checking', '0', 'act_365'),
+    ('savings', 'Savings', '0.02', 'act_365');
+
+-- not a foreign key: SQLite cannot add one with a non-NULL default
+ALTER TABLE ACCOUNTS ADD COLUMN product TEXT NOT NULL DEFAULT 'checking';
+
+-- One row per account and day, for accounts whose product pays interest.
+CREATE TABLE INTEREST_ACCRUALS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    account_number TEXT NOT NULL,
+    accrual_date TEXT NOT NULL, -- YYYY-MM-DD, UTC
+    balance INTEGER NOT NULL, -- end-of-day balance, minor units
+    currency TEXT NOT NULL,
+    annual_rate TEXT NOT NULL, -- as it was on the day
+    day_count TEXT NOT NULL,
+    accrued_micros INTEGER NOT NULL, -- millionths of a minor unit
+    transaction_id INTEGER REFERENCES TRANSACTIONS(id), -- the month's interest posting
+    posted INTEGER NOT NULL DEFAULT 0, -- 1 once its month was posted, even if to nothing
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    CONSTRAINT fk_accrual_account FOREIGN KEY(account_number) REFERENCES ACCOUNTS(account_number)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE,
+    CONSTRAINT uq_accrual_day UNIQUE (account_number, accrual_date)
+);
+CREATE INDEX idx_interest_accruals_unposted ON INTEREST_ACCRUALS (posted, accrual_date);
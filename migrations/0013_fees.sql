@@ -0,0 +1,27 @@
+-- Fee rules per account product, and the fee transactions they produce.
+CREATE TABLE FEE_RULES (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    product TEXT NOT NULL REFERENCES PRODUCTS(code) ON DELETE CASCADE,
+    kind TEXT NOT NULL, -- per_transaction, monthly_maintenance, foreign_currency or overdraft
+    amount INTEGER NOT NULL DEFAULT 0, -- flat part, minor units
+    currency TEXT NOT NULL, -- the rule only applies to accounts in this currency
+    rate TEXT, -- proportional part, a decimal fraction of the transaction amount
+    waive_above INTEGER, -- waived while the balance is at least this, minor units
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_fee_rules_product ON FEE_RULES (product, kind, currency);
+
+-- set on fee transactions: what kind of fee, and the transaction that caused it
+ALTER TABLE TRANSACTIONS ADD COLUMN fee_kind TEXT;
+ALTER TABLE TRANSACTIONS ADD COLUMN fee_for_transaction_id INTEGER REFERENCES TRANSACTIONS(id);
+CREATE INDEX idx_transactions_fee_for ON TRANSACTIONS (fee_for_transaction_id);
+
+-- One row per account and month the maintenance fee was dealt with, charged or waived.
+CREATE TABLE MAINTENANCE_FEE_PERIODS (
+    account_number TEXT NOT NULL REFERENCES ACCOUNTS(account_number)
+        ON DELETE CASCADE
+        ON UPDATE CASCADE,
+    month TEXT NOT NULL, -- YYYY-MM
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (account_number, month)
+);
@@ -0,0 +1,34 @@
+-- Domain events, written in the same database transaction as the change they
+-- describe, and the webhooks they are delivered to.
+CREATE TABLE OUTBOX_EVENTS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    event_type TEXT NOT NULL, -- user.created, account.created, transaction.posted or transfer.completed
+    payload TEXT NOT NULL, -- JSON
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    dispatched_at TEXT -- when deliveries were queued for it; NULL until then
+);
+CREATE INDEX idx_outbox_events_undispatched ON OUTBOX_EVENTS (id) WHERE dispatched_at IS NULL;
+
+CREATE TABLE WEBHOOKS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL, -- HMAC-SHA256 key deliveries are signed with
+    event_types TEXT, -- comma-separated; NULL for every event
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+
+-- One row per event and webhook it is sent to.
+CREATE TABLE WEBHOOK_DELIVERIES (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    webhook_id INTEGER NOT NULL REFERENCES WEBHOOKS(id) ON DELETE CASCADE,
+    event_id INTEGER NOT NULL REFERENCES OUTBOX_EVENTS(id),
+    status TEXT NOT NULL DEFAULT 'pending', -- pending, delivered or dead
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TEXT NOT NULL, -- UTC
+    last_status_code INTEGER, -- HTTP status of the last attempt, if it got one
+    last_error TEXT,
+    delivered_at TEXT,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (webhook_id, event_id)
+);
+CREATE INDEX idx_webhook_deliveries_due ON WEBHOOK_DELIVERIES (status, next_attempt_at);
@@ -0,0 +1,25 @@
+-- Who changed what: one row per mutation of users, accounts and transactions.
+CREATE TABLE AUDIT_LOG (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    actor_user_id INTEGER, -- the authenticated caller; NULL for anonymous requests and the system
+    action TEXT NOT NULL, -- e.g. account.set_overdraft
+    entity_type TEXT NOT NULL, -- user, account or transaction
+    entity_id TEXT NOT NULL,
+    before TEXT, -- JSON snapshot of the entity before the change; NULL when it was created
+    after TEXT, -- JSON snapshot after the change
+    request_id TEXT, -- X-Request-Id of the request that made the change
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+CREATE INDEX idx_audit_log_entity ON AUDIT_LOG (entity_type, entity_id);
+CREATE INDEX idx_audit_log_actor ON AUDIT_LOG (actor_user_id);
+CREATE INDEX idx_audit_log_request ON AUDIT_LOG (request_id);
+
+-- the audit log is append-only
+CREATE TRIGGER audit_log_no_update BEFORE UPDATE ON AUDIT_LOG
+BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;
+CREATE TRIGGER audit_log_no_delete BEFORE DELETE ON AUDIT_LOG
+BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;
@@ -0,0 +1,5 @@
+-- Account lifecycle: only active accounts take postings, closed is final.
+ALTER TABLE ACCOUNTS ADD COLUMN status TEXT NOT NULL DEFAULT 'active'
+    CHECK (status IN ('active', 'frozen', 'dormant', 'closed'));
+-- why the account was last moved to its status; NULL while it never was
+ALTER TABLE ACCOUNTS ADD COLUMN status_reason TEXT;
@@ -0,0 +1,21 @@
+-- Merchants: the canonical party behind the many ways a seller gets spelled.
+-- Names and aliases are matched on their normalized key (see merchant_service).
+CREATE TABLE MERCHANTS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    name TEXT NOT NULL,
+    name_key TEXT NOT NULL UNIQUE,
+    category TEXT NOT NULL, -- four-digit merchant category code, e.g. 5411 for grocery stores
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    updated_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+CREATE TABLE MERCHANT_ALIASES (
+    id INTEGER PRIMARY KEY,
+    merchant_id INTEGER NOT NULL REFERENCES MERCHANTS (id) ON DELETE CASCADE,
+    alias TEXT NOT NULL,
+    alias_key TEXT NOT NULL UNIQUE
+);
+CREATE INDEX idx_merchant_aliases_merchant ON MERCHANT_ALIASES (merchant_id);
+
+-- the merchant a payment's seller was matched to; NULL when none matched
+ALTER TABLE TRANSACTIONS ADD COLUMN merchant_id INTEGER REFERENCES MERCHANTS (id) ON DELETE SET NULL;
+CREATE INDEX idx_transactions_merchant ON TRANSACTIONS (merchant_id, created_at);
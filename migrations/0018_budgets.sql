@@ -0,0 +1,28 @@
+-- Budgets: a user's monthly spending limit with one merchant or in one
+-- merchant category, in one currency.
+CREATE TABLE BUDGETS (
+    id INTEGER PRIMARY KEY, -- implies auto-increment in SQLite
+    user_id INTEGER NOT NULL REFERENCES USERS (id),
+    merchant_id INTEGER REFERENCES MERCHANTS (id) ON DELETE CASCADE,
+    category TEXT, -- four-digit merchant category code
+    amount INTEGER NOT NULL CHECK (amount > 0), -- per calendar month, in minor units
+    currency TEXT NOT NULL,
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    CHECK ((merchant_id IS NULL) <> (category IS NULL))
+);
+CREATE UNIQUE INDEX idx_budgets_target ON BUDGETS (
+    user_id, currency, COALESCE(merchant_id, 0), COALESCE(category, '')
+);
+
+-- One row per budget, month and threshold, written by the posting that first
+-- took the month's spending to that share of the budget.
+CREATE TABLE BUDGET_ALERTS (
+    id INTEGER PRIMARY KEY,
+    budget_id INTEGER NOT NULL REFERENCES BUDGETS (id) ON DELETE CASCADE,
+    period_start TEXT NOT NULL, -- first day of the month
+    threshold INTEGER NOT NULL CHECK (threshold IN (50, 80, 100)), -- percent of the budget
+    spent INTEGER NOT NULL, -- the month's spending after the posting
+    transaction_id INTEGER NOT NULL REFERENCES TRANSACTIONS (id),
+    created_at TEXT DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (budget_id, period_start, threshold)
+);
@@ -0,0 +1,79 @@
+// src/config.rs
+// Runtime configuration read from the environment at startup
+use std::env;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// SQLite connection string; the file is created if it does not exist.
+    pub database_url: String,
+    /// Secret used to sign bearer tokens. Without it a random one is generated,
+    /// which invalidates every issued token on restart.
+    pub token_secret: Option<String>,
+    pub access_token_ttl_secs: i64,
+    pub refresh_token_ttl_secs: i64,
+    /// Bank/branch digits every new account number starts with.
+    pub account_number_prefix: String,
+    /// Digits in a new account number, prefix and check digit included.
+    pub account_number_length: usize,
+    /// IBAN layout: country code, bank code and how many digits the account
+    /// number is zero-padded to. They must follow the country's BBAN structure.
+    pub iban_country_code: String,
+    pub iban_bank_code: String,
+    pub iban_account_digits: usize,
+    /// How often the scheduled payments worker looks for due payments.
+    pub schedule_poll_interval_secs: u64,
+    /// How long a scheduled payment that hit insufficient funds waits before
+    /// retrying, and how many retries it gets before it is skipped.
+    pub schedule_retry_interval_secs: i64,
+    pub schedule_max_retries: i64,
+    /// How often the webhook dispatcher looks for events to deliver.
+    pub webhook_poll_interval_secs: u64,
+    /// Attempts a webhook delivery gets before it is dead, and the wait after
+    /// the first failed one, doubled after every further failure.
+    pub webhook_max_attempts: i64,
+    pub webhook_backoff_base_secs: i64,
+    /// Ids of the users with the admin role, comma-separated. Everyone else is
+    /// a customer. Entries that are not ids are ignored with a warning.
+    pub admin_user_ids: Vec<i64>,
+}
+
+impl Config {
+    pub fn from_env() -> Self {
+        Config {
+            database_url: env::var("DATABASE_URL")
+                .unwrap_or_else(|_| "sqlite://crustacean-capital.db".to_string()),
+            token_secret: env::var("AUTH_TOKEN_SECRET").ok().filter(|s| !s.is_empty()),
+            access_token_ttl_secs: env_or("ACCESS_TOKEN_TTL_SECS", 15 * 60),
+            refresh_token_ttl_secs: env_or("REFRESH_TOKEN_TTL_SECS", 7 * 24 * 60 * 60),
+            account_number_prefix: env_or("ACCOUNT_NUMBER_PREFIX", "1000".to_string()),
+            account_number_length: env_or("ACCOUNT_NUMBER_LENGTH", 16),
+            iban_country_code: env_or("IBAN_COUNTRY_CODE", "RO".to_string()),
+            iban_bank_code: env_or("IBAN_BANK_CODE", "CRAB".to_string()),
+            iban_account_digits: env_or("IBAN_ACCOUNT_DIGITS", 16),
+            schedule_poll_interval_secs: env_or("SCHEDULE_POLL_INTERVAL_SECS", 30),
+            schedule_retry_interval_secs: env_or("SCHEDULE_RETRY_INTERVAL_SECS", 60 * 60),
+            schedule_max_retries: env_or("SCHEDULE_MAX_RETRIES", 3),
+            webhook_poll_interval_secs: env_or("WEBHOOK_POLL_INTERVAL_SECS", 5),
+            webhook_max_attempts: env_or("WEBHOOK_MAX_ATTEMPTS", 8),
+            webhook_backoff_base_secs: env_or("WEBHOOK_BACKOFF_BASE_SECS", 30),
+            admin_user_ids: env::var("ADMIN_USER_IDS")
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|id| !id.is_empty())
+                .filter_map(|id| {
+                    id.parse()
+                        .inspect_err(|_| tracing::warn!("Ignoring ADMIN_USER_IDS entry {:?}", id))
+                        .ok()
+                })
+                .collect(),
+        }
+    }
+}
+
+fn env_or<T: std::str::FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
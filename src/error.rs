@@ -0,0 +1,174 @@
+// src/error.rs
+// Defines the crate-wide error type returned by services and rendered by handlers
+use std::fmt;
+
+use axum::{
+    Json,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::models::money::MoneyError;
+
+#[derive(Debug)]
+pub enum AppError {
+    NotFound(String),
+    Unauthorized(String),
+    Forbidden(String),
+    Conflict(String),
+    Validation(String),
+    InsufficientFunds,
+    Database(sqlx::Error),
+    Internal(String),
+}
+
+/// The JSON body of every error response. `code` is stable and meant for
+/// machines; `message` is for humans and may change.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ErrorBody {
+    pub code: String,
+    pub message: String,
+}
+
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::InsufficientFunds => StatusCode::PAYMENT_REQUIRED,
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::NotFound(_) => "not_found",
+            AppError::Unauthorized(_) => "unauthorized",
+            AppError::Forbidden(_) => "forbidden",
+            AppError::Conflict(_) => "conflict",
+            AppError::Validation(_) => "validation_failed",
+            AppError::InsufficientFunds => "insufficient_funds",
+            AppError::Database(_) => "database_error",
+            AppError::Internal(_) => "internal_error",
+        }
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NotFound(msg)
+            | AppError::Unauthorized(msg)
+            | AppError::Forbidden(msg)
+            | AppError::Conflict(msg)
+            | AppError::Validation(msg) => f.write_str(msg),
+            AppError::InsufficientFunds => f.write_str("Insufficient funds"),
+            AppError::Database(err) => write!(f, "Database error: {}", err),
+            AppError::Internal(msg) => write!(f, "Internal error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for AppError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AppError::Database(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(err: sqlx::Error) -> Self {
+        match &err {
+            sqlx::Error::RowNotFound => AppError::NotFound("Resource not found".to_string()),
+            sqlx::Error::Database(db_err) if db_err.is_unique_violation() => {
+                AppError::Conflict("Resource already exists".to_string())
+            }
+            sqlx::Error::Database(db_err) if db_err.is_foreign_key_violation() => {
+                AppError::Validation("Referenced resource does not exist".to_string())
+            }
+            _ => AppError::Database(err),
+        }
+    }
+}
+
+impl From<MoneyError> for AppError {
+    fn from(err: MoneyError) -> Self {
+        AppError::Validation(err.to_string())
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let message = match &self {
+            // never leak driver internals to the client
+            AppError::Database(err) => {
+                tracing::error!("Database error: {}", err);
+                "Internal database error".to_string()
+            }
+            AppError::Internal(msg) => {
+                tracing::error!("Internal error: {}", msg);
+                "Internal server error".to_string()
+            }
+            other => other.to_string(),
+        };
+        let body = ErrorBody {
+            code: self.code().to_string(),
+            message,
+        };
+        (self.status(), Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn render(err: AppError) -> (StatusCode, ErrorBody) {
+        let res = err.into_response();
+        let status = res.status();
+        let bytes = axum::body::to_bytes(res.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_status_codes_and_bodies() {
+        let cases = [
+            (AppError::NotFound("x".into()), 404, "not_found"),
+            (AppError::Unauthorized("x".into()), 401, "unauthorized"),
+            (AppError::Forbidden("x".into()), 403, "forbidden"),
+            (AppError::Conflict("x".into()), 409, "conflict"),
+            (AppError::Validation("x".into()), 422, "validation_failed"),
+            (AppError::InsufficientFunds, 402, "insufficient_funds"),
+            (
+                AppError::Database(sqlx::Error::PoolClosed),
+                500,
+                "database_error",
+            ),
+            (AppError::Internal("x".into()), 500, "internal_error"),
+        ];
+        for (err, status, code) in cases {
+            let (s, body) = render(err).await;
+            assert_eq!(s.as_u16(), status);
+            assert_eq!(body.code, code);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_database_error_is_not_leaked() {
+        let (_, body) = render(AppError::Database(sqlx::Error::PoolClosed)).await;
+        assert_eq!(body.message, "Internal database error");
+    }
+
+    #[test]
+    fn test_row_not_found_maps_to_not_found() {
+        let err: AppError = sqlx::Error::RowNotFound.into();
+        assert!(matches!(err, AppError::NotFound(_)));
+    }
+}
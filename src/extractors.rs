@@ -0,0 +1,170 @@
+// src/extractors.rs
+// Request extractors shared by the handlers
+use axum::{
+    extract::{FromRef, FromRequestParts, Query},
+    http::{header::AUTHORIZATION, request::Parts},
+};
+use serde::Deserialize;
+
+use sqlx::SqlitePool;
+
+use crate::error::AppError;
+use crate::services::token_service::{TokenKeys, TokenKind};
+use crate::services::user_service;
+
+/// The caller identified by a valid `Authorization: Bearer <access token>` header.
+/// Adding this to a handler's arguments makes the route require authentication.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AuthUser {
+    pub user_id: i64,
+}
+
+impl<S> FromRequestParts<S> for AuthUser
+where
+    TokenKeys: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| AppError::Unauthorized("Missing bearer token".to_string()))?;
+        let claims = TokenKeys::from_ref(state).verify(token.trim(), TokenKind::Access)?;
+        Ok(AuthUser {
+            user_id: claims.sub,
+        })
+    }
+}
+
+/// Like `AuthUser`, but the access token may also come as an `access_token`
+/// query parameter, since browsers cannot set headers on `EventSource` and
+/// WebSocket connections. Only the streaming endpoints accept it; anywhere
+/// else a token in the URL would end up in logs for no reason.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct StreamUser {
+    pub user_id: i64,
+}
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    access_token: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for StreamUser
+where
+    TokenKeys: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if !parts.headers.contains_key(AUTHORIZATION)
+            && let Ok(Query(TokenQuery {
+                access_token: Some(token),
+            })) = Query::<TokenQuery>::try_from_uri(&parts.uri)
+        {
+            let claims = TokenKeys::from_ref(state).verify(token.trim(), TokenKind::Access)?;
+            return Ok(StreamUser {
+                user_id: claims.sub,
+            });
+        }
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        Ok(StreamUser {
+            user_id: user.user_id,
+        })
+    }
+}
+
+/// An authenticated caller with the admin role. The role is looked up on every
+/// request, so revoking it takes effect without waiting for tokens to expire.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct AdminUser {
+    pub user_id: i64,
+}
+
+impl<S> FromRequestParts<S> for AdminUser
+where
+    TokenKeys: FromRef<S>,
+    SqlitePool: FromRef<S>,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        if !user_service::is_admin(&SqlitePool::from_ref(state), user.user_id).await? {
+            return Err(AppError::Forbidden("Admins only".to_string()));
+        }
+        Ok(AdminUser {
+            user_id: user.user_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+
+    use super::*;
+
+    fn parts(authorization: Option<&str>) -> Parts {
+        let mut builder = Request::builder().uri("/accounts");
+        if let Some(value) = authorization {
+            builder = builder.header(AUTHORIZATION, value);
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    #[tokio::test]
+    async fn test_accepts_access_token() {
+        let keys = TokenKeys::new(b"secret", 60, 600);
+        let token = keys.issue(3, TokenKind::Access).unwrap();
+        let mut parts = parts(Some(&format!("Bearer {}", token)));
+        let user = AuthUser::from_request_parts(&mut parts, &keys)
+            .await
+            .unwrap();
+        assert_eq!(user.user_id, 3);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_missing_or_wrong_tokens() {
+        let keys = TokenKeys::new(b"secret", 60, 600);
+        let refresh = keys.issue(3, TokenKind::Refresh).unwrap();
+        for header in [
+            None,
+            Some("Basic dXNlcjpwYXNz".to_string()),
+            Some("Bearer not-a-token".to_string()),
+            Some(format!("Bearer {}", refresh)),
+        ] {
+            let mut parts = parts(header.as_deref());
+            let res = AuthUser::from_request_parts(&mut parts, &keys).await;
+            assert!(matches!(res, Err(AppError::Unauthorized(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_user_accepts_a_token_in_the_query() {
+        let keys = TokenKeys::new(b"secret", 60, 600);
+        let token = keys.issue(3, TokenKind::Access).unwrap();
+        let mut parts = parts_with_uri(&format!("/accounts/1/events?access_token={}", token));
+        let user = StreamUser::from_request_parts(&mut parts, &keys)
+            .await
+            .unwrap();
+        assert_eq!(user.user_id, 3);
+
+        let mut parts = parts_with_uri("/accounts/1/events?access_token=not-a-token");
+        let res = StreamUser::from_request_parts(&mut parts, &keys).await;
+        assert!(matches!(res, Err(AppError::Unauthorized(_))));
+        let mut parts = parts_with_uri("/accounts/1/events");
+        let res = StreamUser::from_request_parts(&mut parts, &keys).await;
+        assert!(matches!(res, Err(AppError::Unauthorized(_))));
+    }
+
+    fn parts_with_uri(uri: &str) -> Parts {
+        Request::builder().uri(uri).body(()).unwrap().into_parts().0
+    }
+}
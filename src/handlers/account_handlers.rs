@@ -1,22 +1,87 @@
+use crate::error::AppError;
+use crate::extractors::{AdminUser, AuthUser};
 use crate::models;
 use crate::services;
-use axum::{Json, extract::State};
+use crate::services::generation_service::AccountNumberScheme;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, State},
+};
 use sqlx::SqlitePool;
 
-#[axum::debug_handler]
+#[axum::debug_handler(state = AppState)]
 pub async fn get_accounts(
     State(db): State<SqlitePool>,
-) -> Json<Vec<models::account::AccountGeneral>> {
+    auth: AuthUser,
+) -> Result<Json<Vec<models::account::AccountGeneral>>, AppError> {
     tracing::info!("Invocation to `get_accounts`");
-    let res = services::account_service::get_accounts(&db).await;
-    Json(res.unwrap())
+    let res = services::account_service::get_accounts(&db, auth.user_id).await;
+    Ok(Json(res?))
 }
-#[axum::debug_handler]
+#[axum::debug_handler(state = AppState)]
 pub async fn create_account(
     State(db): State<SqlitePool>,
+    State(scheme): State<AccountNumberScheme>,
+    auth: AuthUser,
     account: Json<models::account::AccountCreation>,
-) -> Json<models::account::AccountGeneral> {
+) -> Result<Json<models::account::AccountGeneral>, AppError> {
     tracing::info!("Invocation to `create_accounts`");
-    let res = services::account_service::create_account(&db, account.0).await;
-    Json(res.unwrap())
+    let res =
+        services::account_service::create_account(&db, &scheme, auth.user_id, account.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_iban(
+    State(db): State<SqlitePool>,
+    _auth: AuthUser,
+    Path(iban): Path<String>,
+) -> Result<Json<models::account::IbanLookup>, AppError> {
+    tracing::info!("Invocation to `get_iban`");
+    let res = services::account_service::get_iban(&db, &iban).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn set_overdraft(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    Path(account_number): Path<String>,
+    setting: Json<models::account::OverdraftSetting>,
+) -> Result<Json<models::account::AccountGeneral>, AppError> {
+    tracing::info!("Invocation to `set_overdraft`");
+    let res = services::account_service::set_overdraft(&db, &account_number, setting.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn change_status(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    Path(account_number): Path<String>,
+    change: Json<models::account::StatusChange>,
+) -> Result<Json<models::account::AccountGeneral>, AppError> {
+    tracing::info!("Invocation to `change_status`");
+    let res = services::account_service::change_status(&db, &account_number, change.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn close_account(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(account_number): Path<String>,
+    closure: Json<models::account::AccountClosure>,
+) -> Result<Json<models::account::AccountGeneral>, AppError> {
+    tracing::info!("Invocation to `close_account`");
+    let res =
+        services::account_service::close_account(&db, auth.user_id, &account_number, closure.0)
+            .await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_overdrawn_accounts(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<models::account::AccountGeneral>>, AppError> {
+    tracing::info!("Invocation to `get_overdrawn_accounts`");
+    let res = services::account_service::get_overdrawn_accounts(&db).await;
+    Ok(Json(res?))
 }
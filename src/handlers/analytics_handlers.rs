@@ -0,0 +1,61 @@
+use crate::error::AppError;
+use crate::extractors::AuthUser;
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_spending(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Query(query): Query<models::analytics::AnalyticsQuery>,
+) -> Result<Json<Vec<models::analytics::SpendingGroup>>, AppError> {
+    tracing::info!("Invocation to `get_spending`");
+    let res = services::analytics_service::get_spending(&db, auth.user_id, &query).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_top_merchants(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Query(query): Query<models::analytics::AnalyticsQuery>,
+) -> Result<Json<Vec<models::analytics::SpendingGroup>>, AppError> {
+    tracing::info!("Invocation to `get_top_merchants`");
+    let res = services::analytics_service::get_top_merchants(&db, auth.user_id, &query).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_timeline(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Query(query): Query<models::analytics::AnalyticsQuery>,
+) -> Result<Json<Vec<models::analytics::PeriodCashFlow>>, AppError> {
+    tracing::info!("Invocation to `get_timeline`");
+    let res = services::analytics_service::get_timeline(&db, auth.user_id, &query).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_cash_flow(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Query(query): Query<models::analytics::AnalyticsQuery>,
+) -> Result<Json<models::analytics::CashFlow>, AppError> {
+    tracing::info!("Invocation to `get_cash_flow`");
+    let res = services::analytics_service::get_cash_flow(&db, auth.user_id, &query).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_comparison(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Query(query): Query<models::analytics::AnalyticsQuery>,
+) -> Result<Json<models::analytics::PeriodComparison>, AppError> {
+    tracing::info!("Invocation to `get_comparison`");
+    let res = services::analytics_service::get_comparison(&db, auth.user_id, &query).await;
+    Ok(Json(res?))
+}
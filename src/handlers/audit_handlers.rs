@@ -0,0 +1,21 @@
+use crate::error::AppError;
+use crate::extractors::AdminUser;
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_audit_log(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    Query(query): Query<models::audit::AuditQuery>,
+) -> Result<Json<models::audit::AuditPage>, AppError> {
+    tracing::info!("Invocation to `get_audit_log`");
+    let res = services::audit_service::get_audit_log(&db, &query).await;
+    Ok(Json(res?))
+}
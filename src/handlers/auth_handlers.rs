@@ -0,0 +1,28 @@
+use crate::error::AppError;
+use crate::models;
+use crate::services;
+use crate::services::token_service::TokenKeys;
+use crate::state::AppState;
+use axum::{Json, extract::State};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn login(
+    State(pool): State<SqlitePool>,
+    State(tokens): State<TokenKeys>,
+    credentials: Json<models::auth::LoginRequest>,
+) -> Result<Json<models::auth::TokenResponse>, AppError> {
+    tracing::info!("Invocation to `login`");
+    let user = services::auth_service::login(&pool, credentials.0).await?;
+    Ok(Json(tokens.issue_pair(user)?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn refresh(
+    State(pool): State<SqlitePool>,
+    State(tokens): State<TokenKeys>,
+    request: Json<models::auth::RefreshRequest>,
+) -> Result<Json<models::auth::TokenResponse>, AppError> {
+    tracing::info!("Invocation to `refresh`");
+    let res = services::token_service::refresh(&pool, &tokens, request.0).await;
+    Ok(Json(res?))
+}
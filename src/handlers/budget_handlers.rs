@@ -0,0 +1,51 @@
+use crate::error::AppError;
+use crate::extractors::AuthUser;
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn create_budget(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    budget: Json<models::budget::BudgetCreation>,
+) -> Result<Json<models::budget::Budget>, AppError> {
+    tracing::info!("Invocation to `create_budget`");
+    let res = services::budget_service::create_budget(&db, auth.user_id, budget.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn delete_budget(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::budget::Budget>, AppError> {
+    tracing::info!("Invocation to `delete_budget`");
+    let res = services::budget_service::delete_budget(&db, auth.user_id, id).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_alerts(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Query(query): Query<models::budget::BudgetAlertQuery>,
+) -> Result<Json<Vec<models::budget::BudgetAlert>>, AppError> {
+    tracing::info!("Invocation to `get_alerts`");
+    let res = services::budget_service::get_alerts(&db, auth.user_id, &query).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_budget_summary(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::budget::BudgetSummary>, AppError> {
+    tracing::info!("Invocation to `get_budget_summary`");
+    let res = services::budget_service::get_budget_summary(&db, auth.user_id, id).await;
+    Ok(Json(res?))
+}
@@ -0,0 +1,41 @@
+use crate::error::AppError;
+use crate::extractors::{AdminUser, AuthUser};
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_fee_rules(
+    State(db): State<SqlitePool>,
+    _auth: AuthUser,
+    Query(query): Query<models::fee::FeeRuleQuery>,
+) -> Result<Json<Vec<models::fee::FeeRule>>, AppError> {
+    tracing::info!("Invocation to `get_fee_rules`");
+    let res = services::fee_service::get_fee_rules(&db, &query).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn create_fee_rule(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    rule: Json<models::fee::FeeRuleCreation>,
+) -> Result<Json<models::fee::FeeRule>, AppError> {
+    tracing::info!("Invocation to `create_fee_rule`");
+    let res = services::fee_service::create_fee_rule(&db, rule.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn delete_fee_rule(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::fee::FeeRule>, AppError> {
+    tracing::info!("Invocation to `delete_fee_rule`");
+    let res = services::fee_service::delete_fee_rule(&db, id).await;
+    Ok(Json(res?))
+}
@@ -0,0 +1,53 @@
+use crate::error::AppError;
+use crate::extractors::AuthUser;
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn authorize(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    hold: Json<models::hold::HoldCreation>,
+) -> Result<Json<models::hold::Hold>, AppError> {
+    tracing::info!("Invocation to `authorize`");
+    let res = services::hold_service::authorize(&db, auth.user_id, hold.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_hold(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::hold::Hold>, AppError> {
+    tracing::info!("Invocation to `get_hold`");
+    let res = services::hold_service::get_hold(&db, auth.user_id, id).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn capture(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+    capture: Option<Json<models::hold::HoldCapture>>,
+) -> Result<Json<models::hold::CapturedHold>, AppError> {
+    tracing::info!("Invocation to `capture`");
+    let capture = capture.map(|c| c.0).unwrap_or_default();
+    let res = services::hold_service::capture(&db, auth.user_id, id, capture).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn void(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::hold::Hold>, AppError> {
+    tracing::info!("Invocation to `void`");
+    let res = services::hold_service::void(&db, auth.user_id, id).await;
+    Ok(Json(res?))
+}
@@ -0,0 +1,42 @@
+use crate::error::AppError;
+use crate::extractors::{AdminUser, AuthUser};
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_products(
+    State(db): State<SqlitePool>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<models::interest::Product>>, AppError> {
+    tracing::info!("Invocation to `get_products`");
+    let res = services::interest_service::get_products(&db).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn create_product(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    product: Json<models::interest::ProductCreation>,
+) -> Result<Json<models::interest::Product>, AppError> {
+    tracing::info!("Invocation to `create_product`");
+    let res = services::interest_service::create_product(&db, product.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_accruals(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(account_number): Path<String>,
+    Query(query): Query<models::interest::InterestQuery>,
+) -> Result<Json<Vec<models::interest::InterestAccrual>>, AppError> {
+    tracing::info!("Invocation to `get_accruals`");
+    let res =
+        services::interest_service::get_accruals(&db, auth.user_id, &account_number, &query).await;
+    Ok(Json(res?))
+}
@@ -0,0 +1,83 @@
+use crate::error::AppError;
+use crate::extractors::AdminUser;
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{Json, extract::State};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_trial_balance(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+) -> Result<Json<models::ledger::TrialBalance>, AppError> {
+    tracing::info!("Invocation to `get_trial_balance`");
+    let res = services::ledger_service::trial_balance(&db).await;
+    Ok(Json(res?))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode, header::AUTHORIZATION},
+        routing::get,
+    };
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::migrations;
+    use crate::services::generation_service::AccountNumberScheme;
+    use crate::services::stream_service::EventHub;
+    use crate::services::token_service::{TokenKeys, TokenKind};
+
+    async fn setup_app() -> (Router, TokenKeys) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        for username in ["admin", "customer"] {
+            services::user_service::create_user(
+                &pool,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        services::user_service::sync_admins(&pool, &[1])
+            .await
+            .unwrap();
+        let tokens = TokenKeys::new(b"secret", 60, 600);
+        let app = Router::new()
+            .route("/admin/ledger/trial-balance", get(get_trial_balance))
+            .with_state(AppState {
+                pool,
+                tokens: tokens.clone(),
+                account_numbers: AccountNumberScheme::default(),
+                events: EventHub::default(),
+            });
+        (app, tokens)
+    }
+
+    async fn status_for(app: Router, tokens: &TokenKeys, user_id: i64) -> StatusCode {
+        let token = tokens.issue(user_id, TokenKind::Access).unwrap();
+        let request = Request::builder()
+            .uri("/admin/ledger/trial-balance")
+            .header(AUTHORIZATION, format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    #[tokio::test]
+    async fn test_trial_balance_is_admin_only() {
+        let (app, tokens) = setup_app().await;
+        assert_eq!(
+            status_for(app.clone(), &tokens, 2).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(status_for(app, &tokens, 1).await, StatusCode::OK);
+    }
+}
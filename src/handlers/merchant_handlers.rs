@@ -0,0 +1,73 @@
+use crate::error::AppError;
+use crate::extractors::{AdminUser, AuthUser};
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_merchants(
+    State(db): State<SqlitePool>,
+    _auth: AuthUser,
+) -> Result<Json<Vec<models::merchant::Merchant>>, AppError> {
+    tracing::info!("Invocation to `get_merchants`");
+    let res = services::merchant_service::get_merchants(&db).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_merchant(
+    State(db): State<SqlitePool>,
+    _auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::merchant::Merchant>, AppError> {
+    tracing::info!("Invocation to `get_merchant`");
+    let res = services::merchant_service::get_merchant(&db, id).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_merchant_transactions(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+    Query(query): Query<models::transaction::TransactionQuery>,
+) -> Result<Json<models::transaction::TransactionPage>, AppError> {
+    tracing::info!("Invocation to `get_merchant_transactions`");
+    let res =
+        services::merchant_service::get_merchant_transactions(&db, auth.user_id, id, query).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn create_merchant(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    merchant: Json<models::merchant::MerchantCreation>,
+) -> Result<Json<models::merchant::Merchant>, AppError> {
+    tracing::info!("Invocation to `create_merchant`");
+    let res = services::merchant_service::create_merchant(&db, merchant.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn update_merchant(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    Path(id): Path<i64>,
+    merchant: Json<models::merchant::MerchantUpdate>,
+) -> Result<Json<models::merchant::Merchant>, AppError> {
+    tracing::info!("Invocation to `update_merchant`");
+    let res = services::merchant_service::update_merchant(&db, id, merchant.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn delete_merchant(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::merchant::Merchant>, AppError> {
+    tracing::info!("Invocation to `delete_merchant`");
+    let res = services::merchant_service::delete_merchant(&db, id).await;
+    Ok(Json(res?))
+}
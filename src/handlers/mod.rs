@@ -1,3 +1,16 @@
 pub mod account_handlers;
+pub mod analytics_handlers;
+pub mod audit_handlers;
+pub mod auth_handlers;
+pub mod budget_handlers;
+pub mod fee_handlers;
+pub mod hold_handlers;
+pub mod interest_handlers;
+pub mod ledger_handlers;
+pub mod merchant_handlers;
+pub mod schedule_handlers;
+pub mod stream_handlers;
 pub mod transaction_handlers;
+pub mod transfer_handlers;
 pub mod user_handlers;
+pub mod webhook_handlers;
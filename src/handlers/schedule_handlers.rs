@@ -0,0 +1,60 @@
+use crate::error::AppError;
+use crate::extractors::AuthUser;
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_schedules(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+) -> Result<Json<Vec<models::schedule::Schedule>>, AppError> {
+    tracing::info!("Invocation to `get_schedules`");
+    let res = services::schedule_service::get_schedules(&db, auth.user_id).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn create_schedule(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    schedule: Json<models::schedule::ScheduleCreation>,
+) -> Result<Json<models::schedule::Schedule>, AppError> {
+    tracing::info!("Invocation to `create_schedule`");
+    let res = services::schedule_service::create_schedule(&db, auth.user_id, schedule.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_schedule(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::schedule::Schedule>, AppError> {
+    tracing::info!("Invocation to `get_schedule`");
+    let res = services::schedule_service::get_schedule(&db, auth.user_id, id).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn cancel_schedule(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::schedule::Schedule>, AppError> {
+    tracing::info!("Invocation to `cancel_schedule`");
+    let res = services::schedule_service::cancel_schedule(&db, auth.user_id, id).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_schedule_runs(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+) -> Result<Json<Vec<models::schedule::ScheduleRun>>, AppError> {
+    tracing::info!("Invocation to `get_schedule_runs`");
+    let res = services::schedule_service::get_schedule_runs(&db, auth.user_id, id).await;
+    Ok(Json(res?))
+}
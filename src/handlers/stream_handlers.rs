@@ -0,0 +1,100 @@
+use std::convert::Infallible;
+
+use crate::error::AppError;
+use crate::extractors::StreamUser;
+use crate::models::webhook::Event;
+use crate::services;
+use crate::services::stream_service::{self, EventHub};
+use crate::state::AppState;
+use axum::{
+    extract::{
+        Path, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
+    },
+    response::{
+        Response,
+        sse::{self, KeepAlive, Sse},
+    },
+};
+use sqlx::SqlitePool;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_account_events(
+    State(db): State<SqlitePool>,
+    State(hub): State<EventHub>,
+    auth: StreamUser,
+    Path(account_number): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, AppError> {
+    tracing::info!("Invocation to `get_account_events`");
+    let (account_number, receiver) =
+        services::stream_service::subscribe(&db, &hub, auth.user_id, &account_number).await?;
+    let stream = BroadcastStream::new(receiver)
+        // a client that fell behind is dropped; it reconnects and reloads balances
+        .take_while(|received| received.is_ok())
+        .filter_map(move |received| {
+            received
+                .ok()
+                .filter(|event| stream_service::concerns(event, &account_number))
+                .map(|event| Ok(to_sse(&event)))
+        });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+fn to_sse(event: &Event) -> sse::Event {
+    sse::Event::default()
+        .id(event.id.to_string())
+        .event(event.event_type.as_str())
+        .data(serde_json::to_string(event).unwrap_or_default())
+}
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_account_events_ws(
+    State(db): State<SqlitePool>,
+    State(hub): State<EventHub>,
+    auth: StreamUser,
+    Path(account_number): Path<String>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, AppError> {
+    tracing::info!("Invocation to `get_account_events_ws`");
+    let (account_number, receiver) =
+        services::stream_service::subscribe(&db, &hub, auth.user_id, &account_number).await?;
+    Ok(upgrade.on_upgrade(move |socket| forward(socket, receiver, account_number)))
+}
+
+/// Sends the account's events as JSON text messages until either side goes away.
+async fn forward(
+    mut socket: WebSocket,
+    mut receiver: broadcast::Receiver<Event>,
+    account_number: String,
+) {
+    loop {
+        tokio::select! {
+            received = receiver.recv() => match received {
+                Ok(event) if stream_service::concerns(&event, &account_number) => {
+                    let text = serde_json::to_string(&event).unwrap_or_default();
+                    if socket.send(Message::Text(text.into())).await.is_err() {
+                        return;
+                    }
+                }
+                Ok(_) => {}
+                Err(RecvError::Lagged(_)) => {
+                    let _ = socket
+                        .send(Message::Close(Some(CloseFrame {
+                            code: close_code::AGAIN,
+                            reason: "Fell behind, reconnect and reload balances".into(),
+                        })))
+                        .await;
+                    return;
+                }
+                Err(RecvError::Closed) => return,
+            },
+            incoming = socket.recv() => match incoming {
+                // whatever the client sends is ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
@@ -1,22 +1,89 @@
+use crate::error::AppError;
+use crate::extractors::AuthUser;
 use crate::models;
 use crate::services;
-use axum::{Json, extract::State};
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Response},
+};
 use sqlx::SqlitePool;
 
-#[axum::debug_handler]
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "Idempotent-Replayed";
+
+#[axum::debug_handler(state = AppState)]
 pub async fn get_transactions(
     State(db): State<SqlitePool>,
-) -> Json<Vec<models::transaction::TransactionGeneral>> {
+    auth: AuthUser,
+    Query(query): Query<models::transaction::TransactionQuery>,
+) -> Result<Json<models::transaction::TransactionPage>, AppError> {
     tracing::info!("Invocation to `get_transactions`");
-    let res = services::transaction_service::get_transactions(&db).await;
-    Json(res.unwrap())
+    let res = services::transaction_service::get_transactions(&db, auth.user_id, &query).await;
+    Ok(Json(res?))
 }
-#[axum::debug_handler]
+#[axum::debug_handler(state = AppState)]
 pub async fn create_transaction(
     State(db): State<SqlitePool>,
+    auth: AuthUser,
+    headers: HeaderMap,
+    Query(query): Query<models::transaction::TransactionCreationQuery>,
     transaction: Json<models::transaction::TransactionCreation>,
-) -> Json<models::transaction::TransactionCreation> {
+) -> Result<Response, AppError> {
     tracing::info!("Invocation to `create_transactions`");
-    let res = services::transaction_service::create_transaction(&db, transaction.0).await;
-    Json(res.unwrap())
+    if query.preview {
+        let res =
+            services::transaction_service::preview_transaction(&db, auth.user_id, transaction.0)
+                .await;
+        return Ok(Json(res?).into_response());
+    }
+    let Some(key) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        let res =
+            services::transaction_service::create_transaction(&db, auth.user_id, transaction.0)
+                .await;
+        return Ok(Json(res?).into_response());
+    };
+    let key = key
+        .to_str()
+        .map_err(|_| AppError::Validation("Idempotency-Key must be printable ASCII".to_string()))?;
+    services::idempotency_service::validate_client_key(key)?;
+    let stored =
+        services::idempotency_service::run(&db, auth.user_id, key, &transaction.0, async |conn| {
+            services::transaction_service::create_transaction_in(
+                conn,
+                auth.user_id,
+                transaction.0.clone(),
+            )
+            .await
+        })
+        .await?;
+    let status = StatusCode::from_u16(stored.status)
+        .map_err(|err| AppError::Internal(format!("Stored status is invalid: {}", err)))?;
+    Ok((
+        status,
+        [
+            (header::CONTENT_TYPE.as_str(), "application/json"),
+            (
+                IDEMPOTENT_REPLAYED_HEADER,
+                if stored.replayed { "true" } else { "false" },
+            ),
+        ],
+        stored.body,
+    )
+        .into_response())
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn refund_transaction(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    Path(id): Path<i64>,
+    refund: Option<Json<models::transaction::RefundCreation>>,
+) -> Result<Json<models::transaction::TransactionGeneral>, AppError> {
+    tracing::info!("Invocation to `refund_transaction`");
+    let refund = refund.map(|r| r.0).unwrap_or_default();
+    let res =
+        services::transaction_service::refund_transaction(&db, auth.user_id, id, refund).await;
+    Ok(Json(res?))
 }
@@ -0,0 +1,18 @@
+use crate::error::AppError;
+use crate::extractors::AuthUser;
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{Json, extract::State};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn create_transfer(
+    State(db): State<SqlitePool>,
+    auth: AuthUser,
+    transfer: Json<models::transfer::TransferCreation>,
+) -> Result<Json<models::transfer::Transfer>, AppError> {
+    tracing::info!("Invocation to `create_transfer`");
+    let res = services::transfer_service::create_transfer(&db, auth.user_id, transfer.0).await;
+    Ok(Json(res?))
+}
@@ -1,20 +1,27 @@
+use crate::error::AppError;
+use crate::extractors::AuthUser;
 use crate::models;
 use crate::services;
+use crate::state::AppState;
 use axum::{Json, extract::State};
 use sqlx::SqlitePool;
 
-#[axum::debug_handler]
-pub async fn get_users(State(pool): State<SqlitePool>) -> Json<Vec<models::user::User>> {
+#[axum::debug_handler(state = AppState)]
+pub async fn get_users(
+    State(pool): State<SqlitePool>,
+    auth: AuthUser,
+) -> Result<Json<Vec<models::user::User>>, AppError> {
     tracing::info!("Invocation to `get_users`");
-    let res = services::user_service::get_users(&pool).await;
-    Json(res.unwrap())
+    // callers only ever see themselves
+    let res = services::user_service::get_user(&pool, auth.user_id).await;
+    Ok(Json(vec![res?]))
 }
-#[axum::debug_handler]
+#[axum::debug_handler(state = AppState)]
 pub async fn create_user(
     State(pool): State<SqlitePool>,
     user: Json<models::user::UserCreation>,
-) -> Json<models::user::User> {
+) -> Result<Json<models::user::User>, AppError> {
     tracing::info!("Invocation to `create_user`");
     let res = services::user_service::create_user(&pool, user.0).await;
-    Json(res.unwrap())
+    Ok(Json(res?))
 }
@@ -0,0 +1,59 @@
+use crate::error::AppError;
+use crate::extractors::AdminUser;
+use crate::models;
+use crate::services;
+use crate::state::AppState;
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use sqlx::SqlitePool;
+
+#[axum::debug_handler(state = AppState)]
+pub async fn get_webhooks(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<models::webhook::Webhook>>, AppError> {
+    tracing::info!("Invocation to `get_webhooks`");
+    let res = services::webhook_service::get_webhooks(&db).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn create_webhook(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    webhook: Json<models::webhook::WebhookCreation>,
+) -> Result<Json<models::webhook::WebhookRegistration>, AppError> {
+    tracing::info!("Invocation to `create_webhook`");
+    let res = services::webhook_service::create_webhook(&db, webhook.0).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn delete_webhook(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::webhook::Webhook>, AppError> {
+    tracing::info!("Invocation to `delete_webhook`");
+    let res = services::webhook_service::delete_webhook(&db, id).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn get_dead_letters(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+) -> Result<Json<Vec<models::webhook::WebhookDelivery>>, AppError> {
+    tracing::info!("Invocation to `get_dead_letters`");
+    let res = services::webhook_service::get_dead_letters(&db).await;
+    Ok(Json(res?))
+}
+#[axum::debug_handler(state = AppState)]
+pub async fn retry_delivery(
+    State(db): State<SqlitePool>,
+    _admin: AdminUser,
+    Path(id): Path<i64>,
+) -> Result<Json<models::webhook::WebhookDelivery>, AppError> {
+    tracing::info!("Invocation to `retry_delivery`");
+    let res = services::webhook_service::retry_delivery(&db, id).await;
+    Ok(Json(res?))
+}
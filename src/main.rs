@@ -1,45 +1,244 @@
+pub mod config;
+pub mod error;
+pub mod extractors;
 pub mod handlers;
+pub mod middleware;
+pub mod migrations;
 pub mod models;
-pub mod queries;
 pub mod services;
+pub mod state;
 
 use axum::{
     Router,
-    routing::{get, post},
+    routing::{delete, get, post, put},
 };
-use queries::{CREATE_TABLE_ACCOUNT, CREATE_TABLE_TRANSACTION, CREATE_TABLE_USER};
+use services::generation_service::AccountNumberScheme;
+use services::schedule_service::SchedulePolicy;
+use services::stream_service::EventHub;
+use services::token_service::TokenKeys;
+use services::webhook_service::WebhookPolicy;
 use sqlx::SqlitePool;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use state::AppState;
+use std::str::FromStr;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // initialize tracing
     tracing_subscriber::fmt::init();
-    let pool = SqlitePool::connect("sqlite::inmemory:").await?;
-    let conn = pool.acquire().await?;
-    tracing::info!("Established in-memory database connection");
-    sqlx::query(CREATE_TABLE_USER).execute(&pool).await;
-    sqlx::query(CREATE_TABLE_ACCOUNT).execute(&pool).await;
-    sqlx::query(CREATE_TABLE_TRANSACTION).execute(&pool).await;
-    tracing::info!("Initializing tables");
+    let config = config::Config::from_env();
+    let account_numbers = AccountNumberScheme::from_config(&config)?;
+    let options = SqliteConnectOptions::from_str(&config.database_url)?
+        .create_if_missing(true)
+        .journal_mode(SqliteJournalMode::Wal);
+    let pool = SqlitePool::connect_with(options).await?;
+    tracing::info!("Established database connection to {}", config.database_url);
+    // refuses to start if the schema has drifted from the compiled migrations
+    migrations::run(&pool).await?;
+    tracing::info!("Database schema is up to date");
+    let assigned = services::account_service::assign_missing_ibans(&pool, &account_numbers).await?;
+    if assigned > 0 {
+        tracing::info!("Assigned IBANs to {} existing accounts", assigned);
+    }
+    let admins = services::user_service::sync_admins(&pool, &config.admin_user_ids).await?;
+    tracing::info!("{} users have the admin role", admins);
+    for discrepancy in services::ledger_service::reconcile(&pool).await? {
+        tracing::warn!(
+            "Account {} has balance {} but its ledger says {}",
+            discrepancy.account_number,
+            discrepancy.balance,
+            discrepancy.ledger_balance
+        );
+    }
+
+    tokio::spawn(services::hold_service::run_expiry(pool.clone()));
+    tokio::spawn(services::interest_service::run_interest(pool.clone()));
+    tokio::spawn(services::fee_service::run_maintenance_fees(pool.clone()));
+    tokio::spawn(services::schedule_service::run_worker(
+        pool.clone(),
+        SchedulePolicy::from_config(&config),
+        config.schedule_poll_interval_secs,
+    ));
+    let events = EventHub::default();
+    tokio::spawn(services::stream_service::run_publisher(
+        pool.clone(),
+        events.clone(),
+    ));
+    tokio::spawn(services::webhook_service::run_dispatcher(
+        pool.clone(),
+        WebhookPolicy::from_config(&config),
+        config.webhook_poll_interval_secs,
+    ));
 
+    let tokens = TokenKeys::from_config(&config);
     let user_router = Router::new()
         .route("/", get(handlers::user_handlers::get_users))
-        .route("/", post(handlers::user_handlers::create_user));
+        .route("/", post(handlers::user_handlers::create_user))
+        .route(
+            "/{id}/budgets",
+            get(handlers::budget_handlers::get_budget_summary),
+        );
     let account_router = Router::new()
         .route("/", get(handlers::account_handlers::get_accounts))
-        .route("/", post(handlers::account_handlers::create_account));
+        .route("/", post(handlers::account_handlers::create_account))
+        .route("/iban/{iban}", get(handlers::account_handlers::get_iban))
+        .route(
+            "/{account_number}/close",
+            post(handlers::account_handlers::close_account),
+        )
+        .route(
+            "/{account_number}/interest",
+            get(handlers::interest_handlers::get_accruals),
+        )
+        .route(
+            "/{account_number}/events",
+            get(handlers::stream_handlers::get_account_events),
+        )
+        .route(
+            "/{account_number}/events/ws",
+            get(handlers::stream_handlers::get_account_events_ws),
+        );
+    let product_router = Router::new().route("/", get(handlers::interest_handlers::get_products));
     let transaction_router = Router::new()
         .route("/", get(handlers::transaction_handlers::get_transactions))
         .route(
             "/",
             post(handlers::transaction_handlers::create_transaction),
+        )
+        .route(
+            "/{id}/refunds",
+            post(handlers::transaction_handlers::refund_transaction),
+        );
+    let transfer_router =
+        Router::new().route("/", post(handlers::transfer_handlers::create_transfer));
+    let hold_router = Router::new()
+        .route("/", post(handlers::hold_handlers::authorize))
+        .route("/{id}", get(handlers::hold_handlers::get_hold))
+        .route("/{id}/capture", post(handlers::hold_handlers::capture))
+        .route("/{id}/void", post(handlers::hold_handlers::void));
+    let schedule_router = Router::new()
+        .route("/", get(handlers::schedule_handlers::get_schedules))
+        .route("/", post(handlers::schedule_handlers::create_schedule))
+        .route("/{id}", get(handlers::schedule_handlers::get_schedule))
+        .route(
+            "/{id}",
+            delete(handlers::schedule_handlers::cancel_schedule),
+        )
+        .route(
+            "/{id}/runs",
+            get(handlers::schedule_handlers::get_schedule_runs),
+        );
+    let merchant_router = Router::new()
+        .route("/", get(handlers::merchant_handlers::get_merchants))
+        .route("/{id}", get(handlers::merchant_handlers::get_merchant))
+        .route(
+            "/{id}/transactions",
+            get(handlers::merchant_handlers::get_merchant_transactions),
+        );
+    let analytics_router = Router::new()
+        .route("/spending", get(handlers::analytics_handlers::get_spending))
+        .route(
+            "/top-merchants",
+            get(handlers::analytics_handlers::get_top_merchants),
+        )
+        .route("/timeline", get(handlers::analytics_handlers::get_timeline))
+        .route(
+            "/cash-flow",
+            get(handlers::analytics_handlers::get_cash_flow),
+        )
+        .route(
+            "/comparison",
+            get(handlers::analytics_handlers::get_comparison),
+        );
+    let budget_router = Router::new()
+        .route("/", post(handlers::budget_handlers::create_budget))
+        .route("/alerts", get(handlers::budget_handlers::get_alerts))
+        .route("/{id}", delete(handlers::budget_handlers::delete_budget));
+    let fee_rule_router = Router::new().route("/", get(handlers::fee_handlers::get_fee_rules));
+    let admin_router = Router::new()
+        .route("/audit", get(handlers::audit_handlers::get_audit_log))
+        .route(
+            "/ledger/trial-balance",
+            get(handlers::ledger_handlers::get_trial_balance),
+        )
+        .route("/webhooks", get(handlers::webhook_handlers::get_webhooks))
+        .route(
+            "/webhooks",
+            post(handlers::webhook_handlers::create_webhook),
+        )
+        .route(
+            "/webhooks/{id}",
+            delete(handlers::webhook_handlers::delete_webhook),
+        )
+        .route(
+            "/webhooks/dead-letters",
+            get(handlers::webhook_handlers::get_dead_letters),
+        )
+        .route(
+            "/webhooks/dead-letters/{id}/retry",
+            post(handlers::webhook_handlers::retry_delivery),
+        )
+        .route(
+            "/merchants",
+            post(handlers::merchant_handlers::create_merchant),
+        )
+        .route(
+            "/merchants/{id}",
+            put(handlers::merchant_handlers::update_merchant),
+        )
+        .route(
+            "/merchants/{id}",
+            delete(handlers::merchant_handlers::delete_merchant),
+        )
+        .route("/fee-rules", post(handlers::fee_handlers::create_fee_rule))
+        .route(
+            "/fee-rules/{id}",
+            delete(handlers::fee_handlers::delete_fee_rule),
+        )
+        .route(
+            "/products",
+            post(handlers::interest_handlers::create_product),
+        )
+        .route(
+            "/accounts/overdrawn",
+            get(handlers::account_handlers::get_overdrawn_accounts),
+        )
+        .route(
+            "/accounts/{account_number}/overdraft",
+            put(handlers::account_handlers::set_overdraft),
+        )
+        .route(
+            "/accounts/{account_number}/status",
+            put(handlers::account_handlers::change_status),
         );
+    let auth_router = Router::new()
+        .route("/login", post(handlers::auth_handlers::login))
+        .route("/refresh", post(handlers::auth_handlers::refresh));
     // build our application with a route
     let app = Router::new()
+        .nest("/auth", auth_router)
         .nest("/users", user_router)
         .nest("/accounts", account_router)
+        .nest("/products", product_router)
+        .nest("/fee-rules", fee_rule_router)
         .nest("/transactions", transaction_router)
-        .with_state(pool);
+        .nest("/merchants", merchant_router)
+        .nest("/transfers", transfer_router)
+        .nest("/holds", hold_router)
+        .nest("/schedules", schedule_router)
+        .nest("/analytics", analytics_router)
+        .nest("/budgets", budget_router)
+        .nest("/admin", admin_router)
+        .layer(axum::middleware::from_fn_with_state(
+            tokens.clone(),
+            middleware::request_context,
+        ))
+        .with_state(AppState {
+            pool,
+            tokens,
+            account_numbers,
+            events,
+        });
 
     // run our app with hyper, listening globally on port 3000
     let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
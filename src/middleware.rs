@@ -0,0 +1,60 @@
+// src/middleware.rs
+// Middleware wrapped around every route
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue, header::AUTHORIZATION},
+    middleware::Next,
+    response::Response,
+};
+use rand::Rng;
+
+use crate::services::audit_service::{self, AuditContext};
+use crate::services::token_service::{TokenKeys, TokenKind};
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+/// Longest client-supplied request id that is kept; longer ones are replaced.
+pub const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// The client's `X-Request-Id` if it is short printable ASCII, so it can be
+/// logged and stored safely.
+fn client_request_id(request: &Request) -> Option<String> {
+    let id = request.headers().get(REQUEST_ID_HEADER)?.to_str().ok()?;
+    let valid = !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.bytes().all(|b| b.is_ascii_graphic());
+    valid.then(|| id.to_string())
+}
+
+/// Gives every request an id, the client's `X-Request-Id` or a random one, and
+/// runs it in an audit context with that id and the caller behind its bearer
+/// token, if valid. Rejecting bad tokens is left to the extractors. The id is
+/// echoed in the response's `X-Request-Id`.
+pub async fn request_context(
+    State(tokens): State<TokenKeys>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let request_id = client_request_id(&request).unwrap_or_else(|| {
+        rand::thread_rng()
+            .r#gen::<[u8; 16]>()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    });
+    let actor_user_id = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| tokens.verify(token.trim(), TokenKind::Access).ok())
+        .map(|claims| claims.sub);
+    let context = AuditContext {
+        actor_user_id,
+        request_id: Some(request_id.clone()),
+    };
+    let mut response = audit_service::scope(context, next.run(request)).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,325 @@
+// src/migrations.rs
+// Ordered, checksummed schema migrations applied at boot
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+use sqlx::{Row, SqlitePool};
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    pub sql: &'static str,
+}
+
+/// Every migration in application order. Never edit or reorder an entry
+/// once it has shipped; add a new one instead.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial_schema",
+        sql: include_str!("../migrations/0001_initial_schema.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "idempotency_keys",
+        sql: include_str!("../migrations/0002_idempotency_keys.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "transaction_listing_indexes",
+        sql: include_str!("../migrations/0003_transaction_listing_indexes.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "transfers",
+        sql: include_str!("../migrations/0004_transfers.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "ledger",
+        sql: include_str!("../migrations/0005_ledger.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "account_versions",
+        sql: include_str!("../migrations/0006_account_versions.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "account_ibans",
+        sql: include_str!("../migrations/0007_account_ibans.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "holds",
+        sql: include_str!("../migrations/0008_holds.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "refunds",
+        sql: include_str!("../migrations/0009_refunds.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "schedules",
+        sql: include_str!("../migrations/0010_schedules.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "overdrafts",
+        sql: include_str!("../migrations/0011_overdrafts.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "interest",
+        sql: include_str!("../migrations/0012_interest.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "fees",
+        sql: include_str!("../migrations/0013_fees.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "webhooks",
+        sql: include_str!("../migrations/0014_webhooks.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "audit_log",
+        sql: include_str!("../migrations/0015_audit_log.sql"),
+    },
+    Migration {
+        version: 16,
+        name: "account_status",
+        sql: include_str!("../migrations/0016_account_status.sql"),
+    },
+    Migration {
+        version: 17,
+        name: "merchants",
+        sql: include_str!("../migrations/0017_merchants.sql"),
+    },
+    Migration {
+        version: 18,
+        name: "budgets",
+        sql: include_str!("../migrations/0018_budgets.sql"),
+    },
+];
+
+const CREATE_TABLE_SCHEMA_MIGRATIONS: &str = r#"
+CREATE TABLE IF NOT EXISTS SCHEMA_MIGRATIONS (
+    version INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    checksum TEXT NOT NULL, -- hex SHA-256 of the migration SQL
+    applied_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
+"#;
+
+#[derive(Debug)]
+pub enum MigrationError {
+    /// An applied migration no longer matches the one compiled into this binary.
+    ChecksumMismatch {
+        version: i64,
+        name: String,
+    },
+    /// The database has a migration this binary does not know about.
+    UnknownVersion {
+        version: i64,
+        name: String,
+    },
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::ChecksumMismatch { version, name } => write!(
+                f,
+                "Migration {} ({}) was modified after being applied",
+                version, name
+            ),
+            MigrationError::UnknownVersion { version, name } => write!(
+                f,
+                "Database has migration {} ({}) which this build does not know",
+                version, name
+            ),
+            MigrationError::Database(err) => write!(f, "Migration failed: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(err: sqlx::Error) -> Self {
+        MigrationError::Database(err)
+    }
+}
+
+pub fn checksum(sql: &str) -> String {
+    Sha256::digest(sql.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Applies pending migrations in order, each in its own transaction. Refuses
+/// to touch the database if any applied migration has drifted.
+pub async fn run(pool: &SqlitePool) -> Result<(), MigrationError> {
+    run_migrations(pool, MIGRATIONS).await
+}
+
+async fn run_migrations(pool: &SqlitePool, migrations: &[Migration]) -> Result<(), MigrationError> {
+    sqlx::raw_sql(CREATE_TABLE_SCHEMA_MIGRATIONS)
+        .execute(pool)
+        .await?;
+    let applied =
+        sqlx::query("SELECT version, name, checksum FROM SCHEMA_MIGRATIONS ORDER BY version;")
+            .fetch_all(pool)
+            .await?;
+    for row in &applied {
+        let version: i64 = row.try_get("version")?;
+        let name: String = row.try_get("name")?;
+        let stored: String = row.try_get("checksum")?;
+        match migrations.iter().find(|m| m.version == version) {
+            None => return Err(MigrationError::UnknownVersion { version, name }),
+            Some(m) if checksum(m.sql) != stored => {
+                return Err(MigrationError::ChecksumMismatch { version, name });
+            }
+            Some(_) => {}
+        }
+    }
+    let applied: Vec<i64> = applied
+        .iter()
+        .map(|row| row.try_get("version"))
+        .collect::<Result<_, _>>()?;
+    for migration in migrations.iter().filter(|m| !applied.contains(&m.version)) {
+        tracing::info!(
+            "Applying migration {} ({})",
+            migration.version,
+            migration.name
+        );
+        let mut tx = pool.begin().await?;
+        // roll back eagerly rather than on drop, so the schema lock is released before we return
+        if let Err(err) = sqlx::raw_sql(migration.sql).execute(&mut *tx).await {
+            tx.rollback().await?;
+            return Err(err.into());
+        }
+        sqlx::query("INSERT INTO SCHEMA_MIGRATIONS (version, name, checksum) VALUES (?, ?, ?);")
+            .bind(migration.version)
+            .bind(migration.name)
+            .bind(checksum(migration.sql))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        for pair in MIGRATIONS.windows(2) {
+            assert_eq!(pair[1].version, pair[0].version + 1);
+        }
+        assert_eq!(MIGRATIONS[0].version, 1);
+    }
+
+    #[tokio::test]
+    async fn test_run_is_idempotent() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        run(&pool).await.unwrap();
+        run(&pool).await.unwrap();
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM SCHEMA_MIGRATIONS;")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, MIGRATIONS.len() as i64);
+    }
+
+    #[tokio::test]
+    async fn test_applies_only_pending() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        let first = [Migration {
+            version: 1,
+            name: "a",
+            sql: "CREATE TABLE A (id INTEGER);",
+        }];
+        run_migrations(&pool, &first).await.unwrap();
+        let both = [
+            Migration {
+                version: 1,
+                name: "a",
+                sql: "CREATE TABLE A (id INTEGER);",
+            },
+            Migration {
+                version: 2,
+                name: "b",
+                sql: "CREATE TABLE B (id INTEGER); INSERT INTO B VALUES (1);",
+            },
+        ];
+        run_migrations(&pool, &both).await.unwrap();
+        let b: i64 = sqlx::query_scalar("SELECT id FROM B;")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(b, 1);
+    }
+
+    #[tokio::test]
+    async fn test_refuses_modified_migration() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        let original = [Migration {
+            version: 1,
+            name: "a",
+            sql: "CREATE TABLE A (id INTEGER);",
+        }];
+        run_migrations(&pool, &original).await.unwrap();
+        let edited = [Migration {
+            version: 1,
+            name: "a",
+            sql: "CREATE TABLE A (id INTEGER, extra TEXT);",
+        }];
+        let res = run_migrations(&pool, &edited).await;
+        assert!(matches!(
+            res,
+            Err(MigrationError::ChecksumMismatch { version: 1, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refuses_unknown_applied_version() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        run(&pool).await.unwrap();
+        sqlx::query(
+            "INSERT INTO SCHEMA_MIGRATIONS (version, name, checksum) VALUES (999, 'future', 'x');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let res = run(&pool).await;
+        assert!(matches!(
+            res,
+            Err(MigrationError::UnknownVersion { version: 999, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_failed_migration_is_rolled_back() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        let broken = [Migration {
+            version: 1,
+            name: "broken",
+            sql: "CREATE TABLE A (id INTEGER); NOT VALID SQL;",
+        }];
+        assert!(run_migrations(&pool, &broken).await.is_err());
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM SCHEMA_MIGRATIONS;")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+}
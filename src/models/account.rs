@@ -2,24 +2,125 @@
 // Defines the Account struct
 use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+use crate::models::money::{Currency, Money};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Account {
     pub id: Option<i32>, // AUTO_INCREMENT
     pub account_number: String,
-    pub user_id: i32, // Foreign key, assuming it's always present
-    pub balance: f32, // INT, so i32
+    pub user_id: i32,   // Foreign key, assuming it's always present
+    pub balance: Money, // INTEGER minor units + currency
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Account {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Account {
+            id: row.try_get("id")?,
+            account_number: row.try_get("account_number")?,
+            user_id: row.try_get("user_id")?,
+            balance: Money::from_row(row, "balance", "currency")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct AccountGeneral {
     pub account_number: String,
-    pub user_id: i32, // Foreign key, assuming it's always present
-    pub balance: f32, // INT, so i32
+    pub iban: Option<String>, // assigned at startup for accounts that predate IBANs
+    pub user_id: i32,         // Foreign key, assuming it's always present
+    pub balance: Money,       // INTEGER minor units + currency
+    pub available_balance: Money, // balance less pending holds, plus the overdraft limit
+    pub overdraft_limit: Money, // how far below zero the balance may go
+    pub overdraft_fee: Money, // charged for each debit that leaves the balance below zero
+    pub product: String,      // decides the interest the account earns
+    pub status: AccountStatus,
+    pub status_reason: Option<String>, // why it was last moved to its status
     pub created_at: NaiveDateTime,
 }
+impl<'r> sqlx::FromRow<'r, SqliteRow> for AccountGeneral {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(AccountGeneral {
+            account_number: row.try_get("account_number")?,
+            iban: row.try_get("iban")?,
+            user_id: row.try_get("user_id")?,
+            balance: Money::from_row(row, "balance", "currency")?,
+            available_balance: Money::from_row(row, "available_balance", "currency")?,
+            overdraft_limit: Money::from_row(row, "overdraft_limit", "currency")?,
+            overdraft_fee: Money::from_row(row, "overdraft_fee", "currency")?,
+            product: row.try_get("product")?,
+            status: row.try_get("status")?,
+            status_reason: row.try_get("status_reason")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum AccountStatus {
+    Active,  // the only status that takes postings
+    Frozen,  // blocked, e.g. while suspected of fraud
+    Dormant, // unused for a long time; reactivated on request
+    Closed,  // final, with a zero balance
+}
+
+impl AccountStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AccountStatus::Active => "active",
+            AccountStatus::Frozen => "frozen",
+            AccountStatus::Dormant => "dormant",
+            AccountStatus::Closed => "closed",
+        }
+    }
+
+    /// Whether an account may go from this status to `next`. Closing is
+    /// possible from anywhere, reopening from nowhere.
+    pub fn can_become(&self, next: AccountStatus) -> bool {
+        match self {
+            AccountStatus::Active => next != AccountStatus::Active,
+            AccountStatus::Frozen => matches!(next, AccountStatus::Active | AccountStatus::Closed),
+            AccountStatus::Dormant => next != AccountStatus::Dormant,
+            AccountStatus::Closed => false,
+        }
+    }
+}
 #[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, sqlx::FromRow)]
 pub struct AccountCreation {
-    pub user_id: i32, // Foreign key, assuming it's always present
+    // the owner is always the authenticated caller
+    #[serde(default)]
+    pub currency: Currency, // defaults to USD
+    #[serde(default)]
+    pub product: Option<String>, // defaults to checking
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct IbanLookup {
+    // what a payer needs to address an account, and nothing about its owner or balance
+    pub iban: String,
+    pub account_number: String,
+    pub currency: Currency,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct OverdraftSetting {
+    pub limit: Money,       // zero turns the overdraft off
+    pub fee: Option<Money>, // no fee if absent
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct StatusChange {
+    pub status: AccountStatus,
+    pub reason: String,
+    // when closing, where a positive balance goes; ignored otherwise
+    #[serde(default)]
+    pub sweep_to: Option<String>,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AccountClosure {
+    pub reason: String,
+    #[serde(default)]
+    pub sweep_to: Option<String>, // needed unless the balance is zero
 }
@@ -0,0 +1,70 @@
+// src/models/analytics.rs
+// Defines the spending and cash flow aggregates of a user's transactions
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use crate::models::money::{Currency, Money};
+
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpendingGroupBy {
+    #[default]
+    Merchant,
+    Category, // the merchant's category code
+}
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interval {
+    Day,
+    Week, // starting on Monday
+    #[default]
+    Month,
+}
+/// Query string of the `/analytics` endpoints. Each reads the parameters that
+/// apply to it.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AnalyticsQuery {
+    pub account_number: Option<String>, // all of the caller's accounts if absent
+    pub from: Option<NaiveDate>,        // inclusive
+    pub to: Option<NaiveDate>,          // inclusive
+    pub currency: Option<Currency>, // amounts only add up within one; defaults to the account's, or USD
+    #[serde(default)]
+    pub group_by: SpendingGroupBy,
+    #[serde(default)]
+    pub interval: Interval,
+    pub limit: Option<u32>,
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SpendingGroup {
+    pub merchant_id: Option<i64>, // when grouped by merchant; absent for unmatched payments
+    pub name: Option<String>,     // the merchant's
+    pub category: Option<String>, // absent for unmatched payments
+    pub spent: Money,             // less refunds
+    pub transactions: i64,
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CashFlow {
+    pub from: Option<NaiveDate>,
+    pub to: Option<NaiveDate>,
+    pub income: Money,
+    pub spent: Money,
+    pub net: Money, // income less spent
+    pub transactions: i64,
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PeriodCashFlow {
+    pub period_start: NaiveDate,
+    pub income: Money,
+    pub spent: Money,
+    pub net: Money,
+    pub transactions: i64,
+}
+/// A period next to the one of the same length just before it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct PeriodComparison {
+    pub current: CashFlow,
+    pub previous: CashFlow,
+    pub income_change: Money,
+    pub spent_change: Money,
+    pub spent_change_percent: Option<String>, // absent when nothing was spent before
+}
@@ -0,0 +1,109 @@
+// src/models/audit.rs
+// Defines audit log entries and how they are queried
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+pub enum AuditAction {
+    #[serde(rename = "user.create")]
+    #[sqlx(rename = "user.create")]
+    UserCreate,
+    #[serde(rename = "user.change_role")]
+    #[sqlx(rename = "user.change_role")]
+    UserChangeRole,
+    #[serde(rename = "account.create")]
+    #[sqlx(rename = "account.create")]
+    AccountCreate,
+    #[serde(rename = "account.assign_iban")]
+    #[sqlx(rename = "account.assign_iban")]
+    AccountAssignIban,
+    #[serde(rename = "account.set_overdraft")]
+    #[sqlx(rename = "account.set_overdraft")]
+    AccountSetOverdraft,
+    #[serde(rename = "account.change_status")]
+    #[sqlx(rename = "account.change_status")]
+    AccountChangeStatus,
+    #[serde(rename = "transaction.create")]
+    #[sqlx(rename = "transaction.create")]
+    TransactionCreate,
+    #[serde(rename = "transaction.refund")]
+    #[sqlx(rename = "transaction.refund")]
+    TransactionRefund, // on the original, whose refunded_amount grows
+    #[serde(rename = "transaction.set_merchant")]
+    #[sqlx(rename = "transaction.set_merchant")]
+    TransactionSetMerchant, // linked or unlinked when merchants are added, changed or removed
+}
+
+impl AuditAction {
+    /// The kind of entity the action changes, stored alongside it for filtering.
+    pub fn entity_type(&self) -> &'static str {
+        match self {
+            AuditAction::UserCreate | AuditAction::UserChangeRole => "user",
+            AuditAction::AccountCreate
+            | AuditAction::AccountAssignIban
+            | AuditAction::AccountSetOverdraft
+            | AuditAction::AccountChangeStatus => "account",
+            AuditAction::TransactionCreate
+            | AuditAction::TransactionRefund
+            | AuditAction::TransactionSetMerchant => "transaction",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub actor_user_id: Option<i64>, // None for anonymous requests and the system itself
+    pub action: AuditAction,
+    pub entity_type: String,
+    pub entity_id: String,
+    pub before: Option<serde_json::Value>, // None when the entity was created
+    pub after: Option<serde_json::Value>,
+    pub request_id: Option<String>,
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for AuditEntry {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let snapshot = |column: &str| -> Result<Option<serde_json::Value>, sqlx::Error> {
+            let json: Option<String> = row.try_get(column)?;
+            json.map(|json| serde_json::from_str(&json))
+                .transpose()
+                .map_err(|err| sqlx::Error::ColumnDecode {
+                    index: column.to_string(),
+                    source: Box::new(err),
+                })
+        };
+        Ok(AuditEntry {
+            id: row.try_get("id")?,
+            actor_user_id: row.try_get("actor_user_id")?,
+            action: row.try_get("action")?,
+            entity_type: row.try_get("entity_type")?,
+            entity_id: row.try_get("entity_id")?,
+            before: snapshot("before")?,
+            after: snapshot("after")?,
+            request_id: row.try_get("request_id")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+/// Query string of `GET /admin/audit`. Every filter is optional and they combine with AND.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct AuditQuery {
+    pub actor_user_id: Option<i64>,
+    pub action: Option<AuditAction>,
+    pub entity_type: Option<String>,
+    pub entity_id: Option<String>,
+    pub request_id: Option<String>,
+    pub from: Option<NaiveDate>, // inclusive
+    pub to: Option<NaiveDate>,   // inclusive
+    pub limit: Option<u32>,
+    pub cursor: Option<i64>, // `next_cursor` of the previous page
+}
+/// A page of the audit log, newest entries first.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct AuditPage {
+    pub items: Vec<AuditEntry>,
+    pub next_cursor: Option<i64>, // absent on the last page
+}
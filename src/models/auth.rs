@@ -0,0 +1,23 @@
+// src/models/auth.rs
+// Defines the credential payloads used by the auth endpoints
+use serde::{Deserialize, Serialize};
+
+use crate::models::user::User;
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub refresh_token: String,
+    pub token_type: String, // always "Bearer"
+    pub expires_in: i64,    // access token lifetime in seconds
+    pub user: User,
+}
@@ -0,0 +1,84 @@
+// src/models/budget.rs
+// Defines budgets, their progress and the alerts raised as spending nears them
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::models::money::Money;
+
+/// Shares of a budget, in percent, that raise an alert the first time a
+/// month's spending reaches them.
+pub const ALERT_THRESHOLDS: [i64; 3] = [50, 80, 100];
+
+/// A monthly spending limit with one merchant or in one merchant category.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Budget {
+    pub id: i64,
+    pub user_id: i64,
+    pub merchant_id: Option<i64>, // set when the budget is for a merchant
+    pub category: Option<String>, // set when the budget is for a category
+    pub amount: Money,            // per calendar month
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Budget {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Budget {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            merchant_id: row.try_get("merchant_id")?,
+            category: row.try_get("category")?,
+            amount: Money::from_row(row, "amount", "currency")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+/// Body of `POST /budgets`; exactly one of `merchant_id` and `category`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BudgetCreation {
+    pub merchant_id: Option<i64>,
+    pub category: Option<String>,
+    pub amount: Money,
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BudgetProgress {
+    pub budget: Budget,
+    pub spent: Money,      // this month, less refunds
+    pub remaining: Money,  // negative once over budget
+    pub percent_used: i64, // rounded down
+    pub thresholds_crossed: Vec<i64>,
+}
+/// The caller's budgets and how far into each this month's spending is.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BudgetSummary {
+    pub user_id: i64,
+    pub period_start: NaiveDate, // first day of the current month, UTC
+    pub budgets: Vec<BudgetProgress>,
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BudgetAlert {
+    pub id: i64,
+    pub budget_id: i64,
+    pub period_start: NaiveDate,
+    pub threshold: i64,      // percent of the budget
+    pub spent: Money,        // the month's spending after the transaction
+    pub transaction_id: i64, // the one that crossed the threshold
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for BudgetAlert {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(BudgetAlert {
+            id: row.try_get("id")?,
+            budget_id: row.try_get("budget_id")?,
+            period_start: row.try_get("period_start")?,
+            threshold: row.try_get("threshold")?,
+            spent: Money::from_row(row, "spent", "currency")?,
+            transaction_id: row.try_get("transaction_id")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct BudgetAlertQuery {
+    pub budget_id: Option<i64>,
+}
@@ -0,0 +1,91 @@
+// src/models/fee.rs
+// Defines fee rules and the fees they charge
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::models::money::Money;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum FeeKind {
+    PerTransaction,     // every payment made with POST /transactions
+    MonthlyMaintenance, // once a month, for the month just ended
+    ForeignCurrency,    // transfers that convert between currencies
+    Overdraft,          // every debit that leaves the balance below zero
+}
+
+impl FeeKind {
+    /// What the fee transaction is called.
+    pub fn description(&self) -> &'static str {
+        match self {
+            FeeKind::PerTransaction => "Transaction fee",
+            FeeKind::MonthlyMaintenance => "Monthly maintenance fee",
+            FeeKind::ForeignCurrency => "Foreign currency fee",
+            FeeKind::Overdraft => "Overdraft fee",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FeeRule {
+    pub id: i64,
+    pub product: String,
+    pub kind: FeeKind,
+    pub amount: Money, // flat part; its currency picks the accounts it applies to
+    pub rate: Option<String>, // proportional part, "0.03" for 3% of the amount
+    pub waive_above: Option<Money>, // no fee while the balance is at least this
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for FeeRule {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let waive_above: Option<i64> = row.try_get("waive_above")?;
+        let amount = Money::from_row(row, "amount", "currency")?;
+        Ok(FeeRule {
+            id: row.try_get("id")?,
+            product: row.try_get("product")?,
+            kind: row.try_get("kind")?,
+            amount,
+            rate: row.try_get("rate")?,
+            waive_above: waive_above.map(|minor| Money::new(minor, amount.currency())),
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FeeRuleCreation {
+    pub product: String,
+    pub kind: FeeKind,
+    pub amount: Money,
+    pub rate: Option<String>,
+    pub waive_above: Option<Money>,
+}
+/// Query string of `GET /fee-rules`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct FeeRuleQuery {
+    pub product: Option<String>,
+}
+/// A fee a transaction would be charged, from a preview that posted nothing.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FeePreview {
+    pub kind: FeeKind,
+    pub amount: Money,
+}
+/// A fee transaction charged because of another transaction.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct FeeCharge {
+    pub transaction_id: i64,
+    pub kind: FeeKind,
+    pub amount: Money,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for FeeCharge {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(FeeCharge {
+            transaction_id: row.try_get("id")?,
+            kind: row.try_get("fee_kind")?,
+            amount: Money::from_row(row, "amount", "currency")?,
+        })
+    }
+}
@@ -0,0 +1,68 @@
+// src/models/hold.rs
+// Defines the Hold struct
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::models::fee::FeeCharge;
+use crate::models::money::Money;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum HoldStatus {
+    Pending,
+    Captured,
+    Voided,
+    Expired,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Hold {
+    pub id: i64,
+    pub account_number: String,
+    pub seller: String,
+    pub amount: Money,          // authorized
+    pub captured_amount: Money, // zero until captured
+    pub status: HoldStatus,
+    pub transaction_id: Option<i64>, // the posted transaction, once captured
+    pub expires_at: NaiveDateTime,
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Hold {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Hold {
+            id: row.try_get("id")?,
+            account_number: row.try_get("account_number")?,
+            seller: row.try_get("seller")?,
+            amount: Money::from_row(row, "amount", "currency")?,
+            captured_amount: Money::from_row(row, "captured_amount", "currency")?,
+            status: row.try_get("status")?,
+            transaction_id: row.try_get("transaction_id")?,
+            expires_at: row.try_get("expires_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct HoldCreation {
+    pub account_number: String,
+    pub seller: String,
+    pub amount: Money,
+    // defaults to a week
+    pub expires_in_secs: Option<i64>,
+}
+/// Response to capturing a hold, with the fees its transaction was charged as
+/// on a transaction receipt.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CapturedHold {
+    #[serde(flatten)]
+    pub hold: Hold,
+    pub fees: Vec<FeeCharge>,
+}
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct HoldCapture {
+    // defaults to the full authorized amount; any remainder is released
+    pub amount: Option<Money>,
+}
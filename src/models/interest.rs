@@ -0,0 +1,96 @@
+// src/models/interest.rs
+// Defines account products and the interest they accrue
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::models::money::Money;
+
+/// How a year's interest is spread over its days.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+pub enum DayCount {
+    /// actual days over a fixed 365-day year
+    #[default]
+    #[serde(rename = "act_365")]
+    #[sqlx(rename = "act_365")]
+    Act365,
+    /// actual days over a 360-day year
+    #[serde(rename = "act_360")]
+    #[sqlx(rename = "act_360")]
+    Act360,
+    /// actual days over the actual length of the year, 366 in leap years
+    #[serde(rename = "act_act")]
+    #[sqlx(rename = "act_act")]
+    ActAct,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Product {
+    pub code: String,
+    pub name: String,
+    pub annual_rate: String, // decimal fraction, "0.0425" for 4.25%
+    pub day_count: DayCount,
+    pub created_at: NaiveDateTime,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ProductCreation {
+    pub code: String,
+    pub name: String,
+    pub annual_rate: String,
+    #[serde(default)]
+    pub day_count: DayCount,
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct InterestAccrual {
+    pub accrual_date: NaiveDate,
+    pub balance: Money, // at the end of the day
+    pub annual_rate: String,
+    pub day_count: DayCount,
+    pub accrued: String, // decimal in the account's currency, finer than its minor unit
+    pub posted: bool,
+    pub transaction_id: Option<i64>, // the month's interest, once posted
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for InterestAccrual {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let balance = Money::from_row(row, "balance", "currency")?;
+        let accrued_micros: i64 = row.try_get("accrued_micros")?;
+        Ok(InterestAccrual {
+            accrual_date: row.try_get("accrual_date")?,
+            balance,
+            annual_rate: row.try_get("annual_rate")?,
+            day_count: row.try_get("day_count")?,
+            accrued: format_micros(accrued_micros, balance.currency().exponent()),
+            posted: row.try_get("posted")?,
+            transaction_id: row.try_get("transaction_id")?,
+        })
+    }
+}
+/// Query string of `GET /accounts/{account_number}/interest`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct InterestQuery {
+    pub from: Option<NaiveDate>, // inclusive
+    pub to: Option<NaiveDate>,   // inclusive
+}
+
+/// Formats millionths of a minor unit as a decimal of the major unit.
+fn format_micros(micros: i64, exponent: u32) -> String {
+    let decimals = 6 + exponent as usize;
+    let digits = format!("{:0>width$}", micros.unsigned_abs(), width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let sign = if micros < 0 { "-" } else { "" };
+    format!("{}{}.{}", sign, whole, fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_micros() {
+        assert_eq!(format_micros(5_479_452, 2), "0.05479452");
+        assert_eq!(format_micros(123_000_000, 2), "1.23000000");
+        assert_eq!(format_micros(42, 0), "0.000042");
+        assert_eq!(format_micros(-1_500_000, 2), "-0.01500000");
+    }
+}
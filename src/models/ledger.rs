@@ -0,0 +1,31 @@
+// src/models/ledger.rs
+// Defines the trial balance and reconciliation structs
+use serde::{Deserialize, Serialize};
+
+use crate::models::money::Money;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TrialBalanceLine {
+    pub ledger_account: String, // a system account, or all customer accounts together
+    pub debits: Money,
+    pub credits: Money,
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TrialBalanceTotal {
+    pub debits: Money,
+    pub credits: Money,
+    pub balanced: bool,
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TrialBalance {
+    pub lines: Vec<TrialBalanceLine>,
+    pub totals: Vec<TrialBalanceTotal>, // one per currency
+    pub balanced: bool,
+    pub unreconciled_accounts: usize, // cached balances that disagree with the ledger
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Discrepancy {
+    pub account_number: String,
+    pub balance: Money,        // ACCOUNTS.balance
+    pub ledger_balance: Money, // derived from postings
+}
@@ -0,0 +1,29 @@
+// src/models/merchant.rs
+// Defines merchants and the aliases their sellers go by
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct Merchant {
+    pub id: i64,
+    pub name: String,     // canonical, as shown to customers
+    pub category: String, // four-digit merchant category code
+    #[sqlx(skip)]
+    pub aliases: Vec<String>, // other spellings of the seller that match it
+    pub created_at: NaiveDateTime,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MerchantCreation {
+    pub name: String,
+    pub category: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+}
+/// Body of `PUT /admin/merchants/{id}`. Absent fields are left as they are;
+/// `aliases` replaces every alias.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MerchantUpdate {
+    pub name: Option<String>,
+    pub category: Option<String>,
+    pub aliases: Option<Vec<String>>,
+}
@@ -1,5 +1,18 @@
 // src/models/mod.rs
 // This file defines the `models` module and makes its sub-modules public.
-pub mod user;
 pub mod account;
+pub mod analytics;
+pub mod audit;
+pub mod auth;
+pub mod budget;
+pub mod fee;
+pub mod hold;
+pub mod interest;
+pub mod ledger;
+pub mod merchant;
+pub mod money;
+pub mod schedule;
 pub mod transaction;
+pub mod transfer;
+pub mod user;
+pub mod webhook;
@@ -0,0 +1,412 @@
+// src/models/money.rs
+// Defines the Money type: an exact amount in integer minor units plus its currency
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+#[derive(Debug, Default, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "UPPERCASE")]
+#[sqlx(rename_all = "UPPERCASE")]
+pub enum Currency {
+    #[default]
+    Usd,
+    Eur,
+    Gbp,
+    Jpy,
+}
+
+impl Currency {
+    /// ISO 4217 alphabetic code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+            Currency::Jpy => "JPY",
+        }
+    }
+    /// Number of decimal places between the major and minor unit.
+    pub fn exponent(&self) -> u32 {
+        match self {
+            Currency::Jpy => 0,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = MoneyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "USD" => Ok(Currency::Usd),
+            "EUR" => Ok(Currency::Eur),
+            "GBP" => Ok(Currency::Gbp),
+            "JPY" => Ok(Currency::Jpy),
+            _ => Err(MoneyError::UnknownCurrency(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MoneyError {
+    CurrencyMismatch(Currency, Currency),
+    Overflow,
+    InvalidAmount(String),
+    InvalidRate(String),
+    UnknownCurrency(String),
+}
+
+impl fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoneyError::CurrencyMismatch(a, b) => write!(f, "Currency mismatch: {} vs {}", a, b),
+            MoneyError::Overflow => f.write_str("Amount overflow"),
+            MoneyError::InvalidAmount(s) => write!(f, "Invalid amount: {}", s),
+            MoneyError::InvalidRate(s) => write!(f, "Invalid exchange rate: {}", s),
+            MoneyError::UnknownCurrency(s) => write!(f, "Unknown currency: {}", s),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+/// Most fractional digits accepted in an exchange rate.
+pub const MAX_RATE_DECIMALS: usize = 12;
+
+/// An exact monetary amount. The value is held in the currency's minor unit
+/// (e.g. cents) so arithmetic never drifts, and is serialized to JSON as a
+/// decimal string so clients never round it through a float.
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[serde(try_from = "MoneyRepr", into = "MoneyRepr")]
+pub struct Money {
+    minor_units: i64,
+    currency: Currency,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MoneyRepr {
+    amount: String,
+    currency: Currency,
+}
+
+impl TryFrom<MoneyRepr> for Money {
+    type Error = MoneyError;
+
+    fn try_from(repr: MoneyRepr) -> Result<Self, Self::Error> {
+        Money::parse(&repr.amount, repr.currency)
+    }
+}
+
+impl From<Money> for MoneyRepr {
+    fn from(money: Money) -> Self {
+        MoneyRepr {
+            amount: money.format_amount(),
+            currency: money.currency,
+        }
+    }
+}
+
+impl Money {
+    pub fn new(minor_units: i64, currency: Currency) -> Self {
+        Money {
+            minor_units,
+            currency,
+        }
+    }
+    pub fn zero(currency: Currency) -> Self {
+        Money::new(0, currency)
+    }
+    pub fn minor_units(&self) -> i64 {
+        self.minor_units
+    }
+    pub fn currency(&self) -> Currency {
+        self.currency
+    }
+    pub fn is_zero(&self) -> bool {
+        self.minor_units == 0
+    }
+    pub fn is_negative(&self) -> bool {
+        self.minor_units < 0
+    }
+    pub fn is_positive(&self) -> bool {
+        self.minor_units > 0
+    }
+
+    /// Parses a decimal string such as `"-12.34"` without going through a float.
+    /// More fractional digits than the currency allows is an error, not a rounding.
+    pub fn parse(amount: &str, currency: Currency) -> Result<Self, MoneyError> {
+        let invalid = || MoneyError::InvalidAmount(amount.to_string());
+        let (negative, digits) = match amount.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, amount.strip_prefix('+').unwrap_or(amount)),
+        };
+        let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+        let exponent = currency.exponent() as usize;
+        if whole.is_empty()
+            || fraction.len() > exponent
+            || (digits.contains('.') && fraction.is_empty())
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let scale = 10i64.pow(exponent as u32);
+        let whole: i64 = whole.parse().map_err(|_| MoneyError::Overflow)?;
+        let fraction: i64 = if fraction.is_empty() {
+            0
+        } else {
+            format!("{:0<width$}", fraction, width = exponent)
+                .parse()
+                .map_err(|_| invalid())?
+        };
+        let magnitude = whole
+            .checked_mul(scale)
+            .and_then(|w| w.checked_add(fraction))
+            .ok_or(MoneyError::Overflow)?;
+        Ok(Money::new(
+            if negative { -magnitude } else { magnitude },
+            currency,
+        ))
+    }
+
+    /// Formats the amount as a plain decimal string in major units, e.g. `"-12.34"`.
+    pub fn format_amount(&self) -> String {
+        let exponent = self.currency.exponent();
+        if exponent == 0 {
+            return self.minor_units.to_string();
+        }
+        let scale = 10u64.pow(exponent);
+        let magnitude = self.minor_units.unsigned_abs();
+        format!(
+            "{}{}.{:0width$}",
+            if self.minor_units < 0 { "-" } else { "" },
+            magnitude / scale,
+            magnitude % scale,
+            width = exponent as usize
+        )
+    }
+
+    fn ensure_same_currency(&self, other: &Money) -> Result<(), MoneyError> {
+        if self.currency != other.currency {
+            return Err(MoneyError::CurrencyMismatch(self.currency, other.currency));
+        }
+        Ok(())
+    }
+    pub fn checked_add(self, other: Money) -> Result<Money, MoneyError> {
+        self.ensure_same_currency(&other)?;
+        self.minor_units
+            .checked_add(other.minor_units)
+            .map(|m| Money::new(m, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+    pub fn checked_sub(self, other: Money) -> Result<Money, MoneyError> {
+        self.ensure_same_currency(&other)?;
+        self.minor_units
+            .checked_sub(other.minor_units)
+            .map(|m| Money::new(m, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+    pub fn checked_neg(self) -> Result<Money, MoneyError> {
+        self.minor_units
+            .checked_neg()
+            .map(|m| Money::new(m, self.currency))
+            .ok_or(MoneyError::Overflow)
+    }
+
+    /// Converts into `to` at `rate`, a positive decimal string giving units of `to`
+    /// per unit of this currency. Rounds half away from zero to `to`'s minor unit.
+    pub fn convert(self, rate: &str, to: Currency) -> Result<Money, MoneyError> {
+        let invalid = || MoneyError::InvalidRate(rate.to_string());
+        let (whole, fraction) = rate.split_once('.').unwrap_or((rate, ""));
+        if whole.is_empty()
+            || fraction.len() > MAX_RATE_DECIMALS
+            || (rate.contains('.') && fraction.is_empty())
+            || !whole
+                .chars()
+                .chain(fraction.chars())
+                .all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let numerator: i128 = format!("{}{}", whole, fraction)
+            .parse()
+            .map_err(|_| invalid())?;
+        if numerator == 0 {
+            return Err(invalid());
+        }
+        let denominator = 10i128.pow(fraction.len() as u32) * 10i128.pow(self.currency.exponent());
+        let scaled = (self.minor_units as i128)
+            .checked_mul(numerator)
+            .and_then(|v| v.checked_mul(10i128.pow(to.exponent())))
+            .ok_or(MoneyError::Overflow)?;
+        let (quotient, remainder) = (scaled / denominator, scaled % denominator);
+        let rounded = if remainder.abs() * 2 >= denominator {
+            quotient + scaled.signum()
+        } else {
+            quotient
+        };
+        i64::try_from(rounded)
+            .map(|m| Money::new(m, to))
+            .map_err(|_| MoneyError::Overflow)
+    }
+
+    /// Reads a money value stored as an INTEGER minor-unit column plus a TEXT currency column.
+    pub fn from_row(
+        row: &SqliteRow,
+        amount_column: &str,
+        currency_column: &str,
+    ) -> Result<Money, sqlx::Error> {
+        Ok(Money::new(
+            row.try_get(amount_column)?,
+            row.try_get(currency_column)?,
+        ))
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.format_amount(), self.currency)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_format_roundtrip() {
+        for s in ["0.00", "12.34", "-12.34", "0.05", "-0.05", "1000000.00"] {
+            let money = Money::parse(s, Currency::Usd).unwrap();
+            assert_eq!(money.format_amount(), s);
+        }
+        assert_eq!(
+            Money::parse("12", Currency::Usd).unwrap().minor_units(),
+            1200
+        );
+        assert_eq!(
+            Money::parse("12.3", Currency::Usd).unwrap().minor_units(),
+            1230
+        );
+        assert_eq!(
+            Money::parse("150", Currency::Jpy).unwrap().format_amount(),
+            "150"
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_bad_input() {
+        for s in ["", "-", "1.", ".5", "1.234", "abc", "1,00", "1e5"] {
+            assert!(Money::parse(s, Currency::Usd).is_err(), "{}", s);
+        }
+        assert!(Money::parse("1.5", Currency::Jpy).is_err());
+        assert_eq!(
+            Money::parse("99999999999999999999", Currency::Usd),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_is_exact() {
+        let mut total = Money::zero(Currency::Usd);
+        let cent = Money::new(1, Currency::Usd);
+        for _ in 0..100_000 {
+            total = total.checked_add(cent).unwrap();
+        }
+        assert_eq!(total.format_amount(), "1000.00");
+        assert_eq!(
+            total
+                .checked_sub(Money::new(100_000, Currency::Usd))
+                .unwrap(),
+            Money::zero(Currency::Usd)
+        );
+    }
+
+    #[test]
+    fn test_arithmetic_checks() {
+        let usd = Money::new(1, Currency::Usd);
+        let eur = Money::new(1, Currency::Eur);
+        assert_eq!(
+            usd.checked_add(eur),
+            Err(MoneyError::CurrencyMismatch(Currency::Usd, Currency::Eur))
+        );
+        assert_eq!(
+            Money::new(i64::MAX, Currency::Usd).checked_add(usd),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(
+            Money::new(i64::MIN, Currency::Usd).checked_neg(),
+            Err(MoneyError::Overflow)
+        );
+    }
+
+    #[test]
+    fn test_json_representation() {
+        let money = Money::new(-5000, Currency::Usd);
+        let json = serde_json::to_string(&money).unwrap();
+        assert_eq!(json, r#"{"amount":"-50.00","currency":"USD"}"#);
+        let back: Money = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, money);
+        assert!(serde_json::from_str::<Money>(r#"{"amount":"1.001","currency":"USD"}"#).is_err());
+    }
+
+    #[test]
+    fn test_convert() {
+        let usd = Money::new(10_000, Currency::Usd); // 100.00
+        assert_eq!(
+            usd.convert("0.9215", Currency::Eur).unwrap(),
+            Money::new(9_215, Currency::Eur)
+        );
+        assert_eq!(
+            usd.convert("151.237", Currency::Jpy).unwrap(),
+            Money::new(15_124, Currency::Jpy)
+        );
+        assert_eq!(
+            Money::new(15_124, Currency::Jpy)
+                .convert("0.006612", Currency::Usd)
+                .unwrap(),
+            Money::new(10_000, Currency::Usd)
+        );
+        // half a cent rounds away from zero
+        assert_eq!(
+            Money::new(1, Currency::Usd)
+                .convert("0.5", Currency::Eur)
+                .unwrap(),
+            Money::new(1, Currency::Eur)
+        );
+        assert_eq!(
+            Money::new(-1, Currency::Usd)
+                .convert("0.5", Currency::Eur)
+                .unwrap(),
+            Money::new(-1, Currency::Eur)
+        );
+    }
+
+    #[test]
+    fn test_convert_rejects_bad_rates() {
+        let usd = Money::new(100, Currency::Usd);
+        for rate in ["", "0", "0.0", "-1", "1.", ".5", "abc", "1.0000000000001"] {
+            assert_eq!(
+                usd.convert(rate, Currency::Eur),
+                Err(MoneyError::InvalidRate(rate.to_string())),
+                "{}",
+                rate
+            );
+        }
+        assert_eq!(
+            Money::new(i64::MAX, Currency::Usd).convert("2", Currency::Eur),
+            Err(MoneyError::Overflow)
+        );
+    }
+}
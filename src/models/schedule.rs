@@ -0,0 +1,116 @@
+// src/models/schedule.rs
+// Defines the Schedule and ScheduleRun structs
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::models::money::Money;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ScheduleFrequency {
+    Once,
+    Daily,
+    Weekly,
+    Monthly, // same day of the month, or its last day if shorter
+    Cron,
+}
+
+/// What happens to an occurrence that finds too little money in the account.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum InsufficientFundsPolicy {
+    #[default]
+    Retry, // try again later, skipping it after the configured number of retries
+    Skip,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ScheduleStatus {
+    Active,
+    Completed,
+    Cancelled,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ScheduleRunOutcome {
+    Succeeded,
+    Retrying,
+    Skipped,
+    Failed,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: i64,
+    pub user_id: i64,
+    pub account_number: String,
+    pub seller: String,
+    pub amount: Money,
+    pub frequency: ScheduleFrequency,
+    pub cron: Option<String>,
+    pub start_at: NaiveDateTime, // all times are UTC
+    pub end_at: Option<NaiveDateTime>,
+    pub max_runs: Option<i64>,
+    pub on_insufficient_funds: InsufficientFundsPolicy,
+    pub occurrences: i64,
+    pub retry_count: i64,
+    pub next_run_at: Option<NaiveDateTime>, // None once finished
+    pub due_at: Option<NaiveDateTime>,
+    pub status: ScheduleStatus,
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Schedule {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Schedule {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            account_number: row.try_get("account_number")?,
+            seller: row.try_get("seller")?,
+            amount: Money::from_row(row, "amount", "currency")?,
+            frequency: row.try_get("frequency")?,
+            cron: row.try_get("cron")?,
+            start_at: row.try_get("start_at")?,
+            end_at: row.try_get("end_at")?,
+            max_runs: row.try_get("max_runs")?,
+            on_insufficient_funds: row.try_get("on_insufficient_funds")?,
+            occurrences: row.try_get("occurrences")?,
+            retry_count: row.try_get("retry_count")?,
+            next_run_at: row.try_get("next_run_at")?,
+            due_at: row.try_get("due_at")?,
+            status: row.try_get("status")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ScheduleCreation {
+    pub account_number: String,
+    pub seller: String,
+    pub amount: Money, // debited on every occurrence
+    pub frequency: ScheduleFrequency,
+    // required for the cron frequency, e.g. "0 9 1 * *"
+    pub cron: Option<String>,
+    pub start_at: NaiveDateTime,
+    pub end_at: Option<NaiveDateTime>,
+    pub max_runs: Option<i64>,
+    #[serde(default)]
+    pub on_insufficient_funds: InsufficientFundsPolicy,
+}
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct ScheduleRun {
+    pub id: i64,
+    pub schedule_id: i64,
+    pub occurrence: i64,
+    pub scheduled_for: NaiveDateTime,
+    pub outcome: ScheduleRunOutcome,
+    pub message: Option<String>, // why it did not succeed
+    pub created_at: NaiveDateTime,
+}
@@ -1,27 +1,130 @@
 // src/models/transaction.rs
 // Defines the Transaction struct
-use chrono::NaiveDateTime;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
 
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+use crate::models::fee::{FeeCharge, FeeKind, FeePreview};
+use crate::models::money::{Currency, Money};
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Transaction {
     pub id: Option<i32>, // AUTO_INCREMENT
     pub account_number: String,
     pub seller: String,
-    pub amount: f32, // DECIMAL type
+    pub amount: Money, // INTEGER minor units + currency
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Transaction {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Transaction {
+            id: row.try_get("id")?,
+            account_number: row.try_get("account_number")?,
+            seller: row.try_get("seller")?,
+            amount: Money::from_row(row, "amount", "currency")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct TransactionGeneral {
     pub id: Option<i32>, // AUTO_INCREMENT
     pub account_number: String,
     pub seller: String,
-    pub amount: f32, // DECIMAL type
+    pub amount: Money,                        // INTEGER minor units + currency
+    pub transfer_id: Option<i64>,             // set on both legs of a transfer
+    pub original_transaction_id: Option<i64>, // set on refunds
+    pub refunded_amount: Money,               // refunded so far, on the original
+    pub fee_kind: Option<FeeKind>,            // set on fees
+    pub fee_for_transaction_id: Option<i64>,  // the transaction a fee was charged for
+    pub merchant_id: Option<i64>,             // the merchant the seller was matched to
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for TransactionGeneral {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(TransactionGeneral {
+            id: row.try_get("id")?,
+            account_number: row.try_get("account_number")?,
+            seller: row.try_get("seller")?,
+            amount: Money::from_row(row, "amount", "currency")?,
+            transfer_id: row.try_get("transfer_id")?,
+            original_transaction_id: row.try_get("original_transaction_id")?,
+            refunded_amount: Money::from_row(row, "refunded_amount", "currency")?,
+            fee_kind: row.try_get("fee_kind")?,
+            fee_for_transaction_id: row.try_get("fee_for_transaction_id")?,
+            merchant_id: row.try_get("merchant_id")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
 }
-#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, sqlx::FromRow)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct TransactionCreation {
     pub account_number: String,
     pub seller: String,
-    pub amount: f32, // DECIMAL type
+    pub amount: Money, // positive debits the account, negative credits it
+}
+/// Response to `POST /transactions`: the transaction as posted, and the fees
+/// charged along with it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransactionReceipt {
+    pub id: i64,
+    pub account_number: String,
+    pub seller: String,
+    pub merchant_id: Option<i64>, // the merchant the seller was matched to
+    pub amount: Money,
+    pub fees: Vec<FeeCharge>,
+}
+/// Query string of `POST /transactions`.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TransactionCreationQuery {
+    #[serde(default)]
+    pub preview: bool, // respond with the fees that would be charged, posting nothing
+}
+/// Response to `POST /transactions?preview=true`: the transaction as it would
+/// be posted, and the fees that would be charged along with it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransactionPreview {
+    pub account_number: String,
+    pub seller: String,
+    pub merchant_id: Option<i64>,
+    pub amount: Money,
+    pub fees: Vec<FeePreview>,
+}
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct RefundCreation {
+    // defaults to whatever of the original has not been refunded yet
+    pub amount: Option<Money>,
+}
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionSort {
+    #[default]
+    CreatedAtAsc,
+    CreatedAtDesc,
+    AmountAsc,
+    AmountDesc,
+}
+/// Query string of `GET /transactions`. Every filter is optional and they combine with AND.
+#[derive(Debug, Default, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct TransactionQuery {
+    pub account_number: Option<String>,
+    pub from: Option<NaiveDate>,    // inclusive
+    pub to: Option<NaiveDate>,      // inclusive
+    pub min_amount: Option<String>, // decimal string, read in `currency`
+    pub max_amount: Option<String>, // decimal string, read in `currency`
+    pub currency: Option<Currency>, // defaults to USD when an amount bound is given
+    pub seller: Option<String>,     // case-insensitive substring
+    pub merchant_id: Option<i64>,
+    #[serde(default)]
+    pub sort: TransactionSort,
+    pub limit: Option<u32>,
+    pub cursor: Option<String>, // `next_cursor` of the previous page
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransactionPage {
+    pub items: Vec<TransactionGeneral>,
+    pub next_cursor: Option<String>, // absent on the last page
 }
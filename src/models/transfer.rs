@@ -0,0 +1,40 @@
+// src/models/transfer.rs
+// Defines the Transfer struct
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+use crate::models::money::Money;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Transfer {
+    pub id: i64,
+    pub source_account_number: String,
+    pub destination_account_number: String,
+    pub amount: Money,          // debited from the source
+    pub credited_amount: Money, // credited to the destination
+    pub rate: Option<String>,   // destination units per source unit
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Transfer {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(Transfer {
+            id: row.try_get("id")?,
+            source_account_number: row.try_get("source_account_number")?,
+            destination_account_number: row.try_get("destination_account_number")?,
+            amount: Money::from_row(row, "amount", "currency")?,
+            credited_amount: Money::from_row(row, "credited_amount", "credited_currency")?,
+            rate: row.try_get("rate")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct TransferCreation {
+    pub source_account_number: String, // must belong to the caller
+    pub destination_account_number: String,
+    pub amount: Money, // in the source account's currency
+    // required when the destination holds a different currency
+    pub rate: Option<String>,
+}
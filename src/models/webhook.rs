@@ -0,0 +1,161 @@
+// src/models/webhook.rs
+// Defines outbox events, webhooks and their deliveries
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+pub enum EventType {
+    #[serde(rename = "user.created")]
+    #[sqlx(rename = "user.created")]
+    UserCreated,
+    #[serde(rename = "account.created")]
+    #[sqlx(rename = "account.created")]
+    AccountCreated,
+    #[serde(rename = "transaction.posted")]
+    #[sqlx(rename = "transaction.posted")]
+    TransactionPosted, // every TRANSACTIONS row: payments, deposits, transfer legs, refunds, fees, interest
+    #[serde(rename = "transfer.completed")]
+    #[sqlx(rename = "transfer.completed")]
+    TransferCompleted,
+    #[serde(rename = "balance.changed")]
+    #[sqlx(rename = "balance.changed")]
+    BalanceChanged, // balance or available balance, including holds and overdraft limits
+    #[serde(rename = "account.status_changed")]
+    #[sqlx(rename = "account.status_changed")]
+    AccountStatusChanged,
+}
+
+impl EventType {
+    pub const ALL: [EventType; 6] = [
+        EventType::UserCreated,
+        EventType::AccountCreated,
+        EventType::TransactionPosted,
+        EventType::TransferCompleted,
+        EventType::BalanceChanged,
+        EventType::AccountStatusChanged,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::UserCreated => "user.created",
+            EventType::AccountCreated => "account.created",
+            EventType::TransactionPosted => "transaction.posted",
+            EventType::TransferCompleted => "transfer.completed",
+            EventType::BalanceChanged => "balance.changed",
+            EventType::AccountStatusChanged => "account.status_changed",
+        }
+    }
+}
+
+/// What a webhook receives: one event, as recorded in the outbox.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Event {
+    pub id: i64, // the same on every retry, so receivers can drop duplicates
+    #[serde(rename = "type")]
+    pub event_type: EventType,
+    pub created_at: NaiveDateTime,
+    pub data: serde_json::Value,
+}
+// read from an OUTBOX_EVENTS row whose id and created_at are aliased to
+// event_id and event_created_at, as when joined with its deliveries
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Event {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let payload: String = row.try_get("payload")?;
+        Ok(Event {
+            id: row.try_get("event_id")?,
+            event_type: row.try_get("event_type")?,
+            created_at: row.try_get("event_created_at")?,
+            data: serde_json::from_str(&payload).map_err(|err| sqlx::Error::ColumnDecode {
+                index: "payload".to_string(),
+                source: Box::new(err),
+            })?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: i64,
+    pub url: String,
+    pub event_types: Vec<EventType>, // empty for every event
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for Webhook {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        let event_types: Option<String> = row.try_get("event_types")?;
+        let event_types = event_types
+            .unwrap_or_default()
+            .split(',')
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                EventType::ALL
+                    .into_iter()
+                    .find(|event_type| event_type.as_str() == name)
+                    .ok_or_else(|| sqlx::Error::ColumnDecode {
+                        index: "event_types".to_string(),
+                        source: format!("unknown event type {}", name).into(),
+                    })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Webhook {
+            id: row.try_get("id")?,
+            url: row.try_get("url")?,
+            event_types,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct WebhookCreation {
+    pub url: String,
+    #[serde(default)]
+    pub event_types: Vec<EventType>, // empty for every event
+}
+/// Response to registering a webhook, the only time its secret is shown.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct WebhookRegistration {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    pub secret: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum DeliveryStatus {
+    Pending,   // waiting for its next attempt
+    Delivered, // the webhook answered with a 2xx
+    Dead,      // out of attempts; retried only by hand
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub webhook_id: i64,
+    pub event: Event,
+    pub status: DeliveryStatus,
+    pub attempts: i64,
+    pub next_attempt_at: NaiveDateTime,
+    pub last_status_code: Option<i64>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<NaiveDateTime>,
+    pub created_at: NaiveDateTime,
+}
+impl<'r> sqlx::FromRow<'r, SqliteRow> for WebhookDelivery {
+    fn from_row(row: &'r SqliteRow) -> Result<Self, sqlx::Error> {
+        Ok(WebhookDelivery {
+            id: row.try_get("id")?,
+            webhook_id: row.try_get("webhook_id")?,
+            event: Event::from_row(row)?,
+            status: row.try_get("status")?,
+            attempts: row.try_get("attempts")?,
+            next_attempt_at: row.try_get("next_attempt_at")?,
+            last_status_code: row.try_get("last_status_code")?,
+            last_error: row.try_get("last_error")?,
+            delivered_at: row.try_get("delivered_at")?,
+            created_at: row.try_get("created_at")?,
+        })
+    }
+}
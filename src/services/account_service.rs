@@ -1,87 +1,522 @@
-use std::str::FromStr;
-
+use crate::error::AppError;
 use crate::models;
-use crate::services::{generation_service, user_service};
+use crate::models::account::AccountStatus;
+use crate::models::audit::AuditAction;
+use crate::models::money::Money;
+use crate::models::webhook::EventType;
+use crate::services::generation_service::{self, AccountNumberScheme};
+use crate::services::{audit_service, outbox_service, transaction_service, transfer_service};
+
+use sqlx::{SqliteConnection, SqlitePool};
 
-use sqlx::SqlitePool;
+const ACCOUNT_COLUMNS: &str = "account_number, iban, user_id, balance,
+    balance - held + overdraft_limit AS available_balance, overdraft_limit, overdraft_fee, product,
+    status, status_reason, currency, created_at";
 
 pub async fn get_accounts(
     pool: &SqlitePool,
-) -> Result<Vec<models::account::AccountGeneral>, Box<dyn std::error::Error>> {
+    user_id: i64,
+) -> Result<Vec<models::account::AccountGeneral>, AppError> {
     tracing::info!("Invocation to `get_accounts`");
-    let res: Vec<models::account::AccountGeneral> =
-        sqlx::query_as("SELECT account_number, user_id, balance, created_at FROM ACCOUNTS;")
-            .fetch_all(pool)
-            .await?;
+    let res: Vec<models::account::AccountGeneral> = sqlx::query_as(&format!(
+        "SELECT {} FROM ACCOUNTS WHERE user_id = ?;",
+        ACCOUNT_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
     Ok(res)
 }
 pub async fn get_account(
     pool: &SqlitePool,
     id: i64,
-) -> Result<models::account::AccountGeneral, Box<dyn std::error::Error>> {
+) -> Result<models::account::AccountGeneral, AppError> {
     tracing::info!("Invocation to `get_account`");
-    let account: models::account::AccountGeneral = sqlx::query_as(
-        "SELECT account_number, user_id, balance, created_at FROM ACCOUNTS WHERE id = ?;",
-    )
-    .bind(&id)
-    .fetch_one(pool)
+    let account: Option<models::account::AccountGeneral> = sqlx::query_as(&format!(
+        "SELECT {} FROM ACCOUNTS WHERE id = ?;",
+        ACCOUNT_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
     .await?;
-    Ok(account)
+    account.ok_or_else(|| AppError::NotFound(format!("Account {} not found", id)))
 }
 pub async fn get_account_by_account_number(
     pool: &SqlitePool,
     account_number: String,
-) -> Result<models::account::AccountGeneral, Box<dyn std::error::Error>> {
+) -> Result<models::account::AccountGeneral, AppError> {
     tracing::info!("Invocation to `get_account_by_account_number`");
-    let account: models::account::AccountGeneral = sqlx::query_as(
-        "SELECT account_number, user_id, balance, created_at FROM ACCOUNTS WHERE account_number = ?;",
-    )
+    let account_number = resolve_account_number(pool, &account_number).await?;
+    let account: Option<models::account::AccountGeneral> = sqlx::query_as(&format!(
+        "SELECT {} FROM ACCOUNTS WHERE account_number = ?;",
+        ACCOUNT_COLUMNS
+    ))
     .bind(&account_number)
-    .fetch_one(pool)
+    .fetch_optional(pool)
     .await?;
-    Ok(account)
+    account.ok_or_else(|| AppError::NotFound(format!("Account {} not found", account_number)))
 }
+/// Product of accounts opened without naming one.
+pub const DEFAULT_PRODUCT: &str = "checking";
+/// Attempts at finding an unused account number before giving up.
+pub const MAX_ACCOUNT_NUMBER_ATTEMPTS: usize = 5;
+
 pub async fn create_account(
     pool: &SqlitePool,
+    scheme: &AccountNumberScheme,
+    user_id: i64,
     account_creation: models::account::AccountCreation,
-) -> Result<models::account::AccountGeneral, Box<dyn std::error::Error>> {
+) -> Result<models::account::AccountGeneral, AppError> {
     tracing::info!("Invocation to `create_account`");
-    let user_id = account_creation.user_id;
-    let account_number = generation_service::generate_numeric_string(20); // TODO: MAKE ENV
-    let res =
-        sqlx::query("INSERT INTO ACCOUNTS (account_number, user_id, balance) VALUES (?, ?, ?);")
+    let product = account_creation
+        .product
+        .unwrap_or_else(|| DEFAULT_PRODUCT.to_string());
+    let known: Option<String> = sqlx::query_scalar("SELECT code FROM PRODUCTS WHERE code = ?;")
+        .bind(&product)
+        .fetch_optional(pool)
+        .await?;
+    if known.is_none() {
+        return Err(AppError::Validation(format!("Unknown product {}", product)));
+    }
+    for _ in 0..MAX_ACCOUNT_NUMBER_ATTEMPTS {
+        let account_number = scheme.generate();
+        let iban = scheme.iban().derive(&account_number)?;
+        let mut tx = pool.begin().await?;
+        let res = sqlx::query(
+            "INSERT INTO ACCOUNTS (account_number, iban, user_id, balance, currency, product)
+             VALUES (?, ?, ?, ?, ?, ?);",
+        )
+        .bind(account_number)
+        .bind(iban)
+        .bind(user_id)
+        .bind(0i64)
+        .bind(account_creation.currency)
+        .bind(&product)
+        .execute(&mut *tx)
+        .await
+        .map_err(AppError::from);
+        match res {
+            Ok(res) => {
+                let account: models::account::AccountGeneral = sqlx::query_as(&format!(
+                    "SELECT {} FROM ACCOUNTS WHERE id = ?;",
+                    ACCOUNT_COLUMNS
+                ))
+                .bind(res.last_insert_rowid())
+                .fetch_one(&mut *tx)
+                .await?;
+                outbox_service::record(&mut tx, EventType::AccountCreated, &account).await?;
+                audit_service::record(
+                    &mut tx,
+                    AuditAction::AccountCreate,
+                    &account.account_number,
+                    None,
+                    Some(&account),
+                )
+                .await?;
+                tx.commit().await?;
+                return Ok(account);
+            }
+            // the number is taken; draw another one
+            Err(AppError::Conflict(_)) => continue,
+            Err(AppError::Validation(_)) => {
+                return Err(AppError::Validation(format!(
+                    "User {} does not exist",
+                    user_id
+                )));
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Err(AppError::Internal(format!(
+        "No free account number after {} attempts",
+        MAX_ACCOUNT_NUMBER_ATTEMPTS
+    )))
+}
+
+/// Gives accounts opened before IBANs existed their IBAN. Returns how many were assigned.
+pub async fn assign_missing_ibans(
+    pool: &SqlitePool,
+    scheme: &AccountNumberScheme,
+) -> Result<usize, AppError> {
+    tracing::info!("Invocation to `assign_missing_ibans`");
+    let missing: Vec<String> =
+        sqlx::query_scalar("SELECT account_number FROM ACCOUNTS WHERE iban IS NULL;")
+            .fetch_all(pool)
+            .await?;
+    for account_number in &missing {
+        let mut tx = pool.begin().await?;
+        let before = fetch_account(&mut tx, account_number).await?;
+        sqlx::query("UPDATE ACCOUNTS SET iban = ? WHERE account_number = ? AND iban IS NULL;")
+            .bind(scheme.iban().derive(account_number)?)
+            .bind(account_number)
+            .execute(&mut *tx)
+            .await?;
+        let after = fetch_account(&mut tx, account_number).await?;
+        audit_service::record(
+            &mut tx,
+            AuditAction::AccountAssignIban,
+            account_number,
+            Some(&before),
+            Some(&after),
+        )
+        .await?;
+        tx.commit().await?;
+    }
+    Ok(missing.len())
+}
+
+/// Turns an account number or IBAN, as a client sent it, into an account
+/// number. Malformed input is rejected before the database is queried; IBANs
+/// are looked up, plain account numbers are returned as they are.
+pub async fn resolve_account_number(pool: &SqlitePool, input: &str) -> Result<String, AppError> {
+    let mut conn = pool.acquire().await?;
+    resolve_account_number_in(&mut conn, input).await
+}
+
+pub(crate) async fn resolve_account_number_in(
+    conn: &mut SqliteConnection,
+    input: &str,
+) -> Result<String, AppError> {
+    if !generation_service::looks_like_iban(input) {
+        generation_service::validate_account_number(input)?;
+        return Ok(input.to_string());
+    }
+    let iban = generation_service::normalize_iban(input);
+    generation_service::validate_iban(&iban)?;
+    let account_number: Option<String> =
+        sqlx::query_scalar("SELECT account_number FROM ACCOUNTS WHERE iban = ?;")
+            .bind(&iban)
+            .fetch_optional(conn)
+            .await?;
+    account_number.ok_or_else(|| AppError::NotFound(format!("Account {} not found", iban)))
+}
+
+pub async fn get_iban(
+    pool: &SqlitePool,
+    iban: &str,
+) -> Result<models::account::IbanLookup, AppError> {
+    tracing::info!("Invocation to `get_iban`");
+    let iban = generation_service::normalize_iban(iban);
+    generation_service::validate_iban(&iban)?;
+    let lookup: Option<models::account::IbanLookup> =
+        sqlx::query_as("SELECT iban, account_number, currency FROM ACCOUNTS WHERE iban = ?;")
+            .bind(&iban)
+            .fetch_optional(pool)
+            .await?;
+    lookup.ok_or_else(|| AppError::NotFound(format!("Account {} not found", iban)))
+}
+
+/// Sets how far below zero an account may go and what each debit that leaves
+/// it there costs. Lowering the limit below an existing overdraft only stops
+/// further debits.
+pub async fn set_overdraft(
+    pool: &SqlitePool,
+    account_number: &str,
+    setting: models::account::OverdraftSetting,
+) -> Result<models::account::AccountGeneral, AppError> {
+    tracing::info!("Invocation to `set_overdraft`");
+    let account_number = resolve_account_number(pool, account_number).await?;
+    let account = get_account_by_account_number(pool, account_number.clone()).await?;
+    let currency = account.balance.currency();
+    let fee = setting.fee.unwrap_or(Money::zero(currency));
+    if setting.limit.is_negative() || fee.is_negative() {
+        return Err(AppError::Validation(
+            "Overdraft limit and fee must not be negative".to_string(),
+        ));
+    }
+    // a limit or fee in another currency is a validation error
+    account.balance.checked_sub(setting.limit)?;
+    account.balance.checked_sub(fee)?;
+    let mut tx = pool.begin().await?;
+    let before = fetch_account(&mut tx, &account_number).await?;
+    sqlx::query(
+        "UPDATE ACCOUNTS SET overdraft_limit = ?, overdraft_fee = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE account_number = ?;",
+    )
+    .bind(setting.limit.minor_units())
+    .bind(fee.minor_units())
+    .bind(&account_number)
+    .execute(&mut *tx)
+    .await?;
+    let after = fetch_account(&mut tx, &account_number).await?;
+    audit_service::record(
+        &mut tx,
+        AuditAction::AccountSetOverdraft,
+        &account_number,
+        Some(&before),
+        Some(&after),
+    )
+    .await?;
+    record_balance_changed(&mut tx, &account_number).await?;
+    tx.commit().await?;
+    Ok(after)
+}
+
+/// Moves an account to another status, if its current one allows it. Closing
+/// needs no pending holds and a balance that is not negative; a positive one
+/// is transferred to `sweep_to`, which must be an active account in the same
+/// currency, first.
+pub async fn change_status(
+    pool: &SqlitePool,
+    account_number: &str,
+    change: models::account::StatusChange,
+) -> Result<models::account::AccountGeneral, AppError> {
+    tracing::info!("Invocation to `change_status`");
+    let account_number = resolve_account_number(pool, account_number).await?;
+    let sweep_to = match &change.sweep_to {
+        Some(input) if change.status == AccountStatus::Closed => {
+            Some(resolve_account_number(pool, input).await?)
+        }
+        _ => None,
+    };
+    let mut tx = transaction_service::begin_write(pool).await?;
+    let after = change_status_in(
+        &mut tx,
+        &account_number,
+        change.status,
+        &change.reason,
+        sweep_to,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(after)
+}
+
+/// `change_status` inside the caller's database transaction, with the account
+/// numbers already resolved.
+async fn change_status_in(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+    status: AccountStatus,
+    reason: &str,
+    sweep_to: Option<String>,
+) -> Result<models::account::AccountGeneral, AppError> {
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::Validation("A reason is required".to_string()));
+    }
+    let before = fetch_account(conn, account_number).await?;
+    if !before.status.can_become(status) {
+        return Err(AppError::Conflict(format!(
+            "Account {} cannot go from {} to {}",
+            account_number,
+            before.status.as_str(),
+            status.as_str()
+        )));
+    }
+    if status == AccountStatus::Closed {
+        let held: i64 = sqlx::query_scalar("SELECT held FROM ACCOUNTS WHERE account_number = ?;")
+            .bind(account_number)
+            .fetch_one(&mut *conn)
+            .await?;
+        if held > 0 {
+            return Err(AppError::Conflict(format!(
+                "Account {} has pending holds",
+                account_number
+            )));
+        }
+        if before.balance.is_negative() {
+            return Err(AppError::Conflict(format!(
+                "Account {} is overdrawn",
+                account_number
+            )));
+        }
+        if before.balance.is_positive() {
+            let Some(sweep_to) = sweep_to else {
+                return Err(AppError::Conflict(format!(
+                    "Account {} still holds {}; nominate a `sweep_to` account",
+                    account_number, before.balance
+                )));
+            };
+            if sweep_to == account_number {
+                return Err(AppError::Validation(
+                    "Cannot sweep an account into itself".to_string(),
+                ));
+            }
+            ensure_active(conn, &sweep_to).await?;
+            transfer_service::post_transfer(conn, account_number, &sweep_to, before.balance, None)
+                .await?;
+        }
+    }
+    sqlx::query(
+        "UPDATE ACCOUNTS SET status = ?, status_reason = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE account_number = ?;",
+    )
+    .bind(status)
+    .bind(reason)
+    .bind(account_number)
+    .execute(&mut *conn)
+    .await?;
+    let after = fetch_account(conn, account_number).await?;
+    audit_service::record(
+        conn,
+        AuditAction::AccountChangeStatus,
+        account_number,
+        Some(&before),
+        Some(&after),
+    )
+    .await?;
+    outbox_service::record(
+        conn,
+        EventType::AccountStatusChanged,
+        &serde_json::json!({
+            "account_number": account_number,
+            "previous_status": before.status,
+            "status": after.status,
+            "reason": reason,
+        }),
+    )
+    .await?;
+    Ok(after)
+}
+
+/// Closes one of the caller's own accounts, unless it is frozen; see `change_status`.
+pub async fn close_account(
+    pool: &SqlitePool,
+    user_id: i64,
+    account_number: &str,
+    closure: models::account::AccountClosure,
+) -> Result<models::account::AccountGeneral, AppError> {
+    tracing::info!("Invocation to `close_account`");
+    let account_number = resolve_account_number(pool, account_number).await?;
+    let sweep_to = match &closure.sweep_to {
+        Some(input) => Some(resolve_account_number(pool, input).await?),
+        None => None,
+    };
+    let mut tx = transaction_service::begin_write(pool).await?;
+    ensure_owned(&mut tx, user_id, &account_number).await?;
+    // sweeping would get the money out of a freeze; checked under the write
+    // lock, so a freeze cannot land between the check and the sweep
+    if fetch_account(&mut tx, &account_number).await?.status == AccountStatus::Frozen {
+        return Err(AppError::Conflict(format!(
+            "Account {} is frozen",
+            account_number
+        )));
+    }
+    let after = change_status_in(
+        &mut tx,
+        &account_number,
+        AccountStatus::Closed,
+        &closure.reason,
+        sweep_to,
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(after)
+}
+
+/// Every account with a balance below zero, deepest overdraft first.
+pub async fn get_overdrawn_accounts(
+    pool: &SqlitePool,
+) -> Result<Vec<models::account::AccountGeneral>, AppError> {
+    tracing::info!("Invocation to `get_overdrawn_accounts`");
+    let accounts: Vec<models::account::AccountGeneral> = sqlx::query_as(&format!(
+        "SELECT {} FROM ACCOUNTS WHERE balance < 0 ORDER BY balance, account_number;",
+        ACCOUNT_COLUMNS
+    ))
+    .fetch_all(pool)
+    .await?;
+    Ok(accounts)
+}
+
+/// The account as it stands in the caller's database transaction.
+async fn fetch_account(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+) -> Result<models::account::AccountGeneral, AppError> {
+    let account: models::account::AccountGeneral = sqlx::query_as(&format!(
+        "SELECT {} FROM ACCOUNTS WHERE account_number = ?;",
+        ACCOUNT_COLUMNS
+    ))
+    .bind(account_number)
+    .fetch_one(conn)
+    .await?;
+    Ok(account)
+}
+
+/// Records a `balance.changed` event with the account's balance and available
+/// balance as they stand in the caller's database transaction. Called after
+/// anything that moves either of them.
+pub(crate) async fn record_balance_changed(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+) -> Result<(), AppError> {
+    let account = fetch_account(&mut *conn, account_number).await?;
+    outbox_service::record(
+        conn,
+        EventType::BalanceChanged,
+        &serde_json::json!({
+            "account_number": account.account_number,
+            "balance": account.balance,
+            "available_balance": account.available_balance,
+        }),
+    )
+    .await?;
+    Ok(())
+}
+
+/// Fails with Conflict unless the account is active, the only status in which
+/// it takes postings.
+pub async fn ensure_active(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+) -> Result<(), AppError> {
+    let status: Option<AccountStatus> =
+        sqlx::query_scalar("SELECT status FROM ACCOUNTS WHERE account_number = ?;")
             .bind(account_number)
-            .bind(user_id.to_string())
-            .bind(String::from_str("0")?)
-            .execute(pool)
+            .fetch_optional(conn)
             .await?;
-    let created = get_account(pool, res.last_insert_rowid()).await?;
-    Ok(created)
+    match status {
+        None => Err(AppError::NotFound(format!(
+            "Account {} not found",
+            account_number
+        ))),
+        Some(AccountStatus::Active) => Ok(()),
+        Some(status) => Err(AppError::Conflict(format!(
+            "Account {} is {}",
+            account_number,
+            status.as_str()
+        ))),
+    }
+}
+
+/// Fails with NotFound unless the account exists and belongs to `user_id`, so
+/// other users' accounts are indistinguishable from missing ones.
+pub async fn ensure_owned(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    account_number: &str,
+) -> Result<(), AppError> {
+    let owned: Option<i64> =
+        sqlx::query_scalar("SELECT id FROM ACCOUNTS WHERE account_number = ? AND user_id = ?;")
+            .bind(account_number)
+            .bind(user_id)
+            .fetch_optional(conn)
+            .await?;
+    owned
+        .map(|_| ())
+        .ok_or_else(|| AppError::NotFound(format!("Account {} not found", account_number)))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::queries;
+    use crate::migrations;
+    use crate::models::money::{Currency, Money};
+    use crate::services::generation_service::IbanScheme;
+    use crate::services::user_service;
 
     use super::*;
 
     async fn setup_db() -> SqlitePool {
         let pool = SqlitePool::connect(":memory:").await.unwrap();
-        sqlx::query(queries::CREATE_TABLE_USER)
-            .execute(&pool)
-            .await
-            .unwrap();
-        sqlx::query(queries::CREATE_TABLE_ACCOUNT)
-            .execute(&pool)
-            .await
-            .unwrap();
+        migrations::run(&pool).await.unwrap();
         pool
     }
 
     #[tokio::test]
     async fn test_get_accounts_empty() {
         let db = setup_db().await;
-        let accounts = get_accounts(&db).await.unwrap();
+        let accounts = get_accounts(&db, 1).await.unwrap();
         assert!(accounts.is_empty());
     }
 
@@ -97,12 +532,40 @@ mod tests {
         )
         .await
         .unwrap();
-        let account_creation = models::account::AccountCreation { user_id: 1 };
-        let _ = create_account(&db, account_creation.clone()).await.unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let _ = create_account(&db, &Default::default(), 1, account_creation.clone())
+            .await
+            .unwrap();
 
-        let accounts = get_accounts(&db).await.unwrap();
+        let accounts = get_accounts(&db, 1).await.unwrap();
         assert_eq!(accounts.len(), 1);
         assert_eq!(accounts[0].user_id, 1);
+        assert_eq!(accounts[0].balance, Money::zero(Currency::Usd));
+    }
+
+    #[tokio::test]
+    async fn test_create_account_with_currency() {
+        let db = setup_db().await;
+        let _ = user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Eur,
+            product: None,
+        };
+        let account = create_account(&db, &Default::default(), 1, account_creation)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, Money::zero(Currency::Eur));
     }
 
     #[tokio::test]
@@ -135,16 +598,20 @@ mod tests {
         )
         .await
         .unwrap();
-        let users = vec![1, 2, 3];
+        let users: Vec<i64> = vec![1, 2, 3];
         for user in &users {
-            let account_creation = models::account::AccountCreation { user_id: *user };
-            let res = create_account(&db, account_creation).await;
+            let account_creation = models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            };
+            let res = create_account(&db, &Default::default(), *user, account_creation).await;
             assert!(res.is_ok())
         }
-        let accounts = get_accounts(&db).await.unwrap();
-        assert_eq!(accounts.len(), users.len());
-        for (i, account) in accounts.iter().enumerate() {
-            assert_eq!(account.user_id, users[i]);
+        for user in &users {
+            // each user only sees their own account
+            let accounts = get_accounts(&db, *user).await.unwrap();
+            assert_eq!(accounts.len(), 1);
+            assert_eq!(accounts[0].user_id as i64, *user);
         }
     }
     #[tokio::test]
@@ -159,17 +626,414 @@ mod tests {
         )
         .await
         .unwrap();
-        let account_creation = models::account::AccountCreation { user_id: 1 };
-        let _ = create_account(&db, account_creation.clone()).await.unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let _ = create_account(&db, &Default::default(), 1, account_creation.clone())
+            .await
+            .unwrap();
 
         // Try to create another account with the same user_id
-        let result = create_account(&db, account_creation.clone()).await;
+        let result = create_account(&db, &Default::default(), 1, account_creation.clone()).await;
         // Should succeed because account_number is unique, not user_id
         assert!(result.is_ok());
 
-        let accounts = get_accounts(&db).await.unwrap();
+        let accounts = get_accounts(&db, 1).await.unwrap();
         // There should be two accounts with the same user_id
         let count = accounts.iter().filter(|a| a.user_id == 1).count();
         assert_eq!(count, 2);
     }
+
+    #[tokio::test]
+    async fn test_create_account_for_missing_user() {
+        let db = setup_db().await;
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let result = create_account(&db, &Default::default(), 7, account_creation).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_account_by_account_number_not_found() {
+        let db = setup_db().await;
+        let result =
+            get_account_by_account_number(&db, AccountNumberScheme::default().generate()).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+        // malformed numbers never reach the database
+        let result = get_account_by_account_number(&db, "0000".to_string()).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_account_retries_then_gives_up_on_collisions() {
+        let db = setup_db().await;
+        user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        // no random digits, so every attempt draws the same number
+        let scheme = AccountNumberScheme::unchecked("10000000000", 12);
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let first = create_account(&db, &scheme, 1, account_creation.clone())
+            .await
+            .unwrap();
+        generation_service::validate_account_number(&first.account_number).unwrap();
+        let second = create_account(&db, &scheme, 1, account_creation).await;
+        assert!(matches!(second, Err(AppError::Internal(_))));
+        assert_eq!(get_accounts(&db, 1).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_accounts_get_ibans_that_resolve() {
+        let db = setup_db().await;
+        user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account = create_account(
+            &db,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Eur,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        let iban = account.iban.clone().unwrap();
+        generation_service::validate_iban(&iban).unwrap();
+        assert!(iban.ends_with(&account.account_number));
+
+        let lookup = get_iban(&db, &iban.to_lowercase()).await.unwrap();
+        assert_eq!(lookup.account_number, account.account_number);
+        assert_eq!(lookup.currency, Currency::Eur);
+        // printed form, with spaces, is accepted anywhere an account number is
+        let spaced: String = iban
+            .as_bytes()
+            .chunks(4)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect::<Vec<_>>()
+            .join(" ");
+        let by_iban = get_account_by_account_number(&db, spaced).await.unwrap();
+        assert_eq!(by_iban, account);
+
+        let other = IbanScheme::default()
+            .derive(&AccountNumberScheme::default().generate())
+            .unwrap();
+        assert!(matches!(
+            get_iban(&db, &other).await,
+            Err(AppError::NotFound(_))
+        ));
+        let mut typo = iban.into_bytes();
+        let last = typo.len() - 1;
+        typo[last] = if typo[last] == b'9' {
+            b'0'
+        } else {
+            typo[last] + 1
+        };
+        let typo = String::from_utf8(typo).unwrap();
+        assert!(matches!(
+            get_iban(&db, &typo).await,
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_assign_missing_ibans() {
+        let db = setup_db().await;
+        let scheme = AccountNumberScheme::default();
+        let account_number = scheme.generate();
+        sqlx::raw_sql(&format!(
+            "INSERT INTO USERS (username, password) VALUES ('alice', 'x');
+             INSERT INTO ACCOUNTS (account_number, user_id, balance) VALUES ('{}', 1, 0);",
+            account_number
+        ))
+        .execute(&db)
+        .await
+        .unwrap();
+        assert_eq!(assign_missing_ibans(&db, &scheme).await.unwrap(), 1);
+        assert_eq!(assign_missing_ibans(&db, &scheme).await.unwrap(), 0);
+        let account = get_account_by_account_number(&db, account_number.clone())
+            .await
+            .unwrap();
+        assert_eq!(
+            account.iban,
+            Some(scheme.iban().derive(&account_number).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_overdraft_and_list_overdrawn() {
+        let pool = setup_db().await;
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account = create_account(
+            &pool,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        let usd = |minor| Money::new(minor, Currency::Usd);
+        for (limit, fee) in [
+            (usd(-1), None),
+            (usd(1_000), Some(usd(-1))),
+            (Money::new(1_000, Currency::Eur), None),
+        ] {
+            let res = set_overdraft(
+                &pool,
+                &account.account_number,
+                models::account::OverdraftSetting { limit, fee },
+            )
+            .await;
+            assert!(matches!(res, Err(AppError::Validation(_))));
+        }
+        let updated = set_overdraft(
+            &pool,
+            &account.account_number,
+            models::account::OverdraftSetting {
+                limit: usd(1_000),
+                fee: None,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.overdraft_limit, usd(1_000));
+        assert_eq!(updated.overdraft_fee, usd(0));
+        assert_eq!(updated.available_balance, usd(1_000));
+        assert!(get_overdrawn_accounts(&pool).await.unwrap().is_empty());
+
+        sqlx::query("UPDATE ACCOUNTS SET balance = -250 WHERE account_number = ?;")
+            .bind(&account.account_number)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let overdrawn = get_overdrawn_accounts(&pool).await.unwrap();
+        assert_eq!(overdrawn.len(), 1);
+        assert_eq!(overdrawn[0].balance, usd(-250));
+    }
+
+    async fn setup_owners() -> (SqlitePool, String, String) {
+        let pool = setup_db().await;
+        for username in ["alice", "bob"] {
+            user_service::create_user(
+                &pool,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let mut accounts = Vec::new();
+        for user_id in [1, 2] {
+            let account = create_account(
+                &pool,
+                &Default::default(),
+                user_id,
+                models::account::AccountCreation {
+                    currency: Currency::Usd,
+                    product: None,
+                },
+            )
+            .await
+            .unwrap();
+            accounts.push(account.account_number);
+        }
+        let bob = accounts.pop().unwrap();
+        let alice = accounts.pop().unwrap();
+        (pool, alice, bob)
+    }
+
+    async fn deposit(pool: &SqlitePool, account_number: &str, minor: i64) -> Result<(), AppError> {
+        transaction_service::create_transaction(
+            pool,
+            1,
+            models::transaction::TransactionCreation {
+                account_number: account_number.to_string(),
+                seller: "Employer".to_string(),
+                amount: Money::new(-minor, Currency::Usd),
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
+    fn change(status: AccountStatus, sweep_to: Option<&str>) -> models::account::StatusChange {
+        models::account::StatusChange {
+            status,
+            reason: "requested".to_string(),
+            sweep_to: sweep_to.map(str::to_string),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_active_accounts_take_postings() {
+        let (pool, alice, bob) = setup_owners().await;
+        deposit(&pool, &alice, 1_000).await.unwrap();
+        let frozen = change_status(&pool, &alice, change(AccountStatus::Frozen, None))
+            .await
+            .unwrap();
+        assert_eq!(frozen.status, AccountStatus::Frozen);
+        assert_eq!(frozen.status_reason.as_deref(), Some("requested"));
+        assert!(matches!(
+            deposit(&pool, &alice, 1_000).await,
+            Err(AppError::Conflict(_))
+        ));
+        // nor can money be moved in from elsewhere
+        let res = transfer_service::create_transfer(
+            &pool,
+            2,
+            models::transfer::TransferCreation {
+                source_account_number: bob.clone(),
+                destination_account_number: alice.clone(),
+                amount: Money::new(100, Currency::Usd),
+                rate: None,
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+        // frozen accounts go back to active or are closed, nothing else
+        let res = change_status(&pool, &alice, change(AccountStatus::Dormant, None)).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+        let res = change_status(
+            &pool,
+            &alice,
+            models::account::StatusChange {
+                reason: " ".to_string(),
+                ..change(AccountStatus::Active, None)
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+
+        change_status(&pool, &alice, change(AccountStatus::Active, None))
+            .await
+            .unwrap();
+        deposit(&pool, &alice, 1_000).await.unwrap();
+        let account = get_account_by_account_number(&pool, alice).await.unwrap();
+        assert_eq!(account.balance, Money::new(2_000, Currency::Usd));
+    }
+
+    #[tokio::test]
+    async fn test_closing_sweeps_the_balance() {
+        let (pool, alice, bob) = setup_owners().await;
+        deposit(&pool, &alice, 1_500).await.unwrap();
+        let close = |sweep_to: Option<&str>| {
+            close_account(
+                &pool,
+                1,
+                &alice,
+                models::account::AccountClosure {
+                    reason: "moving banks".to_string(),
+                    sweep_to: sweep_to.map(str::to_string),
+                },
+            )
+        };
+        assert!(matches!(close(None).await, Err(AppError::Conflict(_))));
+        assert!(matches!(
+            close(Some(&alice)).await,
+            Err(AppError::Validation(_))
+        ));
+        // only the owner closes through this route
+        let res = close_account(
+            &pool,
+            2,
+            &alice,
+            models::account::AccountClosure {
+                reason: "not mine".to_string(),
+                sweep_to: Some(bob.clone()),
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+        change_status(&pool, &alice, change(AccountStatus::Frozen, None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            close(Some(&bob)).await,
+            Err(AppError::Conflict(_))
+        ));
+        change_status(&pool, &alice, change(AccountStatus::Active, None))
+            .await
+            .unwrap();
+        change_status(&pool, &bob, change(AccountStatus::Dormant, None))
+            .await
+            .unwrap();
+        assert!(matches!(
+            close(Some(&bob)).await,
+            Err(AppError::Conflict(_))
+        ));
+        change_status(&pool, &bob, change(AccountStatus::Active, None))
+            .await
+            .unwrap();
+
+        let closed = close(Some(&bob)).await.unwrap();
+        assert_eq!(closed.status, AccountStatus::Closed);
+        assert_eq!(closed.balance, Money::zero(Currency::Usd));
+        let swept = get_account_by_account_number(&pool, bob).await.unwrap();
+        assert_eq!(swept.balance, Money::new(1_500, Currency::Usd));
+        // closed is final
+        for status in [AccountStatus::Active, AccountStatus::Frozen] {
+            let res = change_status(&pool, &alice, change(status, None)).await;
+            assert!(matches!(res, Err(AppError::Conflict(_))));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_closing_needs_no_holds_and_no_overdraft() {
+        let (pool, alice, bob) = setup_owners().await;
+        deposit(&pool, &alice, 1_000).await.unwrap();
+        crate::services::hold_service::authorize(
+            &pool,
+            1,
+            models::hold::HoldCreation {
+                account_number: alice.clone(),
+                seller: "Hotel".to_string(),
+                amount: Money::new(400, Currency::Usd),
+                expires_in_secs: None,
+            },
+        )
+        .await
+        .unwrap();
+        let res = change_status(&pool, &alice, change(AccountStatus::Closed, Some(&bob))).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+
+        sqlx::query("UPDATE ACCOUNTS SET balance = -250 WHERE account_number = ?;")
+            .bind(&bob)
+            .execute(&pool)
+            .await
+            .unwrap();
+        let res = change_status(&pool, &bob, change(AccountStatus::Closed, None)).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+    }
 }
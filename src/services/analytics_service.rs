@@ -0,0 +1,685 @@
+use chrono::NaiveDate;
+use sqlx::{QueryBuilder, Row, Sqlite, SqlitePool};
+
+use crate::error::AppError;
+use crate::models::analytics::{
+    AnalyticsQuery, CashFlow, Interval, PeriodCashFlow, PeriodComparison, SpendingGroup,
+    SpendingGroupBy,
+};
+use crate::models::money::{Currency, Money};
+use crate::services::account_service;
+
+pub const DEFAULT_GROUP_LIMIT: u32 = 50;
+pub const MAX_GROUP_LIMIT: u32 = 500;
+pub const DEFAULT_TOP_MERCHANTS: u32 = 5;
+
+/// Whether a transaction is money going out: a debit, or the refund of one,
+/// which counts against what it refunds rather than as income.
+const OUTGOING: &str = "COALESCE(o.amount, t.amount) > 0";
+
+/// What the aggregates are taken over, checked once per request.
+struct Scope {
+    user_id: i64,
+    account_number: Option<String>,
+    currency: Currency,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+impl Scope {
+    async fn of(db: &SqlitePool, user_id: i64, query: &AnalyticsQuery) -> Result<Self, AppError> {
+        if let (Some(from), Some(to)) = (query.from, query.to)
+            && from > to
+        {
+            return Err(AppError::Validation("`from` is after `to`".to_string()));
+        }
+        let (account_number, currency) = match &query.account_number {
+            Some(input) => {
+                let account_number = account_service::resolve_account_number(db, input).await?;
+                let mut conn = db.acquire().await?;
+                account_service::ensure_owned(&mut conn, user_id, &account_number).await?;
+                let currency: Currency =
+                    sqlx::query_scalar("SELECT currency FROM ACCOUNTS WHERE account_number = ?;")
+                        .bind(&account_number)
+                        .fetch_one(&mut *conn)
+                        .await?;
+                (Some(account_number), query.currency.unwrap_or(currency))
+            }
+            None => (None, query.currency.unwrap_or_default()),
+        };
+        Ok(Scope {
+            user_id,
+            account_number,
+            currency,
+            from: query.from,
+            to: query.to,
+        })
+    }
+
+    /// Pushes the FROM and WHERE clauses. Across all of a user's accounts,
+    /// transfers between two of them are left out, being neither income nor
+    /// spending.
+    fn push(&self, builder: &mut QueryBuilder<Sqlite>) -> Result<(), AppError> {
+        builder
+            .push(
+                " FROM TRANSACTIONS t JOIN ACCOUNTS a ON a.account_number = t.account_number
+                 LEFT JOIN TRANSACTIONS o ON o.id = t.original_transaction_id
+                 LEFT JOIN MERCHANTS m ON m.id = t.merchant_id
+                 WHERE a.user_id = ",
+            )
+            .push_bind(self.user_id)
+            .push(" AND t.currency = ")
+            .push_bind(self.currency);
+        match &self.account_number {
+            Some(account_number) => {
+                builder
+                    .push(" AND t.account_number = ")
+                    .push_bind(account_number.clone());
+            }
+            None => {
+                builder.push(
+                    " AND NOT EXISTS (SELECT 1 FROM TRANSFERS tr
+                     JOIN ACCOUNTS s ON s.account_number = tr.source_account_number
+                     JOIN ACCOUNTS d ON d.account_number = tr.destination_account_number
+                     WHERE tr.id = t.transfer_id AND s.user_id = d.user_id)",
+                );
+            }
+        }
+        if let Some(from) = self.from {
+            builder
+                .push(" AND t.created_at >= ")
+                .push_bind(from.format("%Y-%m-%d").to_string());
+        }
+        if let Some(to) = self.to {
+            let end = to
+                .succ_opt()
+                .ok_or_else(|| AppError::Validation("Invalid `to` date".to_string()))?;
+            builder
+                .push(" AND t.created_at < ")
+                .push_bind(end.format("%Y-%m-%d").to_string());
+        }
+        Ok(())
+    }
+}
+
+fn push_flow_columns(builder: &mut QueryBuilder<Sqlite>) {
+    builder.push(format!(
+        "COALESCE(SUM(CASE WHEN {0} THEN t.amount ELSE 0 END), 0) AS spent,
+         COALESCE(SUM(CASE WHEN {0} THEN 0 ELSE -t.amount END), 0) AS income,
+         COUNT(t.id) AS transactions",
+        OUTGOING
+    ));
+}
+
+async fn cash_flow_in(db: &SqlitePool, scope: &Scope) -> Result<CashFlow, AppError> {
+    let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT ");
+    push_flow_columns(&mut builder);
+    scope.push(&mut builder)?;
+    let row = builder.build().fetch_one(db).await?;
+    let income = Money::new(row.try_get("income")?, scope.currency);
+    let spent = Money::new(row.try_get("spent")?, scope.currency);
+    Ok(CashFlow {
+        from: scope.from,
+        to: scope.to,
+        income,
+        spent,
+        net: income.checked_sub(spent)?,
+        transactions: row.try_get("transactions")?,
+    })
+}
+
+/// Income and spending over the queried range.
+pub async fn get_cash_flow(
+    db: &SqlitePool,
+    user_id: i64,
+    query: &AnalyticsQuery,
+) -> Result<CashFlow, AppError> {
+    tracing::info!("Invocation to `get_cash_flow`");
+    let scope = Scope::of(db, user_id, query).await?;
+    cash_flow_in(db, &scope).await
+}
+
+/// Income and spending per day, week or month, oldest first. Periods without
+/// transactions are left out.
+pub async fn get_timeline(
+    db: &SqlitePool,
+    user_id: i64,
+    query: &AnalyticsQuery,
+) -> Result<Vec<PeriodCashFlow>, AppError> {
+    tracing::info!("Invocation to `get_timeline`");
+    let scope = Scope::of(db, user_id, query).await?;
+    let period = match query.interval {
+        Interval::Day => "date(t.created_at)",
+        Interval::Week => "date(t.created_at, 'weekday 0', '-6 days')",
+        Interval::Month => "date(t.created_at, 'start of month')",
+    };
+    let mut builder: QueryBuilder<Sqlite> =
+        QueryBuilder::new(format!("SELECT {} AS period_start, ", period));
+    push_flow_columns(&mut builder);
+    scope.push(&mut builder)?;
+    builder.push(" GROUP BY period_start ORDER BY period_start");
+    let rows = builder.build().fetch_all(db).await?;
+    rows.iter()
+        .map(|row| {
+            let income = Money::new(row.try_get("income")?, scope.currency);
+            let spent = Money::new(row.try_get("spent")?, scope.currency);
+            Ok(PeriodCashFlow {
+                period_start: row.try_get("period_start")?,
+                income,
+                spent,
+                net: income.checked_sub(spent)?,
+                transactions: row.try_get("transactions")?,
+            })
+        })
+        .collect()
+}
+
+async fn spending_in(
+    db: &SqlitePool,
+    scope: &Scope,
+    group_by: SpendingGroupBy,
+    matched_only: bool,
+    limit: u32,
+) -> Result<Vec<SpendingGroup>, AppError> {
+    let (columns, group) = match group_by {
+        SpendingGroupBy::Merchant => ("m.id AS merchant_id, m.name, m.category", "t.merchant_id"),
+        SpendingGroupBy::Category => (
+            "NULL AS merchant_id, NULL AS name, m.category",
+            "m.category",
+        ),
+    };
+    let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        "SELECT {}, SUM(t.amount) AS spent, COUNT(t.id) AS transactions",
+        columns
+    ));
+    scope.push(&mut builder)?;
+    builder.push(format!(" AND {}", OUTGOING));
+    if matched_only {
+        builder.push(" AND t.merchant_id IS NOT NULL");
+    }
+    builder.push(format!(
+        " GROUP BY {} ORDER BY spent DESC, {} LIMIT ",
+        group, group
+    ));
+    builder.push_bind(limit as i64);
+    let rows = builder.build().fetch_all(db).await?;
+    rows.iter()
+        .map(|row| {
+            Ok(SpendingGroup {
+                merchant_id: row.try_get("merchant_id")?,
+                name: row.try_get("name")?,
+                category: row.try_get("category")?,
+                spent: Money::new(row.try_get("spent")?, scope.currency),
+                transactions: row.try_get("transactions")?,
+            })
+        })
+        .collect()
+}
+
+/// Spending per merchant or merchant category, largest first. Payments no
+/// merchant matched are one group with neither.
+pub async fn get_spending(
+    db: &SqlitePool,
+    user_id: i64,
+    query: &AnalyticsQuery,
+) -> Result<Vec<SpendingGroup>, AppError> {
+    tracing::info!("Invocation to `get_spending`");
+    let scope = Scope::of(db, user_id, query).await?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_GROUP_LIMIT)
+        .clamp(1, MAX_GROUP_LIMIT);
+    spending_in(db, &scope, query.group_by, false, limit).await
+}
+
+/// The merchants most was spent with.
+pub async fn get_top_merchants(
+    db: &SqlitePool,
+    user_id: i64,
+    query: &AnalyticsQuery,
+) -> Result<Vec<SpendingGroup>, AppError> {
+    tracing::info!("Invocation to `get_top_merchants`");
+    let scope = Scope::of(db, user_id, query).await?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_TOP_MERCHANTS)
+        .clamp(1, MAX_GROUP_LIMIT);
+    spending_in(db, &scope, SpendingGroupBy::Merchant, true, limit).await
+}
+
+/// Compares the queried range, which needs both ends, with the range of the
+/// same number of days just before it.
+pub async fn get_comparison(
+    db: &SqlitePool,
+    user_id: i64,
+    query: &AnalyticsQuery,
+) -> Result<PeriodComparison, AppError> {
+    tracing::info!("Invocation to `get_comparison`");
+    let (Some(from), Some(to)) = (query.from, query.to) else {
+        return Err(AppError::Validation(
+            "A comparison needs both `from` and `to`".to_string(),
+        ));
+    };
+    let scope = Scope::of(db, user_id, query).await?;
+    let days = (to - from).num_days() + 1;
+    let previous_to = from
+        .pred_opt()
+        .ok_or_else(|| AppError::Validation("Invalid `from` date".to_string()))?;
+    let previous = Scope {
+        from: Some(previous_to - chrono::Duration::days(days - 1)),
+        to: Some(previous_to),
+        account_number: scope.account_number.clone(),
+        ..scope
+    };
+    let current = cash_flow_in(db, &scope).await?;
+    let previous = cash_flow_in(db, &previous).await?;
+    let spent_change = current.spent.checked_sub(previous.spent)?;
+    // to a tenth of a percent, rounded toward zero
+    let spent_change_percent = (previous.spent.is_positive()).then(|| {
+        let tenths =
+            spent_change.minor_units() as i128 * 1000 / previous.spent.minor_units() as i128;
+        let sign = if tenths < 0 { "-" } else { "" };
+        format!("{}{}.{}", sign, tenths.abs() / 10, tenths.abs() % 10)
+    });
+    Ok(PeriodComparison {
+        income_change: current.income.checked_sub(previous.income)?,
+        spent_change,
+        spent_change_percent,
+        current,
+        previous,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models;
+    use crate::models::merchant::MerchantCreation;
+    use crate::models::transaction::{RefundCreation, TransactionCreation};
+    use crate::models::transfer::TransferCreation;
+    use crate::services::{merchant_service, transaction_service, transfer_service, user_service};
+
+    use super::*;
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::Usd)
+    }
+
+    async fn open(db: &SqlitePool, user_id: i64, currency: Currency) -> String {
+        account_service::create_account(
+            db,
+            &Default::default(),
+            user_id,
+            models::account::AccountCreation {
+                currency,
+                product: None,
+            },
+        )
+        .await
+        .unwrap()
+        .account_number
+    }
+
+    async fn pay(
+        db: &SqlitePool,
+        user_id: i64,
+        account: &str,
+        seller: &str,
+        amount: Money,
+        at: &str,
+    ) -> i64 {
+        let id = transaction_service::create_transaction(
+            db,
+            user_id,
+            TransactionCreation {
+                account_number: account.to_string(),
+                seller: seller.to_string(),
+                amount,
+            },
+        )
+        .await
+        .unwrap()
+        .id;
+        backdate(db, "id", id, at).await;
+        id
+    }
+
+    async fn backdate(db: &SqlitePool, column: &str, id: i64, at: &str) {
+        sqlx::query(&format!(
+            "UPDATE TRANSACTIONS SET created_at = ? WHERE {} = ?;",
+            column
+        ))
+        .bind(at)
+        .bind(id)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    /// Alice's checking account gets paid and spends at Amazon (partly
+    /// refunded), a grocer and an unknown cafe, then moves money to savings.
+    async fn setup_db() -> (SqlitePool, String, String) {
+        let db = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&db).await.unwrap();
+        for username in ["alice", "bob"] {
+            user_service::create_user(
+                &db,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        for (name, category) in [("Amazon", "5942"), ("Grocer", "5411")] {
+            merchant_service::create_merchant(
+                &db,
+                MerchantCreation {
+                    name: name.to_string(),
+                    category: category.to_string(),
+                    aliases: vec![],
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let checking = open(&db, 1, Currency::Usd).await;
+        let savings = open(&db, 1, Currency::Usd).await;
+        let euros = open(&db, 1, Currency::Eur).await;
+        let bobs = open(&db, 2, Currency::Usd).await;
+
+        pay(
+            &db,
+            1,
+            &checking,
+            "Employer",
+            usd(-100_000),
+            "2026-01-05 09:00:00",
+        )
+        .await;
+        let january = pay(
+            &db,
+            1,
+            &checking,
+            "AMAZON.COM",
+            usd(2_000),
+            "2026-01-10 12:00:00",
+        )
+        .await;
+        pay(
+            &db,
+            1,
+            &checking,
+            "Amazon",
+            usd(3_000),
+            "2026-02-03 12:00:00",
+        )
+        .await;
+        let refund = transaction_service::refund_transaction(
+            &db,
+            1,
+            january,
+            RefundCreation {
+                amount: Some(usd(1_000)),
+            },
+        )
+        .await
+        .unwrap();
+        backdate(&db, "id", refund.id.unwrap() as i64, "2026-02-04 12:00:00").await;
+        pay(
+            &db,
+            1,
+            &checking,
+            "Grocer",
+            usd(1_500),
+            "2026-02-10 18:00:00",
+        )
+        .await;
+        pay(
+            &db,
+            1,
+            &checking,
+            "Corner Cafe",
+            usd(500),
+            "2026-02-11 08:00:00",
+        )
+        .await;
+        let transfer = transfer_service::create_transfer(
+            &db,
+            1,
+            TransferCreation {
+                source_account_number: checking.clone(),
+                destination_account_number: savings,
+                amount: usd(10_000),
+                rate: None,
+            },
+        )
+        .await
+        .unwrap();
+        backdate(&db, "transfer_id", transfer.id, "2026-02-12 10:00:00").await;
+        pay(
+            &db,
+            1,
+            &euros,
+            "Employer",
+            Money::new(-5_000, Currency::Eur),
+            "2026-02-01 09:00:00",
+        )
+        .await;
+        pay(
+            &db,
+            2,
+            &bobs,
+            "Employer",
+            usd(-7_000),
+            "2026-02-01 09:00:00",
+        )
+        .await;
+        pay(&db, 2, &bobs, "Amazon", usd(700), "2026-02-02 09:00:00").await;
+        (db, checking, euros)
+    }
+
+    fn spent(groups: &[SpendingGroup]) -> Vec<(Option<String>, Money, i64)> {
+        groups
+            .iter()
+            .map(|group| (group.category.clone(), group.spent, group.transactions))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_cash_flow_leaves_out_internal_transfers() {
+        let (db, checking, euros_account) = setup_db().await;
+        let flow = get_cash_flow(&db, 1, &AnalyticsQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(flow.income, usd(100_000));
+        // refunds count against spending
+        assert_eq!(flow.spent, usd(6_000));
+        assert_eq!(flow.net, usd(94_000));
+        assert_eq!(flow.transactions, 6);
+
+        // seen from one account, the transfer is money going out
+        let query = AnalyticsQuery {
+            account_number: Some(checking.clone()),
+            ..Default::default()
+        };
+        let flow = get_cash_flow(&db, 1, &query).await.unwrap();
+        assert_eq!(flow.spent, usd(16_000));
+        assert_eq!(flow.transactions, 7);
+        let res = get_cash_flow(&db, 2, &query).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+
+        let euros = get_cash_flow(
+            &db,
+            1,
+            &AnalyticsQuery {
+                currency: Some(Currency::Eur),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(euros.income, Money::new(5_000, Currency::Eur));
+        assert_eq!(euros.spent, Money::zero(Currency::Eur));
+        let by_account = get_cash_flow(
+            &db,
+            1,
+            &AnalyticsQuery {
+                account_number: Some(euros_account),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(by_account.income, euros.income);
+    }
+
+    #[tokio::test]
+    async fn test_timeline_groups_by_period() {
+        let (db, _, _) = setup_db().await;
+        let date = |m, d| NaiveDate::from_ymd_opt(2026, m, d).unwrap();
+        let months = get_timeline(&db, 1, &AnalyticsQuery::default())
+            .await
+            .unwrap();
+        let totals: Vec<(NaiveDate, Money, Money, i64)> = months
+            .iter()
+            .map(|p| (p.period_start, p.income, p.spent, p.transactions))
+            .collect();
+        assert_eq!(
+            totals,
+            vec![
+                (date(1, 1), usd(100_000), usd(2_000), 2),
+                (date(2, 1), usd(0), usd(4_000), 4),
+            ]
+        );
+        let weeks = get_timeline(
+            &db,
+            1,
+            &AnalyticsQuery {
+                interval: Interval::Week,
+                from: Some(date(2, 1)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let starts: Vec<(NaiveDate, Money)> =
+            weeks.iter().map(|p| (p.period_start, p.spent)).collect();
+        // Mondays; the 3rd and 4th fall in the week of the 2nd
+        assert_eq!(
+            starts,
+            vec![(date(2, 2), usd(2_000)), (date(2, 9), usd(2_000))]
+        );
+        let days = get_timeline(
+            &db,
+            1,
+            &AnalyticsQuery {
+                interval: Interval::Day,
+                from: Some(date(2, 10)),
+                to: Some(date(2, 10)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!(days[0].spent, usd(1_500));
+    }
+
+    #[tokio::test]
+    async fn test_spending_by_merchant_and_category() {
+        let (db, _, _) = setup_db().await;
+        let by_merchant = get_spending(&db, 1, &AnalyticsQuery::default())
+            .await
+            .unwrap();
+        let names: Vec<Option<&str>> = by_merchant.iter().map(|g| g.name.as_deref()).collect();
+        assert_eq!(names, vec![Some("Amazon"), Some("Grocer"), None]);
+        assert_eq!(
+            spent(&by_merchant),
+            vec![
+                (Some("5942".to_string()), usd(4_000), 3),
+                (Some("5411".to_string()), usd(1_500), 1),
+                (None, usd(500), 1),
+            ]
+        );
+        let by_category = get_spending(
+            &db,
+            1,
+            &AnalyticsQuery {
+                group_by: SpendingGroupBy::Category,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(spent(&by_category), spent(&by_merchant));
+        assert!(by_category.iter().all(|g| g.merchant_id.is_none()));
+
+        let top = get_top_merchants(
+            &db,
+            1,
+            &AnalyticsQuery {
+                limit: Some(1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].name.as_deref(), Some("Amazon"));
+        // bob's own spending
+        let top = get_top_merchants(&db, 2, &AnalyticsQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(spent(&top), vec![(Some("5942".to_string()), usd(700), 1)]);
+    }
+
+    #[tokio::test]
+    async fn test_compares_with_the_period_before() {
+        let (db, _, _) = setup_db().await;
+        let date = |m, d| NaiveDate::from_ymd_opt(2026, m, d).unwrap();
+        let comparison = get_comparison(
+            &db,
+            1,
+            &AnalyticsQuery {
+                from: Some(date(2, 1)),
+                to: Some(date(2, 28)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        // the 28 days before February
+        assert_eq!(comparison.previous.from, Some(date(1, 4)));
+        assert_eq!(comparison.previous.to, Some(date(1, 31)));
+        assert_eq!(comparison.previous.spent, usd(2_000));
+        assert_eq!(comparison.current.spent, usd(4_000));
+        assert_eq!(comparison.spent_change, usd(2_000));
+        assert_eq!(comparison.spent_change_percent.as_deref(), Some("100.0"));
+        assert_eq!(comparison.income_change, usd(-100_000));
+
+        let res = get_comparison(
+            &db,
+            1,
+            &AnalyticsQuery {
+                from: Some(date(2, 1)),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+        let res = get_comparison(
+            &db,
+            1,
+            &AnalyticsQuery {
+                from: Some(date(1, 1)),
+                to: Some(date(1, 3)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(res.spent_change_percent, None);
+    }
+}
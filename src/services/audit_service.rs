@@ -0,0 +1,624 @@
+use std::future::Future;
+
+use serde::Serialize;
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool};
+
+use crate::error::AppError;
+use crate::models::audit::{AuditAction, AuditEntry, AuditPage, AuditQuery};
+
+const DEFAULT_PAGE_SIZE: u32 = 50;
+const MAX_PAGE_SIZE: u32 = 500;
+
+/// Who is making the current change and as part of which request. Set once
+/// per request by the `request_context` middleware and per run by background
+/// workers, so services can audit without threading it through every call.
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct AuditContext {
+    pub actor_user_id: Option<i64>, // None for anonymous requests and the system
+    pub request_id: Option<String>,
+}
+
+tokio::task_local! {
+    static CONTEXT: AuditContext;
+}
+
+/// Runs `future` with `context` as the audit context of every change it makes.
+pub async fn scope<F: Future>(context: AuditContext, future: F) -> F::Output {
+    CONTEXT.scope(context, future).await
+}
+
+/// The audit context of the running task; empty outside of any `scope`, as for
+/// changes the server makes on its own at startup.
+pub fn current() -> AuditContext {
+    CONTEXT.try_with(Clone::clone).unwrap_or_default()
+}
+
+/// Appends an entry to the audit log inside the caller's database transaction,
+/// so it exists if and only if the change is committed. `before` is `None` for
+/// creations.
+pub async fn record<T: Serialize>(
+    conn: &mut SqliteConnection,
+    action: AuditAction,
+    entity_id: impl ToString,
+    before: Option<&T>,
+    after: Option<&T>,
+) -> Result<i64, AppError> {
+    let snapshot = |value: Option<&T>| {
+        value
+            .map(serde_json::to_string)
+            .transpose()
+            .map_err(|err| AppError::Internal(format!("Could not serialize snapshot: {}", err)))
+    };
+    let context = current();
+    let id = sqlx::query(
+        "INSERT INTO AUDIT_LOG (actor_user_id, action, entity_type, entity_id, before, after, request_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?);",
+    )
+    .bind(context.actor_user_id)
+    .bind(action)
+    .bind(action.entity_type())
+    .bind(entity_id.to_string())
+    .bind(snapshot(before)?)
+    .bind(snapshot(after)?)
+    .bind(context.request_id)
+    .execute(conn)
+    .await?
+    .last_insert_rowid();
+    Ok(id)
+}
+
+/// The audit log, newest first, narrowed by every filter `query` sets.
+pub async fn get_audit_log(db: &SqlitePool, query: &AuditQuery) -> Result<AuditPage, AppError> {
+    tracing::info!("Invocation to `get_audit_log`");
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
+    {
+        return Err(AppError::Validation("`from` is after `to`".to_string()));
+    }
+    let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT id, actor_user_id, action, entity_type, entity_id, before, after, request_id, created_at
+         FROM AUDIT_LOG WHERE 1 = 1",
+    );
+    if let Some(actor_user_id) = query.actor_user_id {
+        builder
+            .push(" AND actor_user_id = ")
+            .push_bind(actor_user_id);
+    }
+    if let Some(action) = query.action {
+        builder.push(" AND action = ").push_bind(action);
+    }
+    if let Some(entity_type) = &query.entity_type {
+        builder
+            .push(" AND entity_type = ")
+            .push_bind(entity_type.clone());
+    }
+    if let Some(entity_id) = &query.entity_id {
+        builder
+            .push(" AND entity_id = ")
+            .push_bind(entity_id.clone());
+    }
+    if let Some(request_id) = &query.request_id {
+        builder
+            .push(" AND request_id = ")
+            .push_bind(request_id.clone());
+    }
+    if let Some(from) = query.from {
+        builder
+            .push(" AND created_at >= ")
+            .push_bind(from.format("%Y-%m-%d").to_string());
+    }
+    if let Some(to) = query.to {
+        let end = to
+            .succ_opt()
+            .ok_or_else(|| AppError::Validation("Invalid `to` date".to_string()))?;
+        builder
+            .push(" AND created_at < ")
+            .push_bind(end.format("%Y-%m-%d").to_string());
+    }
+    if let Some(cursor) = query.cursor {
+        builder.push(" AND id < ").push_bind(cursor);
+    }
+    // one extra row tells us whether there is a next page
+    builder
+        .push(" ORDER BY id DESC LIMIT ")
+        .push_bind(limit as i64 + 1);
+
+    let mut items: Vec<AuditEntry> = builder.build_query_as().fetch_all(db).await?;
+    let next_cursor = if items.len() > limit as usize {
+        items.truncate(limit as usize);
+        items.last().map(|entry| entry.id)
+    } else {
+        None
+    };
+    Ok(AuditPage { items, next_cursor })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models;
+    use crate::models::fee::{FeeKind, FeeRuleCreation};
+    use crate::models::money::{Currency, Money};
+    use crate::models::transaction::{RefundCreation, TransactionCreation};
+    use crate::services::{
+        account_service, fee_service, hold_service, merchant_service, transaction_service,
+        transfer_service, user_service,
+    };
+
+    use super::*;
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::Usd)
+    }
+
+    fn as_user(user_id: i64, request_id: &str) -> AuditContext {
+        AuditContext {
+            actor_user_id: Some(user_id),
+            request_id: Some(request_id.to_string()),
+        }
+    }
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        for username in ["alice", "bob"] {
+            user_service::create_user(
+                &pool,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        pool
+    }
+
+    async fn open(db: &SqlitePool, user_id: i64) -> String {
+        account_service::create_account(
+            db,
+            &Default::default(),
+            user_id,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap()
+        .account_number
+    }
+
+    async fn post(db: &SqlitePool, account_number: &str, minor: i64) -> i64 {
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account_number.to_string(),
+                seller: "Shop".to_string(),
+                amount: usd(minor),
+            },
+        )
+        .await
+        .unwrap()
+        .id
+    }
+
+    async fn entries(db: &SqlitePool, query: AuditQuery) -> Vec<AuditEntry> {
+        get_audit_log(db, &query).await.unwrap().items
+    }
+
+    #[tokio::test]
+    async fn test_records_mutations_with_their_context() {
+        let db = setup_db().await;
+        let account = scope(as_user(1, "req-1"), open(&db, 1)).await;
+        scope(as_user(1, "req-2"), post(&db, &account, -1_000)).await;
+
+        let page = get_audit_log(&db, &AuditQuery::default()).await.unwrap();
+        let actions: Vec<AuditAction> = page.items.iter().map(|entry| entry.action).collect();
+        assert_eq!(
+            actions,
+            vec![
+                AuditAction::TransactionCreate,
+                AuditAction::AccountCreate,
+                AuditAction::UserCreate,
+                AuditAction::UserCreate,
+            ]
+        );
+        assert_eq!(page.next_cursor, None);
+        let posted = &page.items[0];
+        assert_eq!(posted.actor_user_id, Some(1));
+        assert_eq!(posted.request_id.as_deref(), Some("req-2"));
+        assert_eq!(posted.entity_type, "transaction");
+        assert_eq!(posted.before, None);
+        assert_eq!(posted.after.as_ref().unwrap()["amount"]["amount"], "-10.00");
+        let opened = &page.items[1];
+        assert_eq!(opened.entity_id, account);
+        assert_eq!(opened.request_id.as_deref(), Some("req-1"));
+        // signing up happens before anyone is logged in
+        assert_eq!(page.items[3].actor_user_id, None);
+        assert_eq!(page.items[3].request_id, None);
+    }
+
+    #[tokio::test]
+    async fn test_records_before_and_after_snapshots() {
+        let db = setup_db().await;
+        let account = open(&db, 1).await;
+        post(&db, &account, -2_000).await;
+        let id = post(&db, &account, 1_000).await;
+        scope(
+            as_user(1, "refund"),
+            transaction_service::refund_transaction(
+                &db,
+                1,
+                id,
+                RefundCreation {
+                    amount: Some(usd(400)),
+                },
+            ),
+        )
+        .await
+        .unwrap();
+        scope(
+            as_user(2, "overdraft"),
+            account_service::set_overdraft(
+                &db,
+                &account,
+                models::account::OverdraftSetting {
+                    limit: usd(50_000),
+                    fee: None,
+                },
+            ),
+        )
+        .await
+        .unwrap();
+
+        let refund = entries(
+            &db,
+            AuditQuery {
+                action: Some(AuditAction::TransactionRefund),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(refund.len(), 1);
+        assert_eq!(refund[0].entity_id, id.to_string());
+        assert_eq!(
+            refund[0].before.as_ref().unwrap()["refunded_amount"]["amount"],
+            "0.00"
+        );
+        assert_eq!(
+            refund[0].after.as_ref().unwrap()["refunded_amount"]["amount"],
+            "4.00"
+        );
+
+        let overdraft = entries(
+            &db,
+            AuditQuery {
+                request_id: Some("overdraft".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(overdraft.len(), 1);
+        assert_eq!(overdraft[0].action, AuditAction::AccountSetOverdraft);
+        assert_eq!(overdraft[0].actor_user_id, Some(2));
+        assert_eq!(
+            overdraft[0].before.as_ref().unwrap()["overdraft_limit"]["amount"],
+            "0.00"
+        );
+        assert_eq!(
+            overdraft[0].after.as_ref().unwrap()["overdraft_limit"]["amount"],
+            "500.00"
+        );
+    }
+
+    async fn posted(db: &SqlitePool, request_id: &str) -> Vec<AuditEntry> {
+        let mut entries = entries(
+            db,
+            AuditQuery {
+                action: Some(AuditAction::TransactionCreate),
+                request_id: Some(request_id.to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+        entries.reverse();
+        entries
+    }
+
+    #[tokio::test]
+    async fn test_transfers_and_captures_are_audited() {
+        let db = setup_db().await;
+        let source = open(&db, 1).await;
+        let destination = open(&db, 1).await;
+        post(&db, &source, -10_000).await;
+        scope(
+            as_user(1, "transfer"),
+            transfer_service::create_transfer(
+                &db,
+                1,
+                models::transfer::TransferCreation {
+                    source_account_number: source.clone(),
+                    destination_account_number: destination.clone(),
+                    amount: usd(3_000),
+                    rate: None,
+                },
+            ),
+        )
+        .await
+        .unwrap();
+        let legs = posted(&db, "transfer").await;
+        assert_eq!(legs.len(), 2);
+        for (leg, account, amount) in [
+            (&legs[0], &source, "30.00"),
+            (&legs[1], &destination, "-30.00"),
+        ] {
+            let after = leg.after.as_ref().unwrap();
+            assert_eq!(leg.actor_user_id, Some(1));
+            assert_eq!(after["account_number"], account.as_str());
+            assert_eq!(after["amount"]["amount"], amount);
+            assert!(after["transfer_id"].is_i64());
+        }
+
+        let hold = hold_service::authorize(
+            &db,
+            1,
+            models::hold::HoldCreation {
+                account_number: source.clone(),
+                seller: "Hotel".to_string(),
+                amount: usd(2_000),
+                expires_in_secs: None,
+            },
+        )
+        .await
+        .unwrap();
+        scope(
+            as_user(1, "capture"),
+            hold_service::capture(
+                &db,
+                1,
+                hold.id,
+                models::hold::HoldCapture {
+                    amount: Some(usd(1_500)),
+                },
+            ),
+        )
+        .await
+        .unwrap();
+        let captured = posted(&db, "capture").await;
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].actor_user_id, Some(1));
+        let after = captured[0].after.as_ref().unwrap();
+        assert_eq!(after["seller"], "Hotel");
+        assert_eq!(after["amount"]["amount"], "15.00");
+    }
+
+    #[tokio::test]
+    async fn test_fees_are_audited() {
+        let db = setup_db().await;
+        let account = open(&db, 1).await;
+        for (kind, minor) in [
+            (FeeKind::Overdraft, 200),
+            (FeeKind::MonthlyMaintenance, 500),
+        ] {
+            fee_service::create_fee_rule(
+                &db,
+                FeeRuleCreation {
+                    product: "checking".to_string(),
+                    kind,
+                    amount: usd(minor),
+                    rate: None,
+                    waive_above: None,
+                },
+            )
+            .await
+            .unwrap();
+        }
+        account_service::set_overdraft(
+            &db,
+            &account,
+            models::account::OverdraftSetting {
+                limit: usd(10_000),
+                fee: None,
+            },
+        )
+        .await
+        .unwrap();
+        scope(as_user(1, "overdraw"), post(&db, &account, 1_000)).await;
+        let overdraw = posted(&db, "overdraw").await;
+        assert_eq!(overdraw.len(), 2);
+        let debit_id = overdraw[0].entity_id.parse::<i64>().unwrap();
+        let fee = overdraw[1].after.as_ref().unwrap();
+        assert_eq!(overdraw[1].actor_user_id, Some(1));
+        assert_eq!(fee["fee_kind"], "overdraft");
+        assert_eq!(fee["fee_for_transaction_id"], debit_id);
+        assert_eq!(fee["amount"]["amount"], "2.00");
+
+        sqlx::query("UPDATE ACCOUNTS SET created_at = '2026-01-15 00:00:00';")
+            .execute(&db)
+            .await
+            .unwrap();
+        let march = chrono::NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        fee_service::charge_maintenance_fees(&db, march)
+            .await
+            .unwrap();
+        let maintenance = entries(
+            &db,
+            AuditQuery {
+                action: Some(AuditAction::TransactionCreate),
+                ..Default::default()
+            },
+        )
+        .await;
+        let maintenance = maintenance[0].after.as_ref().unwrap();
+        assert_eq!(maintenance["fee_kind"], "monthly_maintenance");
+        assert_eq!(maintenance["account_number"], account.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_refunds_audit_the_refund_row() {
+        let db = setup_db().await;
+        let account = open(&db, 1).await;
+        post(&db, &account, -2_000).await;
+        let id = post(&db, &account, 1_000).await;
+        let refund = scope(
+            as_user(1, "refund"),
+            transaction_service::refund_transaction(
+                &db,
+                1,
+                id,
+                RefundCreation {
+                    amount: Some(usd(400)),
+                },
+            ),
+        )
+        .await
+        .unwrap();
+        let created = posted(&db, "refund").await;
+        assert_eq!(created.len(), 1);
+        assert_eq!(created[0].entity_id, refund.id.unwrap().to_string());
+        assert_eq!(created[0].actor_user_id, Some(1));
+        let after = created[0].after.as_ref().unwrap();
+        assert_eq!(after["original_transaction_id"], id);
+        assert_eq!(after["amount"]["amount"], "-4.00");
+    }
+
+    #[tokio::test]
+    async fn test_postings_record_their_merchant() {
+        let db = setup_db().await;
+        let merchant = merchant_service::create_merchant(
+            &db,
+            models::merchant::MerchantCreation {
+                name: "Shop".to_string(),
+                category: "5411".to_string(),
+                aliases: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        let account = open(&db, 1).await;
+        post(&db, &account, -2_000).await;
+        let id = scope(as_user(1, "payment"), post(&db, &account, 1_000)).await;
+        scope(
+            as_user(1, "refund"),
+            transaction_service::refund_transaction(&db, 1, id, RefundCreation::default()),
+        )
+        .await
+        .unwrap();
+        for request_id in ["payment", "refund"] {
+            let created = posted(&db, request_id).await;
+            assert_eq!(
+                created[0].after.as_ref().unwrap()["merchant_id"],
+                merchant.id
+            );
+        }
+
+        let payloads: Vec<String> = sqlx::query_scalar(
+            "SELECT payload FROM OUTBOX_EVENTS WHERE event_type = 'transaction.posted'
+             ORDER BY id DESC LIMIT 2;",
+        )
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        for payload in payloads {
+            let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+            assert_eq!(payload["merchant_id"], merchant.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_only_role_changes_are_audited() {
+        let db = setup_db().await;
+        user_service::sync_admins(&db, &[1]).await.unwrap();
+        user_service::sync_admins(&db, &[1]).await.unwrap();
+        let changes = entries(
+            &db,
+            AuditQuery {
+                action: Some(AuditAction::UserChangeRole),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].entity_id, "1");
+        assert_eq!(changes[0].before.as_ref().unwrap()["role"], "customer");
+        assert_eq!(changes[0].after.as_ref().unwrap()["role"], "admin");
+    }
+
+    #[tokio::test]
+    async fn test_the_log_is_append_only() {
+        let db = setup_db().await;
+        let update = sqlx::query("UPDATE AUDIT_LOG SET actor_user_id = 2;")
+            .execute(&db)
+            .await;
+        assert!(update.unwrap_err().to_string().contains("append-only"));
+        let delete = sqlx::query("DELETE FROM AUDIT_LOG;").execute(&db).await;
+        assert!(delete.unwrap_err().to_string().contains("append-only"));
+        assert_eq!(entries(&db, AuditQuery::default()).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_filters_and_pages_newest_first() {
+        let db = setup_db().await;
+        let account = open(&db, 1).await;
+        for minor in [-100, -200, -300] {
+            scope(as_user(1, "batch"), post(&db, &account, minor)).await;
+        }
+        let query = AuditQuery {
+            actor_user_id: Some(1),
+            entity_type: Some("transaction".to_string()),
+            limit: Some(2),
+            ..Default::default()
+        };
+        let first = get_audit_log(&db, &query).await.unwrap();
+        assert_eq!(first.items.len(), 2);
+        assert_eq!(
+            first.items[0].after.as_ref().unwrap()["amount"]["amount"],
+            "-3.00"
+        );
+        let second = get_audit_log(
+            &db,
+            &AuditQuery {
+                cursor: first.next_cursor,
+                ..query.clone()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(second.items.len(), 1);
+        assert_eq!(second.next_cursor, None);
+        assert_eq!(
+            second.items[0].after.as_ref().unwrap()["amount"]["amount"],
+            "-1.00"
+        );
+
+        let by_account = entries(
+            &db,
+            AuditQuery {
+                entity_id: Some(account.clone()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(by_account.len(), 1);
+        assert_eq!(by_account[0].action, AuditAction::AccountCreate);
+
+        let today = chrono::Utc::now().date_naive();
+        let res = get_audit_log(
+            &db,
+            &AuditQuery {
+                from: today.succ_opt(),
+                to: Some(today),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+    }
+}
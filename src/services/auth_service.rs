@@ -0,0 +1,257 @@
+use std::sync::LazyLock;
+
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::{Algorithm, Argon2, Params, Version};
+use sqlx::{Row, SqlitePool};
+
+use crate::error::AppError;
+use crate::models;
+use crate::services::user_service;
+
+/// Argon2id cost parameters for new hashes (OWASP baseline: 19 MiB, 2 passes, 1 lane).
+/// Raising these upgrades every user transparently on their next successful login.
+pub const PASSWORD_MEMORY_KIB: u32 = 19 * 1024;
+pub const PASSWORD_ITERATIONS: u32 = 2;
+pub const PASSWORD_PARALLELISM: u32 = 1;
+
+fn current_params() -> Params {
+    Params::new(
+        PASSWORD_MEMORY_KIB,
+        PASSWORD_ITERATIONS,
+        PASSWORD_PARALLELISM,
+        None,
+    )
+    .expect("password hashing parameters are valid")
+}
+
+fn hasher(params: Params) -> Argon2<'static> {
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+}
+
+fn hash_password_with(password: &str, params: Params) -> Result<String, AppError> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = hasher(params)
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|err| AppError::Internal(format!("Password hashing failed: {}", err)))?;
+    Ok(hash.to_string())
+}
+
+/// Hashes a password into a PHC string, which carries the algorithm,
+/// parameters and per-user salt alongside the hash itself.
+pub fn hash_password(password: &str) -> Result<String, AppError> {
+    hash_password_with(password, current_params())
+}
+
+/// Checks a password against a stored PHC string. The parameters are read from
+/// the stored hash, so hashes made with older parameters still verify.
+pub fn verify_password(password: &str, stored: &str) -> bool {
+    match PasswordHash::new(stored) {
+        Ok(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// Whether a stored hash was made with anything other than the current algorithm and parameters.
+pub fn needs_rehash(stored: &str) -> bool {
+    let Ok(parsed) = PasswordHash::new(stored) else {
+        return true;
+    };
+    if parsed.algorithm != Algorithm::Argon2id.ident() || parsed.version != Some(0x13) {
+        return true;
+    }
+    let current = current_params();
+    match Params::try_from(&parsed) {
+        Ok(params) => {
+            params.m_cost() != current.m_cost()
+                || params.t_cost() != current.t_cost()
+                || params.p_cost() != current.p_cost()
+        }
+        Err(_) => true,
+    }
+}
+
+/// Runs a CPU-heavy hashing closure off the async runtime.
+pub async fn run_blocking<T, F>(f: F) -> Result<T, AppError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|err| AppError::Internal(format!("Hashing task failed: {}", err)))
+}
+
+// Verified against when the username does not exist, so a miss costs as much as a hit.
+static DUMMY_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password("dummy password").expect("dummy hash"));
+
+pub async fn login(
+    pool: &SqlitePool,
+    credentials: models::auth::LoginRequest,
+) -> Result<models::user::User, AppError> {
+    tracing::info!("Invocation to `login`");
+    let row = sqlx::query("SELECT id, password FROM USERS WHERE username = ?;")
+        .bind(credentials.username.as_str())
+        .fetch_optional(pool)
+        .await?;
+    let password = credentials.password;
+    let Some(row) = row else {
+        run_blocking(move || verify_password(&password, &DUMMY_HASH)).await?;
+        return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+    };
+    let id: i64 = row.try_get("id")?;
+    let stored: String = row.try_get("password")?;
+    let (verified, password) = {
+        let stored = stored.clone();
+        run_blocking(move || (verify_password(&password, &stored), password)).await?
+    };
+    if !verified {
+        return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+    }
+    if needs_rehash(&stored) {
+        tracing::info!("Upgrading password hash for user {}", id);
+        let upgraded = run_blocking(move || hash_password(&password)).await??;
+        // only replace the hash we verified, in case the password changed meanwhile
+        sqlx::query(
+            "UPDATE USERS SET password = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND password = ?;",
+        )
+        .bind(upgraded)
+        .bind(id)
+        .bind(stored)
+        .execute(pool)
+        .await?;
+    }
+    user_service::get_user(pool, id).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+
+    use super::*;
+
+    async fn setup_pool() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        pool
+    }
+
+    async fn stored_hash(pool: &SqlitePool, username: &str) -> String {
+        sqlx::query_scalar("SELECT password FROM USERS WHERE username = ?;")
+            .bind(username)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hash = hash_password("hunter2").unwrap();
+        assert!(hash.starts_with("$argon2id$v=19$"));
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("hunter3", &hash));
+        assert!(!needs_rehash(&hash));
+    }
+
+    #[test]
+    fn test_hashes_are_salted() {
+        let a = hash_password("same").unwrap();
+        let b = hash_password("same").unwrap();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn test_verify_rejects_plaintext() {
+        assert!(!verify_password("password", "password"));
+    }
+
+    #[tokio::test]
+    async fn test_create_user_stores_hash() {
+        let pool = setup_pool().await;
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "alice".to_string(),
+                password: "alicepass".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let stored = stored_hash(&pool, "alice").await;
+        assert_ne!(stored, "alicepass");
+        assert!(verify_password("alicepass", &stored));
+    }
+
+    #[tokio::test]
+    async fn test_login() {
+        let pool = setup_pool().await;
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "alice".to_string(),
+                password: "alicepass".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let user = login(
+            &pool,
+            models::auth::LoginRequest {
+                username: "alice".to_string(),
+                password: "alicepass".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(user.username, "alice");
+
+        let wrong = login(
+            &pool,
+            models::auth::LoginRequest {
+                username: "alice".to_string(),
+                password: "nope".to_string(),
+            },
+        )
+        .await;
+        assert!(matches!(wrong, Err(AppError::Unauthorized(_))));
+        let unknown = login(
+            &pool,
+            models::auth::LoginRequest {
+                username: "mallory".to_string(),
+                password: "alicepass".to_string(),
+            },
+        )
+        .await;
+        assert!(matches!(unknown, Err(AppError::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn test_login_rehashes_outdated_params() {
+        let pool = setup_pool().await;
+        let legacy =
+            hash_password_with("oldpass", Params::new(8 * 1024, 1, 1, None).unwrap()).unwrap();
+        assert!(needs_rehash(&legacy));
+        sqlx::query("INSERT INTO USERS (username, password) VALUES (?, ?);")
+            .bind("old")
+            .bind(&legacy)
+            .execute(&pool)
+            .await
+            .unwrap();
+        login(
+            &pool,
+            models::auth::LoginRequest {
+                username: "old".to_string(),
+                password: "oldpass".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let upgraded = stored_hash(&pool, "old").await;
+        assert_ne!(upgraded, legacy);
+        assert!(!needs_rehash(&upgraded));
+        assert!(verify_password("oldpass", &upgraded));
+    }
+}
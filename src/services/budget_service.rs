@@ -0,0 +1,510 @@
+use chrono::{Datelike, Months, NaiveDate, NaiveDateTime, Utc};
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::error::AppError;
+use crate::models::budget::{
+    ALERT_THRESHOLDS, Budget, BudgetAlert, BudgetAlertQuery, BudgetCreation, BudgetProgress,
+    BudgetSummary,
+};
+use crate::models::money::Money;
+use crate::services::{merchant_service, transaction_service, user_service};
+
+const BUDGET_COLUMNS: &str =
+    "b.id, b.user_id, b.merchant_id, b.category, b.amount, b.currency, b.created_at";
+
+fn month_of(date: NaiveDate) -> NaiveDate {
+    date.with_day(1).expect("every month has a first day")
+}
+
+/// Whole percent of `budget` that `spent` is, rounded down and never below 0.
+fn percent_used(spent: Money, budget: Money) -> i64 {
+    spent.minor_units().max(0).saturating_mul(100) / budget.minor_units()
+}
+
+async fn get_budget_in(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    id: i64,
+) -> Result<Budget, AppError> {
+    let budget: Option<Budget> = sqlx::query_as(&format!(
+        "SELECT {} FROM BUDGETS b WHERE b.id = ? AND b.user_id = ?;",
+        BUDGET_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?;
+    budget.ok_or_else(|| AppError::NotFound(format!("Budget {} not found", id)))
+}
+
+/// What the budget's owner spent with its merchant or in its category in the
+/// month starting `period_start`. Refunds count against what they refund;
+/// transfers and fees are never linked to a merchant, so never count.
+async fn spent_in(
+    conn: &mut SqliteConnection,
+    budget: &Budget,
+    period_start: NaiveDate,
+) -> Result<Money, AppError> {
+    let spent: i64 = sqlx::query_scalar(
+        "SELECT COALESCE(SUM(t.amount), 0) FROM TRANSACTIONS t
+         JOIN ACCOUNTS a ON a.account_number = t.account_number
+         LEFT JOIN TRANSACTIONS o ON o.id = t.original_transaction_id
+         LEFT JOIN MERCHANTS m ON m.id = t.merchant_id
+         WHERE a.user_id = ? AND t.currency = ? AND COALESCE(o.amount, t.amount) > 0
+         AND (t.merchant_id = ? OR m.category = ?)
+         AND date(t.created_at) >= ? AND date(t.created_at) < ?;",
+    )
+    .bind(budget.user_id)
+    .bind(budget.amount.currency())
+    .bind(budget.merchant_id)
+    .bind(&budget.category)
+    .bind(period_start)
+    .bind(period_start + Months::new(1))
+    .fetch_one(conn)
+    .await?;
+    Ok(Money::new(spent, budget.amount.currency()))
+}
+
+/// A budget for one merchant or one category, one per target and currency.
+pub async fn create_budget(
+    db: &SqlitePool,
+    user_id: i64,
+    budget_creation: BudgetCreation,
+) -> Result<Budget, AppError> {
+    tracing::info!("Invocation to `create_budget`");
+    if budget_creation.merchant_id.is_some() == budget_creation.category.is_some() {
+        return Err(AppError::Validation(
+            "A budget is for either a `merchant_id` or a `category`".to_string(),
+        ));
+    }
+    if let Some(category) = &budget_creation.category {
+        merchant_service::validate_category(category)?;
+    }
+    let amount = budget_creation.amount;
+    if !amount.is_positive() {
+        return Err(AppError::Validation(
+            "Budget amount must be positive".to_string(),
+        ));
+    }
+    let mut tx = transaction_service::begin_write(db).await?;
+    if let Some(merchant_id) = budget_creation.merchant_id {
+        merchant_service::get_merchant_in(&mut tx, merchant_id).await?;
+    }
+    let existing: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM BUDGETS
+         WHERE user_id = ? AND currency = ? AND merchant_id IS ? AND category IS ?;",
+    )
+    .bind(user_id)
+    .bind(amount.currency())
+    .bind(budget_creation.merchant_id)
+    .bind(&budget_creation.category)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(existing) = existing {
+        return Err(AppError::Conflict(format!(
+            "Budget {} already covers this in {}",
+            existing,
+            amount.currency().code()
+        )));
+    }
+    let id = sqlx::query(
+        "INSERT INTO BUDGETS (user_id, merchant_id, category, amount, currency)
+         VALUES (?, ?, ?, ?, ?);",
+    )
+    .bind(user_id)
+    .bind(budget_creation.merchant_id)
+    .bind(&budget_creation.category)
+    .bind(amount.minor_units())
+    .bind(amount.currency())
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    let budget = get_budget_in(&mut tx, user_id, id).await?;
+    tx.commit().await?;
+    Ok(budget)
+}
+
+/// Removes a budget and its alerts.
+pub async fn delete_budget(db: &SqlitePool, user_id: i64, id: i64) -> Result<Budget, AppError> {
+    tracing::info!("Invocation to `delete_budget`");
+    let mut tx = transaction_service::begin_write(db).await?;
+    let budget = get_budget_in(&mut tx, user_id, id).await?;
+    sqlx::query("DELETE FROM BUDGETS WHERE id = ?;")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(budget)
+}
+
+/// A user's budgets with this month's spending against each. Callers see
+/// their own; admins anyone's.
+pub async fn get_budget_summary(
+    db: &SqlitePool,
+    caller_id: i64,
+    user_id: i64,
+) -> Result<BudgetSummary, AppError> {
+    tracing::info!("Invocation to `get_budget_summary`");
+    if caller_id != user_id {
+        if !user_service::is_admin(db, caller_id).await? {
+            return Err(AppError::Forbidden(
+                "Only your own budgets can be viewed".to_string(),
+            ));
+        }
+        user_service::get_user(db, user_id).await?;
+    }
+    let mut conn = db.acquire().await?;
+    let budgets: Vec<Budget> = sqlx::query_as(&format!(
+        "SELECT {} FROM BUDGETS b WHERE b.user_id = ? ORDER BY b.id;",
+        BUDGET_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let period_start = month_of(Utc::now().date_naive());
+    let mut progress = Vec::with_capacity(budgets.len());
+    for budget in budgets {
+        let spent = spent_in(&mut conn, &budget, period_start).await?;
+        let percent_used = percent_used(spent, budget.amount);
+        progress.push(BudgetProgress {
+            remaining: budget.amount.checked_sub(spent)?,
+            thresholds_crossed: ALERT_THRESHOLDS
+                .into_iter()
+                .filter(|threshold| percent_used >= *threshold)
+                .collect(),
+            percent_used,
+            spent,
+            budget,
+        });
+    }
+    Ok(BudgetSummary {
+        user_id,
+        period_start,
+        budgets: progress,
+    })
+}
+
+/// The caller's budget alerts, newest first.
+pub async fn get_alerts(
+    db: &SqlitePool,
+    user_id: i64,
+    query: &BudgetAlertQuery,
+) -> Result<Vec<BudgetAlert>, AppError> {
+    tracing::info!("Invocation to `get_alerts`");
+    let alerts: Vec<BudgetAlert> = sqlx::query_as(
+        "SELECT al.id, al.budget_id, al.period_start, al.threshold, al.spent, b.currency,
+         al.transaction_id, al.created_at
+         FROM BUDGET_ALERTS al JOIN BUDGETS b ON b.id = al.budget_id
+         WHERE b.user_id = ? AND (? IS NULL OR al.budget_id = ?)
+         ORDER BY al.id DESC;",
+    )
+    .bind(user_id)
+    .bind(query.budget_id)
+    .bind(query.budget_id)
+    .fetch_all(db)
+    .await?;
+    Ok(alerts)
+}
+
+/// Checks the budgets a just-posted payment counts towards, inside the
+/// caller's database transaction, and records an alert for each threshold
+/// the month's spending reaches for the first time. Returns how many.
+pub(crate) async fn evaluate(
+    conn: &mut SqliteConnection,
+    transaction_id: i64,
+) -> Result<u64, AppError> {
+    let budgets: Vec<Budget> = sqlx::query_as(&format!(
+        "SELECT {} FROM TRANSACTIONS t
+         JOIN ACCOUNTS a ON a.account_number = t.account_number
+         JOIN MERCHANTS m ON m.id = t.merchant_id
+         JOIN BUDGETS b ON b.user_id = a.user_id AND b.currency = t.currency
+            AND (b.merchant_id = m.id OR b.category = m.category)
+         WHERE t.id = ? AND t.amount > 0
+         ORDER BY b.id;",
+        BUDGET_COLUMNS
+    ))
+    .bind(transaction_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    if budgets.is_empty() {
+        return Ok(0);
+    }
+    let created_at: NaiveDateTime =
+        sqlx::query_scalar("SELECT created_at FROM TRANSACTIONS WHERE id = ?;")
+            .bind(transaction_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    let period_start = month_of(created_at.date());
+    let mut raised = 0;
+    for budget in budgets {
+        let spent = spent_in(&mut *conn, &budget, period_start).await?;
+        let percent_used = percent_used(spent, budget.amount);
+        for threshold in ALERT_THRESHOLDS {
+            if percent_used < threshold {
+                break;
+            }
+            raised += sqlx::query(
+                "INSERT INTO BUDGET_ALERTS (budget_id, period_start, threshold, spent, transaction_id)
+                 VALUES (?, ?, ?, ?, ?)
+                 ON CONFLICT (budget_id, period_start, threshold) DO NOTHING;",
+            )
+            .bind(budget.id)
+            .bind(period_start)
+            .bind(threshold)
+            .bind(spent.minor_units())
+            .bind(transaction_id)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected();
+        }
+    }
+    Ok(raised)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models;
+    use crate::models::hold::{HoldCapture, HoldCreation};
+    use crate::models::merchant::MerchantCreation;
+    use crate::models::money::Currency;
+    use crate::models::transaction::{RefundCreation, TransactionCreation};
+    use crate::services::{account_service, hold_service};
+
+    use super::*;
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::Usd)
+    }
+
+    async fn pay(db: &SqlitePool, account: &str, seller: &str, amount: Money) -> i64 {
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account.to_string(),
+                seller: seller.to_string(),
+                amount,
+            },
+        )
+        .await
+        .unwrap()
+        .id
+    }
+
+    async fn thresholds(db: &SqlitePool, budget_id: i64) -> Vec<i64> {
+        let query = BudgetAlertQuery {
+            budget_id: Some(budget_id),
+        };
+        let mut alerts: Vec<i64> = get_alerts(db, 1, &query)
+            .await
+            .unwrap()
+            .iter()
+            .map(|alert| alert.threshold)
+            .collect();
+        alerts.reverse();
+        alerts
+    }
+
+    /// Alice, with a USD and a EUR account, and an admin; Amazon is a known
+    /// book store.
+    async fn setup_db() -> (SqlitePool, String, String) {
+        let db = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&db).await.unwrap();
+        for username in ["alice", "bob", "admin"] {
+            user_service::create_user(
+                &db,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        user_service::sync_admins(&db, &[3]).await.unwrap();
+        merchant_service::create_merchant(
+            &db,
+            MerchantCreation {
+                name: "Amazon".to_string(),
+                category: "5942".to_string(),
+                aliases: vec![],
+            },
+        )
+        .await
+        .unwrap();
+        let mut accounts = vec![];
+        for currency in [Currency::Usd, Currency::Eur] {
+            let account = account_service::create_account(
+                &db,
+                &Default::default(),
+                1,
+                models::account::AccountCreation {
+                    currency,
+                    product: None,
+                },
+            )
+            .await
+            .unwrap();
+            // money to spend
+            pay(
+                &db,
+                &account.account_number,
+                "Employer",
+                Money::new(-100_000, currency),
+            )
+            .await;
+            accounts.push(account.account_number);
+        }
+        let euros = accounts.pop().unwrap();
+        (db, accounts.pop().unwrap(), euros)
+    }
+
+    fn creation(merchant_id: Option<i64>, category: Option<&str>, amount: Money) -> BudgetCreation {
+        BudgetCreation {
+            merchant_id,
+            category: category.map(str::to_string),
+            amount,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_alerts_once_per_threshold() {
+        let (db, dollars, euros) = setup_db().await;
+        let by_merchant = create_budget(&db, 1, creation(Some(1), None, usd(10_000)))
+            .await
+            .unwrap();
+        let by_category = create_budget(&db, 1, creation(None, Some("5942"), usd(20_000)))
+            .await
+            .unwrap();
+
+        pay(&db, &dollars, "Amazon", usd(4_000)).await;
+        // neither an unknown seller nor another currency counts
+        pay(&db, &dollars, "Corner Cafe", usd(9_000)).await;
+        pay(&db, &euros, "Amazon", Money::new(9_000, Currency::Eur)).await;
+        assert_eq!(thresholds(&db, by_merchant.id).await, Vec::<i64>::new());
+
+        let crossing = pay(&db, &dollars, "AMAZON.COM*1A2B3C", usd(4_500)).await;
+        assert_eq!(thresholds(&db, by_merchant.id).await, vec![50, 80]);
+        let alerts = get_alerts(&db, 1, &BudgetAlertQuery::default())
+            .await
+            .unwrap();
+        assert_eq!(alerts.len(), 2);
+        assert_eq!(alerts[0].transaction_id, crossing);
+        assert_eq!(alerts[0].spent, usd(8_500));
+        assert_eq!(alerts[0].period_start, month_of(Utc::now().date_naive()));
+        assert_eq!(thresholds(&db, by_category.id).await, Vec::<i64>::new());
+
+        // a refund takes spending back under 80%, but the alert is not repeated
+        transaction_service::refund_transaction(
+            &db,
+            1,
+            crossing,
+            RefundCreation {
+                amount: Some(usd(2_000)),
+            },
+        )
+        .await
+        .unwrap();
+        pay(&db, &dollars, "Amazon", usd(2_000)).await;
+        assert_eq!(thresholds(&db, by_merchant.id).await, vec![50, 80]);
+
+        // captured card payments count too
+        let hold = hold_service::authorize(
+            &db,
+            1,
+            HoldCreation {
+                account_number: dollars.clone(),
+                seller: "Amazon".to_string(),
+                amount: usd(2_000),
+                expires_in_secs: None,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(thresholds(&db, by_merchant.id).await, vec![50, 80]);
+        hold_service::capture(&db, 1, hold.id, HoldCapture::default())
+            .await
+            .unwrap();
+        assert_eq!(thresholds(&db, by_merchant.id).await, vec![50, 80, 100]);
+        assert_eq!(thresholds(&db, by_category.id).await, vec![50]);
+
+        // bob sees none of it
+        let bobs = get_alerts(&db, 2, &BudgetAlertQuery::default())
+            .await
+            .unwrap();
+        assert!(bobs.is_empty());
+        // alerts go with their budget
+        delete_budget(&db, 1, by_merchant.id).await.unwrap();
+        assert_eq!(thresholds(&db, by_merchant.id).await, Vec::<i64>::new());
+    }
+
+    #[tokio::test]
+    async fn test_summary_shows_progress() {
+        let (db, dollars, _) = setup_db().await;
+        create_budget(&db, 1, creation(Some(1), None, usd(10_000)))
+            .await
+            .unwrap();
+        create_budget(&db, 1, creation(None, Some("5411"), usd(30_000)))
+            .await
+            .unwrap();
+        pay(&db, &dollars, "Amazon", usd(12_345)).await;
+
+        let summary = get_budget_summary(&db, 1, 1).await.unwrap();
+        assert_eq!(summary.period_start.day(), 1);
+        let progress: Vec<(Money, Money, i64, Vec<i64>)> = summary
+            .budgets
+            .into_iter()
+            .map(|p| (p.spent, p.remaining, p.percent_used, p.thresholds_crossed))
+            .collect();
+        assert_eq!(
+            progress,
+            vec![
+                (usd(12_345), usd(-2_345), 123, vec![50, 80, 100]),
+                (usd(0), usd(30_000), 0, vec![]),
+            ]
+        );
+
+        let res = get_budget_summary(&db, 2, 1).await;
+        assert!(matches!(res, Err(AppError::Forbidden(_))));
+        let summary = get_budget_summary(&db, 3, 1).await.unwrap();
+        assert_eq!(summary.budgets.len(), 2);
+        let res = get_budget_summary(&db, 3, 42).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_budget_checks_its_target() {
+        let (db, _, _) = setup_db().await;
+        for budget in [
+            creation(Some(1), Some("5942"), usd(100)),
+            creation(None, None, usd(100)),
+            creation(None, Some("books"), usd(100)),
+            creation(Some(1), None, usd(0)),
+        ] {
+            let res = create_budget(&db, 1, budget).await;
+            assert!(matches!(res, Err(AppError::Validation(_))));
+        }
+        let res = create_budget(&db, 1, creation(Some(42), None, usd(100))).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+
+        let budget = create_budget(&db, 1, creation(Some(1), None, usd(100)))
+            .await
+            .unwrap();
+        assert_eq!(budget.merchant_id, Some(1));
+        let res = create_budget(&db, 1, creation(Some(1), None, usd(500))).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+        // another currency or another user is another budget
+        let euros = Money::new(100, Currency::Eur);
+        create_budget(&db, 1, creation(Some(1), None, euros))
+            .await
+            .unwrap();
+        create_budget(&db, 2, creation(Some(1), None, usd(100)))
+            .await
+            .unwrap();
+
+        let res = delete_budget(&db, 2, budget.id).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+        delete_budget(&db, 1, budget.id).await.unwrap();
+    }
+}
@@ -0,0 +1,631 @@
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate};
+use sqlx::{Row, SqliteConnection, SqlitePool};
+
+use crate::error::AppError;
+use crate::models;
+use crate::models::fee::{FeeKind, FeeRule};
+use crate::models::money::{Currency, Money};
+use crate::services::transaction_service;
+
+/// How often the background task looks for maintenance fees to charge.
+pub const FEE_JOB_INTERVAL_SECS: u64 = 60 * 60;
+
+const FEE_RULE_COLUMNS: &str = "id, product, kind, amount, currency, rate, waive_above, created_at";
+
+pub async fn get_fee_rules(
+    db: &SqlitePool,
+    query: &models::fee::FeeRuleQuery,
+) -> Result<Vec<FeeRule>, AppError> {
+    tracing::info!("Invocation to `get_fee_rules`");
+    let rules: Vec<FeeRule> = sqlx::query_as(&format!(
+        "SELECT {} FROM FEE_RULES WHERE product = COALESCE(?, product) ORDER BY product, id;",
+        FEE_RULE_COLUMNS
+    ))
+    .bind(&query.product)
+    .fetch_all(db)
+    .await?;
+    Ok(rules)
+}
+
+pub async fn create_fee_rule(
+    db: &SqlitePool,
+    rule_creation: models::fee::FeeRuleCreation,
+) -> Result<FeeRule, AppError> {
+    tracing::info!("Invocation to `create_fee_rule`");
+    let amount = rule_creation.amount;
+    let currency = amount.currency();
+    if amount.is_negative() {
+        return Err(AppError::Validation(
+            "Fee amount must not be negative".to_string(),
+        ));
+    }
+    if let Some(rate) = &rule_creation.rate {
+        if rule_creation.kind == FeeKind::MonthlyMaintenance {
+            return Err(AppError::Validation(
+                "Monthly maintenance fees cannot have a rate".to_string(),
+            ));
+        }
+        // the same check a conversion at this rate would make
+        Money::new(1, currency).convert(rate, currency)?;
+    } else if amount.is_zero() {
+        return Err(AppError::Validation(
+            "A fee rule needs an amount or a rate".to_string(),
+        ));
+    }
+    if let Some(waive_above) = rule_creation.waive_above
+        && (waive_above.currency() != currency || waive_above.is_negative())
+    {
+        return Err(AppError::Validation(
+            "waive_above must be a non-negative amount in the fee's currency".to_string(),
+        ));
+    }
+    let known: Option<String> = sqlx::query_scalar("SELECT code FROM PRODUCTS WHERE code = ?;")
+        .bind(&rule_creation.product)
+        .fetch_optional(db)
+        .await?;
+    if known.is_none() {
+        return Err(AppError::Validation(format!(
+            "Unknown product {}",
+            rule_creation.product
+        )));
+    }
+    let id = sqlx::query(
+        "INSERT INTO FEE_RULES (product, kind, amount, currency, rate, waive_above)
+         VALUES (?, ?, ?, ?, ?, ?);",
+    )
+    .bind(&rule_creation.product)
+    .bind(rule_creation.kind)
+    .bind(amount.minor_units())
+    .bind(currency)
+    .bind(&rule_creation.rate)
+    .bind(rule_creation.waive_above.map(|w| w.minor_units()))
+    .execute(db)
+    .await?
+    .last_insert_rowid();
+    let rule: FeeRule = sqlx::query_as(&format!(
+        "SELECT {} FROM FEE_RULES WHERE id = ?;",
+        FEE_RULE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_one(db)
+    .await?;
+    Ok(rule)
+}
+
+/// Stops a rule from charging anything further. Fees it already charged stay.
+pub async fn delete_fee_rule(db: &SqlitePool, id: i64) -> Result<FeeRule, AppError> {
+    tracing::info!("Invocation to `delete_fee_rule`");
+    let rule: Option<FeeRule> = sqlx::query_as(&format!(
+        "DELETE FROM FEE_RULES WHERE id = ? RETURNING {};",
+        FEE_RULE_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+    rule.ok_or_else(|| AppError::NotFound(format!("Fee rule {} not found", id)))
+}
+
+/// The fees of `kind` an account owes right now, inside the caller's database
+/// transaction: one per matching rule of its product that is not waived at its
+/// current balance, plus, for overdrafts, the account's own overdraft fee.
+/// `base` is the amount proportional fees are a fraction of. `pending` is a
+/// debit that has not been posted yet, taken off the balance first so that a
+/// preview sees the balance the real charge would.
+pub async fn fees_due(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+    kind: FeeKind,
+    base: Option<Money>,
+    pending: Option<Money>,
+) -> Result<Vec<Money>, AppError> {
+    let account = sqlx::query(
+        "SELECT product, balance, currency, overdraft_fee FROM ACCOUNTS WHERE account_number = ?;",
+    )
+    .bind(account_number)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Account {} not found", account_number)))?;
+    let product: String = account.try_get("product")?;
+    let mut balance = Money::from_row(&account, "balance", "currency")?;
+    if let Some(pending) = pending {
+        balance = balance.checked_sub(pending)?;
+    }
+    let currency: Currency = account.try_get("currency")?;
+    let rules: Vec<FeeRule> = sqlx::query_as(&format!(
+        "SELECT {} FROM FEE_RULES WHERE product = ? AND kind = ? AND currency = ? ORDER BY id;",
+        FEE_RULE_COLUMNS
+    ))
+    .bind(&product)
+    .bind(kind)
+    .bind(currency)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut fees = Vec::new();
+    for rule in &rules {
+        if rule.waive_above.is_some_and(|waive_above| {
+            balance
+                .checked_sub(waive_above)
+                .is_ok_and(|d| !d.is_negative())
+        }) {
+            continue;
+        }
+        let proportional = match (&rule.rate, base) {
+            (Some(rate), Some(base)) => {
+                let base = if base.is_negative() {
+                    base.checked_neg()?
+                } else {
+                    base
+                };
+                base.convert(rate, currency)?
+            }
+            _ => Money::zero(currency),
+        };
+        fees.push(rule.amount.checked_add(proportional)?);
+    }
+    if kind == FeeKind::Overdraft {
+        fees.push(Money::from_row(&account, "overdraft_fee", "currency")?);
+    }
+    fees.retain(|fee| fee.is_positive());
+    Ok(fees)
+}
+
+/// Charges every account whose product has a maintenance fee for the month
+/// before `today`, once per account and month. Accounts opened during that
+/// month or later are not charged for it. Returns how many accounts were looked at.
+pub async fn charge_maintenance_fees(db: &SqlitePool, today: NaiveDate) -> Result<u64, AppError> {
+    let month_start = today.with_day(1).unwrap_or(today);
+    let month = month_start
+        .pred_opt()
+        .unwrap_or(month_start)
+        .format("%Y-%m")
+        .to_string();
+    let accounts: Vec<String> = sqlx::query_scalar(
+        "SELECT a.account_number FROM ACCOUNTS a
+         WHERE a.created_at < ? AND a.status != 'closed'
+         AND a.product IN (SELECT product FROM FEE_RULES WHERE kind = 'monthly_maintenance')
+         AND NOT EXISTS (SELECT 1 FROM MAINTENANCE_FEE_PERIODS m
+            WHERE m.account_number = a.account_number AND m.month = ?)
+         ORDER BY a.account_number;",
+    )
+    .bind(month_start)
+    .bind(&month)
+    .fetch_all(db)
+    .await?;
+    let mut charged = 0;
+    for account_number in &accounts {
+        let mut tx = transaction_service::begin_write(db).await?;
+        let claimed = sqlx::query(
+            "INSERT INTO MAINTENANCE_FEE_PERIODS (account_number, month) VALUES (?, ?)
+             ON CONFLICT (account_number, month) DO NOTHING;",
+        )
+        .bind(account_number)
+        .bind(&month)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if claimed == 0 {
+            // another run got there first
+            tx.rollback().await?;
+            continue;
+        }
+        transaction_service::charge_fees(
+            &mut tx,
+            account_number,
+            FeeKind::MonthlyMaintenance,
+            None,
+            None,
+        )
+        .await?;
+        tx.commit().await?;
+        charged += 1;
+    }
+    Ok(charged)
+}
+
+/// Background task that charges maintenance fees every `FEE_JOB_INTERVAL_SECS`.
+pub async fn run_maintenance_fees(db: SqlitePool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(FEE_JOB_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        match charge_maintenance_fees(&db, chrono::Utc::now().date_naive()).await {
+            Ok(0) => {}
+            Ok(accounts) => tracing::info!("Dealt with maintenance fees of {} accounts", accounts),
+            Err(err) => tracing::error!("Charging maintenance fees failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models::fee::FeeRuleCreation;
+    use crate::models::transaction::TransactionCreation;
+    use crate::models::transfer::TransferCreation;
+    use crate::services::{account_service, ledger_service, transfer_service, user_service};
+
+    use super::*;
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::Usd)
+    }
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "alice".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        pool
+    }
+
+    async fn open(db: &SqlitePool, currency: Currency, deposit: i64) -> String {
+        let account = account_service::create_account(
+            db,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account.account_number.clone(),
+                seller: "Employer".to_string(),
+                amount: Money::new(-deposit, currency),
+            },
+        )
+        .await
+        .unwrap();
+        account.account_number
+    }
+
+    fn rule(kind: FeeKind, amount: Money) -> FeeRuleCreation {
+        FeeRuleCreation {
+            product: "checking".to_string(),
+            kind,
+            amount,
+            rate: None,
+            waive_above: None,
+        }
+    }
+
+    async fn pay(db: &SqlitePool, account_number: &str, minor: i64) -> Vec<Money> {
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account_number.to_string(),
+                seller: "Shop".to_string(),
+                amount: usd(minor),
+            },
+        )
+        .await
+        .unwrap()
+        .fees
+        .into_iter()
+        .map(|fee| fee.amount)
+        .collect()
+    }
+
+    async fn balance(db: &SqlitePool, account_number: &str) -> Money {
+        account_service::get_account_by_account_number(db, account_number.to_string())
+            .await
+            .unwrap()
+            .balance
+    }
+
+    #[tokio::test]
+    async fn test_transaction_fee_is_linked_and_in_the_receipt() {
+        let db = setup_db().await;
+        let account = open(&db, Currency::Usd, 100_000).await;
+        create_fee_rule(
+            &db,
+            FeeRuleCreation {
+                rate: Some("0.01".to_string()),
+                ..rule(FeeKind::PerTransaction, usd(25))
+            },
+        )
+        .await
+        .unwrap();
+
+        let receipt = transaction_service::create_transaction(
+            &db,
+            1,
+            TransactionCreation {
+                account_number: account.clone(),
+                seller: "Shop".to_string(),
+                amount: usd(10_000),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(receipt.fees.len(), 1);
+        assert_eq!(receipt.fees[0].kind, FeeKind::PerTransaction);
+        assert_eq!(receipt.fees[0].amount, usd(125));
+        assert_eq!(balance(&db, &account).await, usd(100_000 - 10_000 - 125));
+
+        let fee = transaction_service::get_transactions(&db, 1, &Default::default())
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .find(|t| t.id.map(i64::from) == Some(receipt.fees[0].transaction_id))
+            .unwrap();
+        assert_eq!(fee.fee_kind, Some(FeeKind::PerTransaction));
+        assert_eq!(fee.fee_for_transaction_id, Some(receipt.id));
+        assert_eq!(fee.seller, "Transaction fee");
+        assert!(ledger_service::reconcile(&db).await.unwrap().is_empty());
+
+        // deposits are free
+        let deposit = transaction_service::create_transaction(
+            &db,
+            1,
+            TransactionCreation {
+                account_number: account,
+                seller: "Employer".to_string(),
+                amount: usd(-5_000),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(deposit.fees.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_fee_is_waived_above_the_minimum_balance() {
+        let db = setup_db().await;
+        let account = open(&db, Currency::Usd, 60_000).await;
+        create_fee_rule(
+            &db,
+            FeeRuleCreation {
+                waive_above: Some(usd(50_000)),
+                ..rule(FeeKind::PerTransaction, usd(100))
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(pay(&db, &account, 5_000).await, vec![]);
+        // 55_000 before, but 49_000 after this one
+        assert_eq!(pay(&db, &account, 6_000).await, vec![usd(100)]);
+    }
+
+    #[tokio::test]
+    async fn test_rules_only_apply_to_their_product_and_currency() {
+        let db = setup_db().await;
+        let account = open(&db, Currency::Usd, 10_000).await;
+        create_fee_rule(
+            &db,
+            FeeRuleCreation {
+                product: "savings".to_string(),
+                ..rule(FeeKind::PerTransaction, usd(100))
+            },
+        )
+        .await
+        .unwrap();
+        create_fee_rule(
+            &db,
+            rule(FeeKind::PerTransaction, Money::new(100, Currency::Eur)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(pay(&db, &account, 1_000).await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn test_deleted_rules_stop_charging() {
+        let db = setup_db().await;
+        let account = open(&db, Currency::Usd, 10_000).await;
+        let created = create_fee_rule(&db, rule(FeeKind::PerTransaction, usd(100)))
+            .await
+            .unwrap();
+        assert_eq!(pay(&db, &account, 1_000).await, vec![usd(100)]);
+        delete_fee_rule(&db, created.id).await.unwrap();
+        assert_eq!(pay(&db, &account, 1_000).await, vec![]);
+        assert!(matches!(
+            delete_fee_rule(&db, created.id).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_foreign_currency_fee_on_transfers() {
+        let db = setup_db().await;
+        let from = open(&db, Currency::Usd, 10_000).await;
+        let to = open(&db, Currency::Eur, 0).await;
+        create_fee_rule(
+            &db,
+            FeeRuleCreation {
+                rate: Some("0.03".to_string()),
+                ..rule(FeeKind::ForeignCurrency, usd(0))
+            },
+        )
+        .await
+        .unwrap();
+        let transfer = |destination: String| TransferCreation {
+            source_account_number: from.clone(),
+            destination_account_number: destination,
+            amount: usd(1_000),
+            rate: Some("0.9".to_string()),
+        };
+        transfer_service::create_transfer(&db, 1, transfer(to.clone()))
+            .await
+            .unwrap();
+        assert_eq!(balance(&db, &from).await, usd(10_000 - 1_000 - 30));
+        assert_eq!(balance(&db, &to).await, Money::new(900, Currency::Eur));
+
+        // same-currency transfers are not charged
+        let other = open(&db, Currency::Usd, 0).await;
+        transfer_service::create_transfer(
+            &db,
+            1,
+            TransferCreation {
+                rate: None,
+                ..transfer(other)
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(balance(&db, &from).await, usd(10_000 - 2_000 - 30));
+        assert!(ledger_service::reconcile(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_overdraft_rules_add_to_the_account_fee() {
+        let db = setup_db().await;
+        let account = open(&db, Currency::Usd, 1_000).await;
+        account_service::set_overdraft(
+            &db,
+            &account,
+            models::account::OverdraftSetting {
+                limit: usd(10_000),
+                fee: Some(usd(500)),
+            },
+        )
+        .await
+        .unwrap();
+        create_fee_rule(&db, rule(FeeKind::Overdraft, usd(200)))
+            .await
+            .unwrap();
+        let mut fees = pay(&db, &account, 2_000).await;
+        fees.sort_by_key(|fee| fee.minor_units());
+        assert_eq!(fees, vec![usd(200), usd(500)]);
+        assert_eq!(balance(&db, &account).await, usd(1_000 - 2_000 - 700));
+    }
+
+    #[tokio::test]
+    async fn test_preview_matches_the_charge_and_posts_nothing() {
+        let db = setup_db().await;
+        let account = open(&db, Currency::Usd, 60_000).await;
+        account_service::set_overdraft(
+            &db,
+            &account,
+            models::account::OverdraftSetting {
+                limit: usd(100_000),
+                fee: Some(usd(500)),
+            },
+        )
+        .await
+        .unwrap();
+        create_fee_rule(&db, rule(FeeKind::Overdraft, usd(200)))
+            .await
+            .unwrap();
+        create_fee_rule(
+            &db,
+            FeeRuleCreation {
+                waive_above: Some(usd(50_000)),
+                ..rule(FeeKind::PerTransaction, usd(100))
+            },
+        )
+        .await
+        .unwrap();
+        // waived, then charged, then charged on top of the overdraft fees
+        for minor in [5_000, 6_000, 60_000] {
+            let before = balance(&db, &account).await;
+            let preview = transaction_service::preview_transaction(
+                &db,
+                1,
+                TransactionCreation {
+                    account_number: account.clone(),
+                    seller: "Shop".to_string(),
+                    amount: usd(minor),
+                },
+            )
+            .await
+            .unwrap();
+            assert_eq!(balance(&db, &account).await, before);
+            let mut previewed: Vec<Money> = preview.fees.iter().map(|fee| fee.amount).collect();
+            let mut charged = pay(&db, &account, minor).await;
+            previewed.sort_by_key(|fee| fee.minor_units());
+            charged.sort_by_key(|fee| fee.minor_units());
+            assert_eq!(previewed, charged, "{}", minor);
+        }
+        assert_eq!(balance(&db, &account).await, usd(60_000 - 71_000 - 900));
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_fee_is_charged_once_a_month() {
+        let db = setup_db().await;
+        let account = open(&db, Currency::Usd, 10_000).await;
+        create_fee_rule(&db, rule(FeeKind::MonthlyMaintenance, usd(500)))
+            .await
+            .unwrap();
+        let today = chrono::Utc::now().date_naive();
+        // opened this month: nothing owed for last month yet
+        assert_eq!(charge_maintenance_fees(&db, today).await.unwrap(), 0);
+
+        sqlx::query("UPDATE ACCOUNTS SET created_at = '2026-01-15 00:00:00';")
+            .execute(&db)
+            .await
+            .unwrap();
+        let march = NaiveDate::from_ymd_opt(2026, 3, 2).unwrap();
+        assert_eq!(charge_maintenance_fees(&db, march).await.unwrap(), 1);
+        assert_eq!(charge_maintenance_fees(&db, march).await.unwrap(), 0);
+        assert_eq!(balance(&db, &account).await, usd(9_500));
+
+        let fee = transaction_service::get_transactions(&db, 1, &Default::default())
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .find(|t| t.fee_kind == Some(FeeKind::MonthlyMaintenance))
+            .unwrap();
+        assert_eq!(fee.seller, "Monthly maintenance fee");
+        assert_eq!(fee.fee_for_transaction_id, None);
+        assert!(ledger_service::reconcile(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_fee_rule_validation() {
+        let db = setup_db().await;
+        let invalid = [
+            rule(FeeKind::PerTransaction, usd(-1)),
+            rule(FeeKind::PerTransaction, usd(0)),
+            FeeRuleCreation {
+                rate: Some("3%".to_string()),
+                ..rule(FeeKind::PerTransaction, usd(0))
+            },
+            FeeRuleCreation {
+                rate: Some("0.01".to_string()),
+                ..rule(FeeKind::MonthlyMaintenance, usd(100))
+            },
+            FeeRuleCreation {
+                waive_above: Some(Money::new(100, Currency::Eur)),
+                ..rule(FeeKind::PerTransaction, usd(100))
+            },
+            FeeRuleCreation {
+                product: "platinum".to_string(),
+                ..rule(FeeKind::PerTransaction, usd(100))
+            },
+        ];
+        for creation in invalid {
+            assert!(
+                matches!(
+                    create_fee_rule(&db, creation.clone()).await,
+                    Err(AppError::Validation(_))
+                ),
+                "{:?}",
+                creation
+            );
+        }
+        assert!(
+            get_fee_rules(&db, &Default::default())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
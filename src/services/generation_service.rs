@@ -1,5 +1,42 @@
-use rand::{thread_rng, Rng};
+use rand::{Rng, thread_rng};
 
+use crate::config::Config;
+use crate::error::AppError;
+
+/// Shortest and longest account number the scheme issues, check digit included.
+pub const MIN_ACCOUNT_NUMBER_LEN: usize = 12;
+pub const MAX_ACCOUNT_NUMBER_LEN: usize = 19;
+/// ISO 13616 bounds on a whole IBAN.
+pub const MIN_IBAN_LEN: usize = 15;
+pub const MAX_IBAN_LEN: usize = 34;
+/// BBAN structure of each country this bank can issue IBANs in: the country
+/// code, the shape of its bank code (`n` a digit, `a` an uppercase letter) and
+/// exactly how many characters follow it, which hold the account number. Only
+/// countries without a national check digit inside the BBAN are listed, since
+/// the bank would have to compute it.
+const BBAN_FORMATS: &[(&str, &str, usize)] = &[
+    ("AT", "nnnnn", 11),
+    ("BG", "aaaannnn", 10),
+    ("CH", "nnnnn", 12),
+    ("CY", "nnnnnnnn", 16),
+    ("CZ", "nnnn", 16),
+    ("DE", "nnnnnnnn", 10),
+    ("DK", "nnnn", 10),
+    ("GB", "aaaannnnnn", 8),
+    ("GR", "nnnnnnn", 16),
+    ("HR", "nnnnnnn", 10),
+    ("IE", "aaaannnnnn", 8),
+    ("LI", "nnnnn", 12),
+    ("LT", "nnnnn", 11),
+    ("LU", "nnn", 13),
+    ("LV", "aaaa", 13),
+    ("MT", "aaaannnnn", 18),
+    ("NL", "aaaa", 10),
+    ("PL", "nnnnnnnn", 16),
+    ("RO", "aaaa", 16),
+    ("SE", "nnn", 17),
+    ("SK", "nnnn", 16),
+];
 
 pub fn generate_numeric_string(length: usize) -> String {
     let mut rng = thread_rng();
@@ -12,6 +49,265 @@ pub fn generate_numeric_string(length: usize) -> String {
     result
 }
 
+/// Luhn check digit for a string of ASCII digits.
+pub fn luhn_check_digit(digits: &str) -> char {
+    let sum: u32 = digits
+        .bytes()
+        .rev()
+        .enumerate()
+        .map(|(i, b)| {
+            let d = (b - b'0') as u32;
+            // the digit next to the check digit is doubled, then every other one
+            if i % 2 == 0 {
+                if d * 2 > 9 { d * 2 - 9 } else { d * 2 }
+            } else {
+                d
+            }
+        })
+        .sum();
+    char::from(b'0' + ((10 - sum % 10) % 10) as u8)
+}
+
+/// Rejects malformed account numbers before they reach the database: anything
+/// that is not all digits, has the wrong length or fails its check digit.
+pub fn validate_account_number(account_number: &str) -> Result<(), AppError> {
+    let invalid = || AppError::Validation(format!("Invalid account number {}", account_number));
+    if !account_number.bytes().all(|b| b.is_ascii_digit())
+        || !(MIN_ACCOUNT_NUMBER_LEN..=MAX_ACCOUNT_NUMBER_LEN).contains(&account_number.len())
+    {
+        return Err(invalid());
+    }
+    let (body, check) = account_number.split_at(account_number.len() - 1);
+    if check.starts_with(luhn_check_digit(body)) {
+        Ok(())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Whether input meant as an account identifier is an IBAN rather than a bare
+/// account number. IBANs start with a country code; account numbers are digits.
+pub fn looks_like_iban(input: &str) -> bool {
+    input.bytes().take(2).all(|b| b.is_ascii_alphabetic()) && input.len() >= 2
+}
+
+/// Strips the spaces IBANs are usually printed with and uppercases the letters.
+pub fn normalize_iban(input: &str) -> String {
+    input
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+/// The remainder mod 97 of an IBAN-style string with letters read as 10..35.
+fn mod97(chars: impl Iterator<Item = u8>) -> u32 {
+    chars.fold(0, |acc, b| {
+        let value = if b.is_ascii_digit() {
+            (b - b'0') as u32
+        } else {
+            (b - b'A') as u32 + 10
+        };
+        if value >= 10 {
+            (acc * 100 + value) % 97
+        } else {
+            (acc * 10 + value) % 97
+        }
+    })
+}
+
+fn iban_check_digits(country_code: &str, bban: &str) -> String {
+    let remainder = mod97(bban.bytes().chain(country_code.bytes()).chain(*b"00"));
+    format!("{:02}", 98 - remainder)
+}
+
+/// Checks an already normalized IBAN's shape and its mod-97 check digits.
+pub fn validate_iban(iban: &str) -> Result<(), AppError> {
+    let invalid = || AppError::Validation(format!("Invalid IBAN {}", iban));
+    let bytes = iban.as_bytes();
+    if !(MIN_IBAN_LEN..=MAX_IBAN_LEN).contains(&bytes.len())
+        || !bytes[..2].iter().all(|b| b.is_ascii_uppercase())
+        || !bytes[2..4].iter().all(|b| b.is_ascii_digit())
+        || !bytes[4..]
+            .iter()
+            .all(|b| b.is_ascii_digit() || b.is_ascii_uppercase())
+    {
+        return Err(invalid());
+    }
+    if mod97(bytes[4..].iter().chain(&bytes[..4]).copied()) != 1 {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
+/// Layout of the IBANs this bank issues: country code, check digits, then a
+/// BBAN of the bank code followed by the account number zero-padded to
+/// `account_digits`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct IbanScheme {
+    country_code: String,
+    bank_code: String,
+    account_digits: usize,
+}
+
+impl Default for IbanScheme {
+    /// A Romanian layout: a four-letter bank code and 16 digits, as many as
+    /// the default account numbers have.
+    fn default() -> Self {
+        IbanScheme {
+            country_code: "RO".to_string(),
+            bank_code: "CRAB".to_string(),
+            account_digits: 16,
+        }
+    }
+}
+
+impl IbanScheme {
+    pub fn new(country_code: &str, bank_code: &str, account_digits: usize) -> Result<Self, String> {
+        let Some(&(_, bank_code_shape, bban_account_digits)) = BBAN_FORMATS
+            .iter()
+            .find(|(code, _, _)| *code == country_code)
+        else {
+            return Err(format!(
+                "IBANs cannot be issued for country code {:?}",
+                country_code
+            ));
+        };
+        let shaped = bank_code.len() == bank_code_shape.len()
+            && bank_code
+                .bytes()
+                .zip(bank_code_shape.bytes())
+                .all(|(b, kind)| match kind {
+                    b'n' => b.is_ascii_digit(),
+                    _ => b.is_ascii_uppercase(),
+                });
+        if !shaped {
+            return Err(format!(
+                "{} bank codes look like {:?} (n a digit, a an uppercase letter), not {:?}",
+                country_code, bank_code_shape, bank_code
+            ));
+        }
+        if account_digits != bban_account_digits {
+            return Err(format!(
+                "{} IBANs hold {} account digits, not {}",
+                country_code, bban_account_digits, account_digits
+            ));
+        }
+        Ok(IbanScheme {
+            country_code: country_code.to_string(),
+            bank_code: bank_code.to_string(),
+            account_digits,
+        })
+    }
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        IbanScheme::new(
+            &config.iban_country_code,
+            &config.iban_bank_code,
+            config.iban_account_digits,
+        )
+    }
+
+    pub fn derive(&self, account_number: &str) -> Result<String, AppError> {
+        if account_number.len() > self.account_digits {
+            return Err(AppError::Internal(format!(
+                "Account number {} does not fit the IBAN layout",
+                account_number
+            )));
+        }
+        let bban = format!(
+            "{}{:0>width$}",
+            self.bank_code,
+            account_number,
+            width = self.account_digits
+        );
+        let check = iban_check_digits(&self.country_code, &bban);
+        Ok(format!("{}{}{}", self.country_code, check, bban))
+    }
+}
+
+/// How new account numbers are made: a fixed bank/branch prefix, random
+/// digits, then a Luhn check digit, `length` digits in all.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct AccountNumberScheme {
+    prefix: String,
+    length: usize,
+    iban: IbanScheme,
+}
+
+impl Default for AccountNumberScheme {
+    fn default() -> Self {
+        AccountNumberScheme {
+            prefix: "1000".to_string(),
+            length: 16,
+            iban: IbanScheme::default(),
+        }
+    }
+}
+
+impl AccountNumberScheme {
+    pub fn new(prefix: &str, length: usize) -> Result<Self, String> {
+        if !prefix.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(format!("Account number prefix {:?} must be digits", prefix));
+        }
+        if !(MIN_ACCOUNT_NUMBER_LEN..=MAX_ACCOUNT_NUMBER_LEN).contains(&length) {
+            return Err(format!(
+                "Account number length must be between {} and {}",
+                MIN_ACCOUNT_NUMBER_LEN, MAX_ACCOUNT_NUMBER_LEN
+            ));
+        }
+        // leave room for the check digit and enough random digits to make collisions rare
+        if prefix.len() + 1 + 6 > length {
+            return Err(format!(
+                "Account number prefix {:?} is too long for {} digits",
+                prefix, length
+            ));
+        }
+        Ok(AccountNumberScheme {
+            prefix: prefix.to_string(),
+            length,
+            iban: IbanScheme::default(),
+        })
+    }
+    pub fn with_iban(self, iban: IbanScheme) -> Result<Self, String> {
+        if iban.account_digits < self.length {
+            return Err(format!(
+                "IBANs hold {} account digits but account numbers have {}",
+                iban.account_digits, self.length
+            ));
+        }
+        Ok(AccountNumberScheme { iban, ..self })
+    }
+    pub fn from_config(config: &Config) -> Result<Self, String> {
+        AccountNumberScheme::new(&config.account_number_prefix, config.account_number_length)?
+            .with_iban(IbanScheme::from_config(config)?)
+    }
+
+    pub fn iban(&self) -> &IbanScheme {
+        &self.iban
+    }
+
+    pub fn generate(&self) -> String {
+        let mut number = self.prefix.clone();
+        number.push_str(&generate_numeric_string(
+            self.length - self.prefix.len() - 1,
+        ));
+        number.push(luhn_check_digit(&number));
+        number
+    }
+}
+
+#[cfg(test)]
+impl AccountNumberScheme {
+    /// Skips validation, so tests can make a scheme with no random digits at all.
+    pub fn unchecked(prefix: &str, length: usize) -> Self {
+        AccountNumberScheme {
+            prefix: prefix.to_string(),
+            length,
+            iban: IbanScheme::default(),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -50,4 +346,106 @@ mod tests {
         // It's possible for them to be equal, but highly unlikely
         assert_ne!(s1, s2);
     }
+
+    #[test]
+    fn test_luhn_check_digit() {
+        // the classic example from the algorithm's description
+        assert_eq!(luhn_check_digit("7992739871"), '3');
+        assert_eq!(luhn_check_digit("424242424242424"), '2');
+    }
+
+    #[test]
+    fn test_generated_numbers_validate() {
+        let scheme = AccountNumberScheme::new("12345", 14).unwrap();
+        for _ in 0..100 {
+            let number = scheme.generate();
+            assert_eq!(number.len(), 14);
+            assert!(number.starts_with("12345"));
+            validate_account_number(&number).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_validate_account_number_rejects_typos() {
+        let number = AccountNumberScheme::default().generate();
+        // any single changed digit breaks the check digit
+        for i in 0..number.len() {
+            let mut typo = number.clone().into_bytes();
+            typo[i] = b'0' + (typo[i] - b'0' + 1) % 10;
+            let typo = String::from_utf8(typo).unwrap();
+            assert!(validate_account_number(&typo).is_err(), "{}", typo);
+        }
+        for bad in ["", "abc", "1234", &"1".repeat(25), "1000 0000 0000 0000"] {
+            assert!(validate_account_number(bad).is_err(), "{}", bad);
+        }
+        // 20 digits used to pass without a check digit
+        let unchecked = generate_numeric_string(20);
+        assert!(
+            validate_account_number(&unchecked).is_err(),
+            "{}",
+            unchecked
+        );
+    }
+
+    #[test]
+    fn test_scheme_rejects_bad_config() {
+        assert!(AccountNumberScheme::new("12a", 16).is_err());
+        assert!(AccountNumberScheme::new("1", 11).is_err());
+        assert!(AccountNumberScheme::new("1", 20).is_err());
+        assert!(AccountNumberScheme::new("1234567890", 16).is_err());
+    }
+
+    #[test]
+    fn test_validate_iban() {
+        for iban in ["GB82WEST12345698765432", "DE89370400440532013000"] {
+            validate_iban(iban).unwrap();
+        }
+        for iban in [
+            "GB82WEST12345698765433",
+            "GB28WEST12345698765432",
+            "gb82west12345698765432",
+            "GB82",
+            "GB82 WEST 1234 5698 7654 32",
+        ] {
+            assert!(validate_iban(iban).is_err(), "{}", iban);
+        }
+        assert_eq!(
+            normalize_iban("gb82 west 1234 5698 7654 32"),
+            "GB82WEST12345698765432"
+        );
+        assert!(looks_like_iban("GB82WEST12345698765432"));
+        assert!(!looks_like_iban("1000123456789013"));
+    }
+
+    #[test]
+    fn test_derived_ibans_validate() {
+        let scheme = IbanScheme::new("DE", "37040044", 10).unwrap();
+        // the published example IBAN for this bank code and account
+        assert_eq!(
+            scheme.derive("532013000").unwrap(),
+            "DE89370400440532013000"
+        );
+        let iban = IbanScheme::default()
+            .derive(&AccountNumberScheme::default().generate())
+            .unwrap();
+        validate_iban(&iban).unwrap();
+        assert!(iban.starts_with("RO") && iban.len() == 24);
+        assert!(IbanScheme::default().derive(&"1".repeat(17)).is_err());
+    }
+
+    #[test]
+    fn test_iban_scheme_rejects_bad_config() {
+        assert!(IbanScheme::new("de", "37040044", 10).is_err());
+        assert!(IbanScheme::new("XX", "1", 20).is_err());
+        // German bank codes are eight digits
+        assert!(IbanScheme::new("DE", "3704004", 10).is_err());
+        assert!(IbanScheme::new("DE", "3704004A", 10).is_err());
+        assert!(IbanScheme::new("DE", "10000000", 20).is_err());
+        assert!(IbanScheme::new("NL", "1234", 10).is_err());
+        IbanScheme::new("NL", "ABNA", 10).unwrap();
+        IbanScheme::new("GB", "WEST123456", 8).unwrap();
+        IbanScheme::from_config(&Config::from_env()).unwrap();
+        let short = IbanScheme::new("DE", "37040044", 10).unwrap();
+        assert!(AccountNumberScheme::default().with_iban(short).is_err());
+    }
 }
@@ -0,0 +1,482 @@
+use std::time::Duration;
+
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::error::AppError;
+use crate::models;
+use crate::models::hold::HoldStatus;
+use crate::models::money::{Currency, Money};
+use crate::services::{account_service, ledger_service, transaction_service};
+
+pub const DEFAULT_HOLD_TTL_SECS: i64 = 7 * 24 * 60 * 60;
+pub const MAX_HOLD_TTL_SECS: i64 = 30 * 24 * 60 * 60;
+/// How often the background task looks for expired holds.
+pub const HOLD_EXPIRY_INTERVAL_SECS: u64 = 60;
+
+// matches the format of CURRENT_TIMESTAMP, so it compares correctly as TEXT
+fn now_timestamp() -> String {
+    chrono::Utc::now()
+        .naive_utc()
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+async fn get_hold_in(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    id: i64,
+) -> Result<models::hold::Hold, AppError> {
+    let hold: Option<models::hold::Hold> = sqlx::query_as(
+        "SELECT h.id, h.account_number, h.seller, h.amount, h.currency, h.captured_amount, h.status,
+         h.transaction_id, h.expires_at, h.created_at
+         FROM HOLDS h JOIN ACCOUNTS a ON a.account_number = h.account_number
+         WHERE h.id = ? AND a.user_id = ?;",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?;
+    hold.ok_or_else(|| AppError::NotFound(format!("Hold {} not found", id)))
+}
+
+pub async fn get_hold(
+    db: &SqlitePool,
+    user_id: i64,
+    id: i64,
+) -> Result<models::hold::Hold, AppError> {
+    tracing::info!("Invocation to `get_hold`");
+    let mut conn = db.acquire().await?;
+    get_hold_in(&mut conn, user_id, id).await
+}
+
+/// Fails unless the hold can still be captured or voided.
+fn ensure_pending(hold: &models::hold::Hold) -> Result<(), AppError> {
+    if hold.status != HoldStatus::Pending {
+        let status = match hold.status {
+            HoldStatus::Pending => "pending",
+            HoldStatus::Captured => "captured",
+            HoldStatus::Voided => "voided",
+            HoldStatus::Expired => "expired",
+        };
+        return Err(AppError::Conflict(format!(
+            "Hold {} is already {}",
+            hold.id, status
+        )));
+    }
+    if hold.expires_at <= chrono::Utc::now().naive_utc() {
+        return Err(AppError::Conflict(format!("Hold {} has expired", hold.id)));
+    }
+    Ok(())
+}
+
+async fn release(
+    conn: &mut SqliteConnection,
+    hold: &models::hold::Hold,
+    status: HoldStatus,
+) -> Result<(), AppError> {
+    sqlx::query(
+        "UPDATE HOLDS SET status = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ? AND status = 'pending';",
+    )
+    .bind(status)
+    .bind(hold.id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "UPDATE ACCOUNTS SET held = held - ?, updated_at = CURRENT_TIMESTAMP WHERE account_number = ?;",
+    )
+    .bind(hold.amount.minor_units())
+    .bind(&hold.account_number)
+    .execute(&mut *conn)
+    .await?;
+    account_service::record_balance_changed(conn, &hold.account_number).await?;
+    Ok(())
+}
+
+/// Reserves funds for a card payment, overdraft included. The available
+/// balance drops at once; the balance itself only moves when the hold is
+/// captured.
+pub async fn authorize(
+    db: &SqlitePool,
+    user_id: i64,
+    hold_creation: models::hold::HoldCreation,
+) -> Result<models::hold::Hold, AppError> {
+    tracing::info!("Invocation to `authorize`");
+    let account_number =
+        account_service::resolve_account_number(db, &hold_creation.account_number).await?;
+    let amount = hold_creation.amount;
+    if !amount.is_positive() {
+        return Err(AppError::Validation(
+            "Hold amount must be positive".to_string(),
+        ));
+    }
+    let ttl = hold_creation
+        .expires_in_secs
+        .unwrap_or(DEFAULT_HOLD_TTL_SECS);
+    if !(1..=MAX_HOLD_TTL_SECS).contains(&ttl) {
+        return Err(AppError::Validation(format!(
+            "expires_in_secs must be between 1 and {}",
+            MAX_HOLD_TTL_SECS
+        )));
+    }
+
+    let mut tx = transaction_service::begin_write(db).await?;
+    account_service::ensure_owned(&mut tx, user_id, &account_number).await?;
+    account_service::ensure_active(&mut tx, &account_number).await?;
+    let reserved = sqlx::query(
+        "UPDATE ACCOUNTS SET held = held + ?, updated_at = CURRENT_TIMESTAMP
+         WHERE account_number = ? AND currency = ? AND balance - held + overdraft_limit >= ?;",
+    )
+    .bind(amount.minor_units())
+    .bind(&account_number)
+    .bind(amount.currency())
+    .bind(amount.minor_units())
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if reserved == 0 {
+        let currency: Currency =
+            sqlx::query_scalar("SELECT currency FROM ACCOUNTS WHERE account_number = ?;")
+                .bind(&account_number)
+                .fetch_one(&mut *tx)
+                .await?;
+        // a currency mismatch is a validation error, not a lack of funds
+        Money::zero(currency).checked_sub(amount)?;
+        return Err(AppError::InsufficientFunds);
+    }
+    let id = sqlx::query(
+        "INSERT INTO HOLDS (account_number, seller, amount, currency, expires_at)
+         VALUES (?, ?, ?, ?, datetime('now', ?));",
+    )
+    .bind(&account_number)
+    .bind(&hold_creation.seller)
+    .bind(amount.minor_units())
+    .bind(amount.currency())
+    .bind(format!("+{} seconds", ttl))
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    account_service::record_balance_changed(&mut tx, &account_number).await?;
+    let hold = get_hold_in(&mut tx, user_id, id).await?;
+    tx.commit().await?;
+    Ok(hold)
+}
+
+/// Posts up to the authorized amount as a transaction, charged the same fees as
+/// any other payment, and releases the rest. A hold is captured at most once.
+pub async fn capture(
+    db: &SqlitePool,
+    user_id: i64,
+    id: i64,
+    hold_capture: models::hold::HoldCapture,
+) -> Result<models::hold::CapturedHold, AppError> {
+    tracing::info!("Invocation to `capture`");
+    let mut tx = transaction_service::begin_write(db).await?;
+    let hold = get_hold_in(&mut tx, user_id, id).await?;
+    ensure_pending(&hold)?;
+    account_service::ensure_active(&mut tx, &hold.account_number).await?;
+    let amount: Money = hold_capture.amount.unwrap_or(hold.amount);
+    if !amount.is_positive() || hold.amount.checked_sub(amount)?.is_negative() {
+        return Err(AppError::Validation(format!(
+            "Capture amount must be positive and at most {}",
+            hold.amount
+        )));
+    }
+    release(&mut tx, &hold, HoldStatus::Captured).await?;
+    let receipt = transaction_service::post_payment(
+        &mut tx,
+        hold.account_number,
+        hold.seller,
+        amount,
+        ledger_service::EXTERNAL_SELLERS,
+    )
+    .await?;
+    sqlx::query("UPDATE HOLDS SET captured_amount = ?, transaction_id = ? WHERE id = ?;")
+        .bind(amount.minor_units())
+        .bind(receipt.id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    let hold = get_hold_in(&mut tx, user_id, id).await?;
+    tx.commit().await?;
+    Ok(models::hold::CapturedHold {
+        hold,
+        fees: receipt.fees,
+    })
+}
+
+pub async fn void(db: &SqlitePool, user_id: i64, id: i64) -> Result<models::hold::Hold, AppError> {
+    tracing::info!("Invocation to `void`");
+    let mut tx = transaction_service::begin_write(db).await?;
+    let hold = get_hold_in(&mut tx, user_id, id).await?;
+    ensure_pending(&hold)?;
+    release(&mut tx, &hold, HoldStatus::Voided).await?;
+    let hold = get_hold_in(&mut tx, user_id, id).await?;
+    tx.commit().await?;
+    Ok(hold)
+}
+
+/// Releases every pending hold past its expiry. Returns how many expired.
+pub async fn expire_holds(db: &SqlitePool) -> Result<u64, AppError> {
+    // one timestamp for both statements, so they agree on which holds expired
+    let now = now_timestamp();
+    let mut tx = transaction_service::begin_write(db).await?;
+    let released: Vec<String> = sqlx::query_scalar(
+        "UPDATE ACCOUNTS SET held = held - (
+            SELECT SUM(h.amount) FROM HOLDS h
+            WHERE h.account_number = ACCOUNTS.account_number AND h.status = 'pending' AND h.expires_at <= ?
+         ), updated_at = CURRENT_TIMESTAMP
+         WHERE account_number IN (
+            SELECT account_number FROM HOLDS WHERE status = 'pending' AND expires_at <= ?
+         )
+         RETURNING account_number;",
+    )
+    .bind(&now)
+    .bind(&now)
+    .fetch_all(&mut *tx)
+    .await?;
+    for account_number in &released {
+        account_service::record_balance_changed(&mut tx, account_number).await?;
+    }
+    let expired = sqlx::query(
+        "UPDATE HOLDS SET status = 'expired', updated_at = CURRENT_TIMESTAMP
+         WHERE status = 'pending' AND expires_at <= ?;",
+    )
+    .bind(&now)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    tx.commit().await?;
+    Ok(expired)
+}
+
+/// Background task that expires holds every `HOLD_EXPIRY_INTERVAL_SECS`.
+pub async fn run_expiry(db: SqlitePool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(HOLD_EXPIRY_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        match expire_holds(&db).await {
+            Ok(0) => {}
+            Ok(expired) => tracing::info!("Expired {} holds", expired),
+            Err(err) => tracing::error!("Expiring holds failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models::fee::{FeeKind, FeeRuleCreation};
+    use crate::models::hold::{HoldCapture, HoldCreation};
+    use crate::models::transaction::TransactionCreation;
+    use crate::services::{fee_service, user_service};
+
+    use super::*;
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::Usd)
+    }
+
+    async fn setup_db() -> (SqlitePool, String) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        for username in ["alice", "bob"] {
+            user_service::create_user(
+                &pool,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let account = account_service::create_account(
+            &pool,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        transaction_service::create_transaction(
+            &pool,
+            1,
+            TransactionCreation {
+                account_number: account.account_number.clone(),
+                seller: "Employer".to_string(),
+                amount: usd(-10_000),
+            },
+        )
+        .await
+        .unwrap();
+        (pool, account.account_number)
+    }
+
+    async fn hold(db: &SqlitePool, account_number: &str, minor: i64) -> models::hold::Hold {
+        authorize(
+            db,
+            1,
+            HoldCreation {
+                account_number: account_number.to_string(),
+                seller: "Hotel".to_string(),
+                amount: usd(minor),
+                expires_in_secs: None,
+            },
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn balances(db: &SqlitePool, account_number: &str) -> (Money, Money) {
+        let account =
+            account_service::get_account_by_account_number(db, account_number.to_string())
+                .await
+                .unwrap();
+        (account.balance, account.available_balance)
+    }
+
+    #[tokio::test]
+    async fn test_authorize_reduces_available_balance_only() {
+        let (db, anumber) = setup_db().await;
+        let h = hold(&db, &anumber, 6_000).await;
+        assert_eq!(h.status, HoldStatus::Pending);
+        assert_eq!(balances(&db, &anumber).await, (usd(10_000), usd(4_000)));
+
+        // neither another hold nor a plain debit may spend the reserved funds
+        let res = authorize(
+            &db,
+            1,
+            HoldCreation {
+                account_number: anumber.clone(),
+                seller: "Hotel".to_string(),
+                amount: usd(4_001),
+                expires_in_secs: None,
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::InsufficientFunds)));
+        let res = transaction_service::create_transaction(
+            &db,
+            1,
+            TransactionCreation {
+                account_number: anumber.clone(),
+                seller: "Grocer".to_string(),
+                amount: usd(4_001),
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::InsufficientFunds)));
+        // and no one else can see the hold
+        assert!(matches!(
+            get_hold(&db, 2, h.id).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_partial_capture_posts_and_releases_the_rest() {
+        let (db, anumber) = setup_db().await;
+        let h = hold(&db, &anumber, 6_000).await;
+        let captured = capture(
+            &db,
+            1,
+            h.id,
+            HoldCapture {
+                amount: Some(usd(4_500)),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(captured.hold.status, HoldStatus::Captured);
+        assert_eq!(captured.hold.captured_amount, usd(4_500));
+        assert!(captured.hold.transaction_id.is_some());
+        assert!(captured.fees.is_empty());
+        assert_eq!(balances(&db, &anumber).await, (usd(5_500), usd(5_500)));
+        // a hold is captured once
+        let again = capture(&db, 1, h.id, HoldCapture::default()).await;
+        assert!(matches!(again, Err(AppError::Conflict(_))));
+        assert!(ledger_service::reconcile(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_capture_charges_per_transaction_fees() {
+        let (db, anumber) = setup_db().await;
+        fee_service::create_fee_rule(
+            &db,
+            FeeRuleCreation {
+                product: "checking".to_string(),
+                kind: FeeKind::PerTransaction,
+                amount: usd(25),
+                rate: None,
+                waive_above: None,
+            },
+        )
+        .await
+        .unwrap();
+        let h = hold(&db, &anumber, 1_000).await;
+        let captured = capture(&db, 1, h.id, HoldCapture::default()).await.unwrap();
+        assert_eq!(captured.fees.len(), 1);
+        assert_eq!(captured.fees[0].kind, FeeKind::PerTransaction);
+        assert_eq!(captured.fees[0].amount, usd(25));
+        assert_eq!(balances(&db, &anumber).await, (usd(8_975), usd(8_975)));
+    }
+
+    #[tokio::test]
+    async fn test_capture_rejects_more_than_authorized() {
+        let (db, anumber) = setup_db().await;
+        let h = hold(&db, &anumber, 1_000).await;
+        for amount in [usd(1_001), usd(0), Money::new(500, Currency::Eur)] {
+            let res = capture(
+                &db,
+                1,
+                h.id,
+                HoldCapture {
+                    amount: Some(amount),
+                },
+            )
+            .await;
+            assert!(matches!(res, Err(AppError::Validation(_))), "{}", amount);
+        }
+        assert_eq!(balances(&db, &anumber).await, (usd(10_000), usd(9_000)));
+    }
+
+    #[tokio::test]
+    async fn test_void_releases_the_hold() {
+        let (db, anumber) = setup_db().await;
+        let h = hold(&db, &anumber, 6_000).await;
+        let voided = void(&db, 1, h.id).await.unwrap();
+        assert_eq!(voided.status, HoldStatus::Voided);
+        assert_eq!(balances(&db, &anumber).await, (usd(10_000), usd(10_000)));
+        let res = capture(&db, 1, h.id, HoldCapture::default()).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_expired_holds_are_released() {
+        let (db, anumber) = setup_db().await;
+        let fresh = hold(&db, &anumber, 1_000).await;
+        let stale = hold(&db, &anumber, 2_000).await;
+        sqlx::query("UPDATE HOLDS SET expires_at = datetime('now', '-1 seconds') WHERE id = ?;")
+            .bind(stale.id)
+            .execute(&db)
+            .await
+            .unwrap();
+        let res = capture(&db, 1, stale.id, HoldCapture::default()).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+
+        assert_eq!(expire_holds(&db).await.unwrap(), 1);
+        assert_eq!(expire_holds(&db).await.unwrap(), 0);
+        assert_eq!(
+            get_hold(&db, 1, stale.id).await.unwrap().status,
+            HoldStatus::Expired
+        );
+        assert_eq!(
+            get_hold(&db, 1, fresh.id).await.unwrap().status,
+            HoldStatus::Pending
+        );
+        assert_eq!(balances(&db, &anumber).await, (usd(10_000), usd(9_000)));
+    }
+}
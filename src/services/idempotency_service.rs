@@ -0,0 +1,477 @@
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Acquire, Row, SqliteConnection, SqlitePool};
+
+use crate::error::{AppError, ErrorBody};
+use crate::services::transaction_service;
+
+/// Keys are forgotten after this long, after which the same key starts a new request.
+pub const IDEMPOTENCY_KEY_TTL_HOURS: i64 = 24;
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+/// How long a claimed key may go without a response before a retry may assume
+/// the original request was abandoned and run it again.
+pub const IDEMPOTENCY_LEASE_SECS: i64 = 60;
+/// Keys the scheduled payments worker runs its occurrences under. Clients may
+/// not use them, or a request could replay or block a schedule's payment.
+pub const SCHEDULE_KEY_PREFIX: &str = "schedule-";
+
+/// A response as it was (or will be) sent for an idempotent request.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StoredResponse {
+    pub status: u16,
+    pub body: String, // JSON
+    pub replayed: bool,
+}
+
+pub fn fingerprint(request: &impl Serialize) -> Result<String, AppError> {
+    let bytes = serde_json::to_vec(request)
+        .map_err(|err| AppError::Internal(format!("Could not serialize request: {}", err)))?;
+    Ok(Sha256::digest(&bytes)
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect())
+}
+
+fn validate_key(key: &str) -> Result<(), AppError> {
+    if key.is_empty()
+        || key.len() > MAX_IDEMPOTENCY_KEY_LEN
+        || !key.chars().all(|c| c.is_ascii_graphic())
+    {
+        return Err(AppError::Validation(format!(
+            "Idempotency-Key must be 1 to {} printable ASCII characters",
+            MAX_IDEMPOTENCY_KEY_LEN
+        )));
+    }
+    Ok(())
+}
+
+/// Checks a key sent by a client, which must also stay out of the namespace
+/// reserved for scheduled payments.
+pub fn validate_client_key(key: &str) -> Result<(), AppError> {
+    validate_key(key)?;
+    if key.starts_with(SCHEDULE_KEY_PREFIX) {
+        return Err(AppError::Validation(format!(
+            "Idempotency-Key must not start with {:?}",
+            SCHEDULE_KEY_PREFIX
+        )));
+    }
+    Ok(())
+}
+
+/// Runs `op` at most once per (user, key). A retry with the same body replays
+/// the stored response; a retry with a different body is rejected with 422,
+/// and one arriving while the original is still running gets 409.
+///
+/// `op` runs in a database transaction that also stores its response, so the
+/// change and the response are committed together or not at all. A claim whose
+/// request never finished, because it was cancelled or its commit failed, is
+/// taken over by a retry once `IDEMPOTENCY_LEASE_SECS` have passed.
+///
+/// Client errors are stored and replayed like successes, with whatever `op`
+/// wrote before failing rolled back. Server errors are not, and release the
+/// key so the client can try again.
+pub async fn run<T, F>(
+    pool: &SqlitePool,
+    user_id: i64,
+    key: &str,
+    request: &impl Serialize,
+    op: F,
+) -> Result<StoredResponse, AppError>
+where
+    T: Serialize,
+    F: AsyncFnOnce(&mut SqliteConnection) -> Result<T, AppError>,
+{
+    tracing::info!("Invocation to `idempotency_service::run`");
+    validate_key(key)?;
+    let fingerprint = fingerprint(request)?;
+    sqlx::query(
+        "DELETE FROM IDEMPOTENCY_KEYS WHERE user_id = ? AND idempotency_key = ?
+         AND created_at < datetime('now', ?);",
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(format!("-{} hours", IDEMPOTENCY_KEY_TTL_HOURS))
+    .execute(pool)
+    .await?;
+    // claiming the key first means two concurrent retries cannot both run `op`
+    let claimed = sqlx::query(
+        "INSERT INTO IDEMPOTENCY_KEYS (user_id, idempotency_key, fingerprint) VALUES (?, ?, ?)
+         ON CONFLICT (user_id, idempotency_key) DO NOTHING;",
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(&fingerprint)
+    .execute(pool)
+    .await?
+    .rows_affected()
+        == 1;
+    if !claimed && !take_over(pool, user_id, key, &fingerprint).await? {
+        return replay(pool, user_id, key, &fingerprint).await;
+    }
+
+    let mut tx = transaction_service::begin_write(pool).await?;
+    // a savepoint, so a client error can undo `op` and still store its response
+    let mut attempt = tx.begin().await?;
+    let (status, body) = match op(&mut attempt).await {
+        Ok(value) => {
+            attempt.commit().await?;
+            (
+                200,
+                serde_json::to_string(&value).map_err(|err| {
+                    AppError::Internal(format!("Could not serialize response: {}", err))
+                })?,
+            )
+        }
+        Err(err) if err.status().is_server_error() => {
+            attempt.rollback().await?;
+            tx.rollback().await?;
+            release(pool, user_id, key).await?;
+            return Err(err);
+        }
+        Err(err) => {
+            attempt.rollback().await?;
+            (
+                err.status().as_u16(),
+                serde_json::to_string(&ErrorBody {
+                    code: err.code().to_string(),
+                    message: err.to_string(),
+                })
+                .map_err(|err| AppError::Internal(format!("Could not serialize error: {}", err)))?,
+            )
+        }
+    };
+    let stored = sqlx::query(
+        "UPDATE IDEMPOTENCY_KEYS SET response_status = ?, response_body = ?
+         WHERE user_id = ? AND idempotency_key = ? AND response_status IS NULL;",
+    )
+    .bind(status as i64)
+    .bind(&body)
+    .bind(user_id)
+    .bind(key)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if stored == 0 {
+        // the claim was taken over after our lease ran out and that retry won
+        tx.rollback().await?;
+        return replay(pool, user_id, key, &fingerprint).await;
+    }
+    tx.commit().await?;
+    Ok(StoredResponse {
+        status,
+        body,
+        replayed: false,
+    })
+}
+
+/// Claims a key whose original request has held it for longer than the lease
+/// without storing a response. Returns whether the claim is now ours.
+async fn take_over(
+    pool: &SqlitePool,
+    user_id: i64,
+    key: &str,
+    fingerprint: &str,
+) -> Result<bool, AppError> {
+    let taken = sqlx::query(
+        "UPDATE IDEMPOTENCY_KEYS SET created_at = CURRENT_TIMESTAMP
+         WHERE user_id = ? AND idempotency_key = ? AND fingerprint = ?
+         AND response_status IS NULL AND created_at < datetime('now', ?);",
+    )
+    .bind(user_id)
+    .bind(key)
+    .bind(fingerprint)
+    .bind(format!("-{} seconds", IDEMPOTENCY_LEASE_SECS))
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(taken == 1)
+}
+
+async fn replay(
+    pool: &SqlitePool,
+    user_id: i64,
+    key: &str,
+    fingerprint: &str,
+) -> Result<StoredResponse, AppError> {
+    let row = sqlx::query(
+        "SELECT fingerprint, response_status, response_body FROM IDEMPOTENCY_KEYS
+         WHERE user_id = ? AND idempotency_key = ?;",
+    )
+    .bind(user_id)
+    .bind(key)
+    .fetch_one(pool)
+    .await?;
+    let stored_fingerprint: String = row.try_get("fingerprint")?;
+    if stored_fingerprint != fingerprint {
+        return Err(AppError::Validation(
+            "Idempotency-Key was already used with a different request".to_string(),
+        ));
+    }
+    let status: Option<i64> = row.try_get("response_status")?;
+    let body: Option<String> = row.try_get("response_body")?;
+    match (status, body) {
+        (Some(status), Some(body)) => Ok(StoredResponse {
+            status: status as u16,
+            body,
+            replayed: true,
+        }),
+        _ => Err(AppError::Conflict(
+            "A request with this Idempotency-Key is still in progress".to_string(),
+        )),
+    }
+}
+
+async fn release(pool: &SqlitePool, user_id: i64, key: &str) -> Result<(), AppError> {
+    sqlx::query("DELETE FROM IDEMPOTENCY_KEYS WHERE user_id = ? AND idempotency_key = ?;")
+        .bind(user_id)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models;
+    use crate::models::money::{Currency, Money};
+    use crate::models::transaction::TransactionCreation;
+    use crate::services::{account_service, transaction_service, user_service};
+
+    use super::*;
+
+    async fn setup_db() -> (SqlitePool, String) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account = account_service::create_account(
+            &pool,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        (pool, account.account_number)
+    }
+
+    async fn post(
+        pool: &SqlitePool,
+        key: &str,
+        tx: TransactionCreation,
+    ) -> Result<StoredResponse, AppError> {
+        run(pool, 1, key, &tx, async |conn| {
+            transaction_service::create_transaction_in(conn, 1, tx.clone()).await
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_retry_is_applied_once() {
+        let (pool, anumber) = setup_db().await;
+        let tx = TransactionCreation {
+            account_number: anumber.clone(),
+            seller: "Employer".to_string(),
+            amount: Money::new(-1000, Currency::Usd),
+        };
+        let first = post(&pool, "key-1", tx.clone()).await.unwrap();
+        let second = post(&pool, "key-1", tx.clone()).await.unwrap();
+        assert!(!first.replayed);
+        assert!(second.replayed);
+        assert_eq!(first.status, second.status);
+        assert_eq!(first.body, second.body);
+
+        let transactions = transaction_service::get_transactions(&pool, 1, &Default::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(transactions.len(), 1);
+        let account = account_service::get_account_by_account_number(&pool, anumber)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, Money::new(1000, Currency::Usd));
+    }
+
+    #[tokio::test]
+    async fn test_reused_key_with_different_body_is_rejected() {
+        let (pool, anumber) = setup_db().await;
+        let tx = TransactionCreation {
+            account_number: anumber.clone(),
+            seller: "Employer".to_string(),
+            amount: Money::new(-1000, Currency::Usd),
+        };
+        post(&pool, "key-1", tx.clone()).await.unwrap();
+        let res = post(
+            &pool,
+            "key-1",
+            TransactionCreation {
+                amount: Money::new(-2000, Currency::Usd),
+                ..tx
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_replayed() {
+        let (pool, anumber) = setup_db().await;
+        let tx = TransactionCreation {
+            account_number: anumber.clone(),
+            seller: "Seller".to_string(),
+            amount: Money::new(500, Currency::Usd),
+        };
+        let first = post(&pool, "key-1", tx.clone()).await.unwrap();
+        assert_eq!(first.status, 402);
+        // funds arriving later must not change the answer for the same key
+        transaction_service::create_transaction(
+            &pool,
+            1,
+            TransactionCreation {
+                amount: Money::new(-1000, Currency::Usd),
+                ..tx.clone()
+            },
+        )
+        .await
+        .unwrap();
+        let second = post(&pool, "key-1", tx).await.unwrap();
+        assert!(second.replayed);
+        assert_eq!(second.status, 402);
+        assert_eq!(first.body, second.body);
+    }
+
+    #[tokio::test]
+    async fn test_in_flight_key_conflicts() {
+        let (pool, anumber) = setup_db().await;
+        let tx = TransactionCreation {
+            account_number: anumber,
+            seller: "Seller".to_string(),
+            amount: Money::new(-500, Currency::Usd),
+        };
+        sqlx::query(
+            "INSERT INTO IDEMPOTENCY_KEYS (user_id, idempotency_key, fingerprint) VALUES (1, 'key-1', ?);",
+        )
+        .bind(fingerprint(&tx).unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+        let res = post(&pool, "key-1", tx).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_request_is_rolled_back_and_retried_after_the_lease() {
+        let (pool, anumber) = setup_db().await;
+        let tx = TransactionCreation {
+            account_number: anumber.clone(),
+            seller: "Employer".to_string(),
+            amount: Money::new(-1000, Currency::Usd),
+        };
+        // the client hangs up after the deposit is posted but before it commits
+        let cancelled = tokio::time::timeout(
+            std::time::Duration::from_millis(100),
+            run(&pool, 1, "key-1", &tx, async |conn| {
+                let receipt =
+                    transaction_service::create_transaction_in(conn, 1, tx.clone()).await?;
+                std::future::pending::<()>().await;
+                Ok(receipt)
+            }),
+        )
+        .await;
+        assert!(cancelled.is_err());
+        let account = account_service::get_account_by_account_number(&pool, anumber.clone())
+            .await
+            .unwrap();
+        assert_eq!(account.balance, Money::new(0, Currency::Usd));
+
+        // still leased to the cancelled request
+        let res = post(&pool, "key-1", tx.clone()).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+
+        sqlx::query("UPDATE IDEMPOTENCY_KEYS SET created_at = datetime('now', ?);")
+            .bind(format!("-{} seconds", IDEMPOTENCY_LEASE_SECS + 1))
+            .execute(&pool)
+            .await
+            .unwrap();
+        let retried = post(&pool, "key-1", tx.clone()).await.unwrap();
+        assert!(!retried.replayed);
+        assert_eq!(retried.status, 200);
+        let replayed = post(&pool, "key-1", tx).await.unwrap();
+        assert!(replayed.replayed);
+        assert_eq!(replayed.body, retried.body);
+        let account = account_service::get_account_by_account_number(&pool, anumber)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, Money::new(1000, Currency::Usd));
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_roll_back_what_op_wrote() {
+        let (pool, anumber) = setup_db().await;
+        let tx = TransactionCreation {
+            account_number: anumber.clone(),
+            seller: "Employer".to_string(),
+            amount: Money::new(-1000, Currency::Usd),
+        };
+        let stored = run(&pool, 1, "key-1", &tx, async |conn| {
+            transaction_service::create_transaction_in(conn, 1, tx.clone()).await?;
+            Err::<(), _>(AppError::Validation("changed my mind".to_string()))
+        })
+        .await
+        .unwrap();
+        assert_eq!(stored.status, 422);
+        let account = account_service::get_account_by_account_number(&pool, anumber)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, Money::new(0, Currency::Usd));
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_release_the_key() {
+        let (pool, _) = setup_db().await;
+        let res: Result<StoredResponse, AppError> = run(&pool, 1, "key-1", &"body", async |_| {
+            Err::<(), _>(AppError::Internal("boom".to_string()))
+        })
+        .await;
+        assert!(res.is_err());
+        let retried = run(&pool, 1, "key-1", &"body", async |_| Ok("done"))
+            .await
+            .unwrap();
+        assert!(!retried.replayed);
+        assert_eq!(retried.body, "\"done\"");
+    }
+
+    #[tokio::test]
+    async fn test_rejects_invalid_keys() {
+        let (pool, _) = setup_db().await;
+        for key in ["", "has space", &"x".repeat(256)] {
+            let res = run(&pool, 1, key, &"body", async |_| Ok(())).await;
+            assert!(matches!(res, Err(AppError::Validation(_))));
+        }
+    }
+
+    #[test]
+    fn test_clients_cannot_use_schedule_keys() {
+        assert!(validate_client_key("key-1").is_ok());
+        assert!(validate_client_key("Schedule-1-0-0").is_ok());
+        assert!(matches!(
+            validate_client_key("schedule-1-0-0"),
+            Err(AppError::Validation(_))
+        ));
+        assert!(matches!(
+            validate_client_key(""),
+            Err(AppError::Validation(_))
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+use std::time::Duration;
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use sqlx::{Row, SqlitePool};
+
+use crate::error::AppError;
+use crate::models;
+use crate::models::account::AccountStatus;
+use crate::models::interest::DayCount;
+use crate::models::money::{Currency, Money};
+use crate::services::transaction_service::PostingLink;
+use crate::services::{account_service, ledger_service, transaction_service};
+
+/// Decimals an annual rate may have.
+pub const MAX_RATE_DECIMALS: usize = 8;
+/// How often the background task accrues and posts interest.
+pub const INTEREST_JOB_INTERVAL_SECS: u64 = 60 * 60;
+/// Accruals are kept in millionths of a minor unit and rounded when posted.
+const MICROS_PER_MINOR_UNIT: i128 = 1_000_000;
+
+/// Reads a rate such as "0.0425" as an integer number of 10^-MAX_RATE_DECIMALS.
+fn parse_rate(rate: &str) -> Result<i128, AppError> {
+    let invalid = || {
+        AppError::Validation(format!(
+            "annual_rate must be a decimal fraction below 1 with at most {} decimals, e.g. \"0.0425\"",
+            MAX_RATE_DECIMALS
+        ))
+    };
+    let (whole, fraction) = rate.split_once('.').unwrap_or((rate, ""));
+    if whole != "0"
+        || fraction.len() > MAX_RATE_DECIMALS
+        || (rate.contains('.') && fraction.is_empty())
+        || !fraction.chars().all(|c| c.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+    let padded = format!("{:0<width$}", fraction, width = MAX_RATE_DECIMALS);
+    padded.parse().map_err(|_| invalid())
+}
+
+fn days_in_year(day_count: DayCount, date: NaiveDate) -> i128 {
+    match day_count {
+        DayCount::Act365 => 365,
+        DayCount::Act360 => 360,
+        DayCount::ActAct if date.leap_year() => 366,
+        DayCount::ActAct => 365,
+    }
+}
+
+/// One day's interest on `balance` minor units, in millionths of a minor unit,
+/// rounded half away from zero. Nothing accrues on a balance at or below zero.
+fn daily_interest(balance: i64, rate: i128, day_count: DayCount, date: NaiveDate) -> i64 {
+    if balance <= 0 {
+        return 0;
+    }
+    let numerator = balance as i128 * rate * MICROS_PER_MINOR_UNIT;
+    let denominator = 10i128.pow(MAX_RATE_DECIMALS as u32) * days_in_year(day_count, date);
+    let rounded = (numerator + denominator / 2) / denominator;
+    i64::try_from(rounded).unwrap_or(i64::MAX)
+}
+
+pub async fn get_products(db: &SqlitePool) -> Result<Vec<models::interest::Product>, AppError> {
+    tracing::info!("Invocation to `get_products`");
+    let products: Vec<models::interest::Product> = sqlx::query_as(
+        "SELECT code, name, annual_rate, day_count, created_at FROM PRODUCTS ORDER BY code;",
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(products)
+}
+
+pub async fn create_product(
+    db: &SqlitePool,
+    product_creation: models::interest::ProductCreation,
+) -> Result<models::interest::Product, AppError> {
+    tracing::info!("Invocation to `create_product`");
+    if product_creation.code.is_empty() || product_creation.name.is_empty() {
+        return Err(AppError::Validation("Missing required fields".to_string()));
+    }
+    parse_rate(&product_creation.annual_rate)?;
+    sqlx::query("INSERT INTO PRODUCTS (code, name, annual_rate, day_count) VALUES (?, ?, ?, ?);")
+        .bind(&product_creation.code)
+        .bind(&product_creation.name)
+        .bind(&product_creation.annual_rate)
+        .bind(product_creation.day_count)
+        .execute(db)
+        .await
+        .map_err(|err| match AppError::from(err) {
+            AppError::Conflict(_) => {
+                AppError::Conflict(format!("Product {} already exists", product_creation.code))
+            }
+            other => other,
+        })?;
+    let product: models::interest::Product = sqlx::query_as(
+        "SELECT code, name, annual_rate, day_count, created_at FROM PRODUCTS WHERE code = ?;",
+    )
+    .bind(&product_creation.code)
+    .fetch_one(db)
+    .await?;
+    Ok(product)
+}
+
+pub async fn get_accruals(
+    db: &SqlitePool,
+    user_id: i64,
+    account_number: &str,
+    query: &models::interest::InterestQuery,
+) -> Result<Vec<models::interest::InterestAccrual>, AppError> {
+    tracing::info!("Invocation to `get_accruals`");
+    let account_number = account_service::resolve_account_number(db, account_number).await?;
+    let mut conn = db.acquire().await?;
+    account_service::ensure_owned(&mut conn, user_id, &account_number).await?;
+    let accruals: Vec<models::interest::InterestAccrual> = sqlx::query_as(
+        "SELECT accrual_date, balance, currency, annual_rate, day_count, accrued_micros, posted,
+         transaction_id
+         FROM INTEREST_ACCRUALS
+         WHERE account_number = ? AND accrual_date >= COALESCE(?, accrual_date)
+         AND accrual_date <= COALESCE(?, accrual_date)
+         ORDER BY accrual_date;",
+    )
+    .bind(&account_number)
+    .bind(query.from)
+    .bind(query.to)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(accruals)
+}
+
+/// Accrues a day's interest on every account whose product pays any, for
+/// each day before `today` that has not been accrued yet. A day's interest is
+/// worked out from the balance at its end, so a late run accrues the same as
+/// a punctual one. Returns how many days were accrued.
+pub async fn accrue_interest(db: &SqlitePool, today: NaiveDate) -> Result<u64, AppError> {
+    let accounts = sqlx::query(
+        "SELECT a.account_number, a.created_at, p.annual_rate, p.day_count,
+            (SELECT MAX(i.accrual_date) FROM INTEREST_ACCRUALS i
+             WHERE i.account_number = a.account_number) AS last_accrual
+         FROM ACCOUNTS a JOIN PRODUCTS p ON p.code = a.product WHERE a.status != 'closed';",
+    )
+    .fetch_all(db)
+    .await?;
+    let mut accrued = 0;
+    for account in &accounts {
+        let account_number: String = account.try_get("account_number")?;
+        let annual_rate: String = account.try_get("annual_rate")?;
+        let rate = parse_rate(&annual_rate)?;
+        let day_count: DayCount = account.try_get("day_count")?;
+        let created_at: NaiveDateTime = account.try_get("created_at")?;
+        let last_accrual: Option<NaiveDate> = account.try_get("last_accrual")?;
+        let first = match last_accrual {
+            Some(last) => last.succ_opt().unwrap_or(last),
+            None => created_at.date(),
+        };
+        if rate == 0 || first >= today {
+            continue;
+        }
+
+        let mut tx = transaction_service::begin_write(db).await?;
+        let row = sqlx::query("SELECT balance, currency FROM ACCOUNTS WHERE account_number = ?;")
+            .bind(&account_number)
+            .fetch_one(&mut *tx)
+            .await?;
+        let balance: i64 = row.try_get("balance")?;
+        let currency: Currency = row.try_get("currency")?;
+        // walking back from today's balance, undoing each day's transactions
+        let since: Vec<(i64, NaiveDateTime)> = sqlx::query_as(
+            "SELECT amount, created_at FROM TRANSACTIONS
+             WHERE account_number = ? AND created_at >= ? ORDER BY created_at DESC;",
+        )
+        .bind(&account_number)
+        .bind(first.succ_opt().unwrap_or(first).and_hms_opt(0, 0, 0))
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut end_of_day = balance;
+        let mut later = since.iter().peekable();
+        let mut day = today.pred_opt().unwrap_or(today);
+        let mut days = Vec::new();
+        while day >= first {
+            // transactions after the end of `day` are not in its balance
+            while let Some((amount, _)) = later.next_if(|(_, at)| at.date() > day) {
+                end_of_day += amount;
+            }
+            days.push((day, end_of_day));
+            match day.pred_opt() {
+                Some(previous) => day = previous,
+                None => break,
+            }
+        }
+        for (day, end_of_day) in days.into_iter().rev() {
+            accrued += sqlx::query(
+                "INSERT INTO INTEREST_ACCRUALS (account_number, accrual_date, balance, currency,
+                 annual_rate, day_count, accrued_micros) VALUES (?, ?, ?, ?, ?, ?, ?)
+                 ON CONFLICT (account_number, accrual_date) DO NOTHING;",
+            )
+            .bind(&account_number)
+            .bind(day)
+            .bind(end_of_day)
+            .bind(currency)
+            .bind(&annual_rate)
+            .bind(day_count)
+            .bind(daily_interest(end_of_day, rate, day_count, day))
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        tx.commit().await?;
+    }
+    Ok(accrued)
+}
+
+/// Credits each account with its accrued interest for every month that ended
+/// before `today`, as one transaction per account and month, rounded half up
+/// to the minor unit. Returns how many transactions were posted.
+pub async fn post_interest(db: &SqlitePool, today: NaiveDate) -> Result<u64, AppError> {
+    let month_start = today.with_day(1).unwrap_or(today);
+    let months = sqlx::query(
+        "SELECT i.account_number, i.currency, substr(i.accrual_date, 1, 7) AS month,
+            SUM(i.accrued_micros) AS accrued_micros, a.status
+         FROM INTEREST_ACCRUALS i JOIN ACCOUNTS a ON a.account_number = i.account_number
+         WHERE i.posted = 0 AND i.accrual_date < ?
+         GROUP BY i.account_number, i.currency, month ORDER BY month, i.account_number;",
+    )
+    .bind(month_start)
+    .fetch_all(db)
+    .await?;
+    let mut posted = 0;
+    for month in &months {
+        let account_number: String = month.try_get("account_number")?;
+        let currency: Currency = month.try_get("currency")?;
+        let label: String = month.try_get("month")?;
+        let micros: i64 = month.try_get("accrued_micros")?;
+        let status: AccountStatus = month.try_get("status")?;
+        let minor = (micros as i128 + MICROS_PER_MINOR_UNIT / 2) / MICROS_PER_MINOR_UNIT;
+        let amount = Money::new(
+            i64::try_from(minor).map_err(|_| AppError::Internal("Interest overflow".into()))?,
+            currency,
+        );
+
+        let mut tx = transaction_service::begin_write(db).await?;
+        // closed accounts forfeit what they had not been credited yet
+        let transaction_id = if amount.is_positive() && status != AccountStatus::Closed {
+            Some(
+                transaction_service::post_to_account(
+                    &mut tx,
+                    &account_number,
+                    &format!("Interest {}", label),
+                    amount.checked_neg()?,
+                    ledger_service::INTEREST,
+                    PostingLink::None,
+                )
+                .await?,
+            )
+        } else {
+            None
+        };
+        let marked = sqlx::query(
+            "UPDATE INTEREST_ACCRUALS SET posted = 1, transaction_id = ?
+             WHERE account_number = ? AND posted = 0 AND substr(accrual_date, 1, 7) = ?;",
+        )
+        .bind(transaction_id)
+        .bind(&account_number)
+        .bind(&label)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+        if marked == 0 {
+            // another run posted this month first
+            tx.rollback().await?;
+            continue;
+        }
+        tx.commit().await?;
+        posted += transaction_id.is_some() as u64;
+    }
+    Ok(posted)
+}
+
+/// Background task that accrues interest for finished days and posts it for
+/// finished months, every `INTEREST_JOB_INTERVAL_SECS`.
+pub async fn run_interest(db: SqlitePool) {
+    let mut interval = tokio::time::interval(Duration::from_secs(INTEREST_JOB_INTERVAL_SECS));
+    loop {
+        interval.tick().await;
+        let today = chrono::Utc::now().date_naive();
+        match accrue_interest(&db, today).await {
+            Ok(0) => {}
+            Ok(days) => tracing::info!("Accrued {} days of interest", days),
+            Err(err) => tracing::error!("Accruing interest failed: {}", err),
+        }
+        match post_interest(&db, today).await {
+            Ok(0) => {}
+            Ok(posted) => tracing::info!("Posted interest to {} accounts", posted),
+            Err(err) => tracing::error!("Posting interest failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models::interest::{InterestQuery, ProductCreation};
+    use crate::models::transaction::TransactionCreation;
+    use crate::services::user_service;
+
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "alice".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        create_product(
+            &pool,
+            ProductCreation {
+                code: "saver".to_string(),
+                name: "Saver".to_string(),
+                annual_rate: "0.0365".to_string(),
+                day_count: DayCount::Act365,
+            },
+        )
+        .await
+        .unwrap();
+        pool
+    }
+
+    async fn open(db: &SqlitePool, product: &str, opened: &str) -> String {
+        let account = account_service::create_account(
+            db,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: Some(product.to_string()),
+            },
+        )
+        .await
+        .unwrap();
+        sqlx::query("UPDATE ACCOUNTS SET created_at = ? WHERE account_number = ?;")
+            .bind(opened)
+            .bind(&account.account_number)
+            .execute(db)
+            .await
+            .unwrap();
+        account.account_number
+    }
+
+    async fn deposit(db: &SqlitePool, account_number: &str, minor: i64, at: &str) {
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account_number.to_string(),
+                seller: "Employer".to_string(),
+                amount: Money::new(-minor, Currency::Usd),
+            },
+        )
+        .await
+        .unwrap();
+        sqlx::query(
+            "UPDATE TRANSACTIONS SET created_at = ? WHERE id = (SELECT MAX(id) FROM TRANSACTIONS);",
+        )
+        .bind(at)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_parse_rate() {
+        assert_eq!(parse_rate("0.0425").unwrap(), 4_250_000);
+        assert_eq!(parse_rate("0").unwrap(), 0);
+        for bad in ["4.25", "1", "0.", ".5", "0.123456789", "-0.01", "0.0x"] {
+            assert!(
+                matches!(parse_rate(bad), Err(AppError::Validation(_))),
+                "{}",
+                bad
+            );
+        }
+    }
+
+    #[test]
+    fn test_day_count_conventions() {
+        let rate = parse_rate("0.0365").unwrap();
+        // 10,000.00 at 3.65% is exactly 1.00 a day over 365 days
+        assert_eq!(
+            daily_interest(1_000_000, rate, DayCount::Act365, date(2028, 2, 29)),
+            100_000_000
+        );
+        // 100 * 365 / 360 = 101.3888...
+        assert_eq!(
+            daily_interest(1_000_000, rate, DayCount::Act360, date(2027, 6, 1)),
+            101_388_889
+        );
+        // 100 * 365 / 366 = 99.7267...
+        assert_eq!(
+            daily_interest(1_000_000, rate, DayCount::ActAct, date(2028, 6, 1)),
+            99_726_776
+        );
+        assert_eq!(
+            daily_interest(1_000_000, rate, DayCount::ActAct, date(2027, 6, 1)),
+            100_000_000
+        );
+        assert_eq!(
+            daily_interest(-500, rate, DayCount::Act365, date(2027, 6, 1)),
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_accrues_daily_and_posts_monthly() {
+        let db = setup_db().await;
+        let saver = open(&db, "saver", "2026-01-30 10:00:00").await;
+        let checking = open(&db, "checking", "2026-01-30 10:00:00").await;
+        deposit(&db, &saver, 1_000_000, "2026-01-30 12:00:00").await;
+        deposit(&db, &saver, 100_000, "2026-02-01 12:00:00").await;
+        deposit(&db, &checking, 1_000_000, "2026-01-30 12:00:00").await;
+
+        let today = date(2026, 2, 3);
+        assert_eq!(accrue_interest(&db, today).await.unwrap(), 4);
+        assert_eq!(accrue_interest(&db, today).await.unwrap(), 0);
+        assert_eq!(post_interest(&db, today).await.unwrap(), 1);
+        assert_eq!(post_interest(&db, today).await.unwrap(), 0);
+
+        let accruals = get_accruals(&db, 1, &saver, &InterestQuery::default())
+            .await
+            .unwrap();
+        let days: Vec<(NaiveDate, i64, &str, bool)> = accruals
+            .iter()
+            .map(|a| {
+                (
+                    a.accrual_date,
+                    a.balance.minor_units(),
+                    a.accrued.as_str(),
+                    a.posted,
+                )
+            })
+            .collect();
+        assert_eq!(
+            days,
+            vec![
+                (date(2026, 1, 30), 1_000_000, "1.00000000", true),
+                (date(2026, 1, 31), 1_000_000, "1.00000000", true),
+                (date(2026, 2, 1), 1_100_000, "1.10000000", false),
+                (date(2026, 2, 2), 1_100_000, "1.10000000", false),
+            ]
+        );
+        assert!(accruals[0].transaction_id.is_some());
+        let account = account_service::get_account_by_account_number(&db, saver.clone())
+            .await
+            .unwrap();
+        assert_eq!(account.balance, Money::new(1_100_200, Currency::Usd));
+        assert!(ledger_service::reconcile(&db).await.unwrap().is_empty());
+
+        let february = InterestQuery {
+            from: Some(date(2026, 2, 1)),
+            to: None,
+        };
+        assert_eq!(
+            get_accruals(&db, 1, &saver, &february).await.unwrap().len(),
+            2
+        );
+        assert!(
+            get_accruals(&db, 1, &checking, &InterestQuery::default())
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        assert!(matches!(
+            get_accruals(&db, 2, &saver, &InterestQuery::default()).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_product_rejects_bad_input() {
+        let db = setup_db().await;
+        let product = |code: &str, rate: &str| ProductCreation {
+            code: code.to_string(),
+            name: "Bonus".to_string(),
+            annual_rate: rate.to_string(),
+            day_count: DayCount::ActAct,
+        };
+        let res = create_product(&db, product("bonus", "5")).await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+        let res = create_product(&db, product("saver", "0.01")).await;
+        assert!(matches!(res, Err(AppError::Conflict(_))));
+        let created = create_product(&db, product("bonus", "0.05")).await.unwrap();
+        assert_eq!(created.day_count, DayCount::ActAct);
+        assert_eq!(get_products(&db).await.unwrap().len(), 4);
+
+        let res = account_service::create_account(
+            &db,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: Some("gold".to_string()),
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+    }
+}
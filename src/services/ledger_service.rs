@@ -0,0 +1,355 @@
+use std::collections::HashMap;
+
+use sqlx::{Row, SqliteConnection, SqlitePool};
+
+use crate::error::AppError;
+use crate::models;
+use crate::models::money::{Currency, Money};
+
+/// Counterparty for debits paid out to merchants.
+pub const EXTERNAL_SELLERS: &str = "SYS:EXTERNAL_SELLERS";
+/// Counterparty for money credited from outside the bank.
+pub const DEPOSITS: &str = "SYS:DEPOSITS";
+/// Income from fees charged to customer accounts.
+pub const FEES: &str = "SYS:FEES";
+/// Interest paid to customer accounts.
+pub const INTEREST: &str = "SYS:INTEREST";
+/// Both legs of a transfer post against this, so it nets to zero per currency
+/// unless the transfer converted between currencies.
+pub const TRANSFER_CLEARING: &str = "SYS:TRANSFER_CLEARING";
+/// Trial balance line that sums every customer account.
+pub const CUSTOMER_ACCOUNTS: &str = "CUSTOMER_ACCOUNTS";
+
+/// One side of a journal entry. Positive amounts are debits, negative credits.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Posting<'a> {
+    pub ledger_account: &'a str,
+    pub amount: Money,
+}
+
+/// The system account on the other side of a plain (non-transfer) transaction.
+pub fn counterparty_for(amount: Money) -> &'static str {
+    if amount.is_positive() {
+        EXTERNAL_SELLERS
+    } else {
+        DEPOSITS
+    }
+}
+
+fn check_balanced(postings: &[Posting<'_>]) -> Result<(), AppError> {
+    let mut sums: HashMap<Currency, i128> = HashMap::new();
+    for posting in postings {
+        *sums.entry(posting.amount.currency()).or_default() += posting.amount.minor_units() as i128;
+    }
+    if postings.len() < 2 || sums.values().any(|sum| *sum != 0) {
+        return Err(AppError::Internal(format!(
+            "Unbalanced journal entry: {:?}",
+            postings
+        )));
+    }
+    Ok(())
+}
+
+/// Writes a journal entry inside the caller's database transaction, refusing
+/// it unless the postings sum to zero in every currency.
+pub async fn post_entry(
+    conn: &mut SqliteConnection,
+    description: &str,
+    transaction_id: Option<i64>,
+    postings: &[Posting<'_>],
+) -> Result<i64, AppError> {
+    check_balanced(postings)?;
+    let entry_id =
+        sqlx::query("INSERT INTO JOURNAL_ENTRIES (description, transaction_id) VALUES (?, ?);")
+            .bind(description)
+            .bind(transaction_id)
+            .execute(&mut *conn)
+            .await?
+            .last_insert_rowid();
+    for posting in postings {
+        sqlx::query(
+            "INSERT INTO POSTINGS (journal_entry_id, ledger_account, amount, currency) VALUES (?, ?, ?, ?);",
+        )
+        .bind(entry_id)
+        .bind(posting.ledger_account)
+        .bind(posting.amount.minor_units())
+        .bind(posting.amount.currency())
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(entry_id)
+}
+
+/// Accounts whose stored balance disagrees with the balance their postings imply.
+pub async fn reconcile(db: &SqlitePool) -> Result<Vec<models::ledger::Discrepancy>, AppError> {
+    tracing::info!("Invocation to `reconcile`");
+    let rows = sqlx::query(
+        "SELECT account_number, balance, currency, ledger_balance FROM (
+            SELECT a.account_number, a.balance, a.currency,
+                -COALESCE((SELECT SUM(p.amount) FROM POSTINGS p
+                    WHERE p.ledger_account = a.account_number AND p.currency = a.currency), 0)
+                    AS ledger_balance
+            FROM ACCOUNTS a
+        ) WHERE balance <> ledger_balance ORDER BY account_number;",
+    )
+    .fetch_all(db)
+    .await?;
+    rows.iter()
+        .map(|row| {
+            Ok(models::ledger::Discrepancy {
+                account_number: row.try_get("account_number")?,
+                balance: Money::from_row(row, "balance", "currency")?,
+                ledger_balance: Money::from_row(row, "ledger_balance", "currency")?,
+            })
+        })
+        .collect()
+}
+
+pub async fn trial_balance(db: &SqlitePool) -> Result<models::ledger::TrialBalance, AppError> {
+    tracing::info!("Invocation to `trial_balance`");
+    let rows = sqlx::query(
+        "SELECT CASE WHEN ledger_account LIKE 'SYS:%' THEN ledger_account ELSE ? END AS line,
+            currency,
+            SUM(CASE WHEN amount > 0 THEN amount ELSE 0 END) AS debits,
+            SUM(CASE WHEN amount < 0 THEN -amount ELSE 0 END) AS credits
+         FROM POSTINGS GROUP BY line, currency ORDER BY line, currency;",
+    )
+    .bind(CUSTOMER_ACCOUNTS)
+    .fetch_all(db)
+    .await?;
+    let mut lines = Vec::with_capacity(rows.len());
+    let mut totals: Vec<models::ledger::TrialBalanceTotal> = Vec::new();
+    for row in &rows {
+        let line = models::ledger::TrialBalanceLine {
+            ledger_account: row.try_get("line")?,
+            debits: Money::from_row(row, "debits", "currency")?,
+            credits: Money::from_row(row, "credits", "currency")?,
+        };
+        match totals
+            .iter_mut()
+            .find(|total| total.debits.currency() == line.debits.currency())
+        {
+            Some(total) => {
+                total.debits = total.debits.checked_add(line.debits)?;
+                total.credits = total.credits.checked_add(line.credits)?;
+            }
+            None => totals.push(models::ledger::TrialBalanceTotal {
+                debits: line.debits,
+                credits: line.credits,
+                balanced: false,
+            }),
+        }
+        lines.push(line);
+    }
+    for total in &mut totals {
+        total.balanced = total.debits == total.credits;
+    }
+    let unreconciled_accounts = reconcile(db).await?.len();
+    Ok(models::ledger::TrialBalance {
+        lines,
+        balanced: totals.iter().all(|total| total.balanced),
+        totals,
+        unreconciled_accounts,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models::transaction::TransactionCreation;
+    use crate::models::transfer::TransferCreation;
+    use crate::services::{account_service, transaction_service, transfer_service, user_service};
+
+    use super::*;
+
+    async fn setup_db() -> (SqlitePool, String, String) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "alice".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let mut numbers = Vec::new();
+        for currency in [Currency::Usd, Currency::Eur] {
+            let account = account_service::create_account(
+                &pool,
+                &Default::default(),
+                1,
+                models::account::AccountCreation {
+                    currency,
+                    product: None,
+                },
+            )
+            .await
+            .unwrap();
+            numbers.push(account.account_number);
+        }
+        let eur = numbers.pop().unwrap();
+        let usd = numbers.pop().unwrap();
+        (pool, usd, eur)
+    }
+
+    async fn post(db: &SqlitePool, account_number: &str, seller: &str, amount: Money) {
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account_number.to_string(),
+                seller: seller.to_string(),
+                amount,
+            },
+        )
+        .await
+        .unwrap();
+    }
+
+    fn line<'a>(
+        balance: &'a models::ledger::TrialBalance,
+        ledger_account: &str,
+        currency: Currency,
+    ) -> &'a models::ledger::TrialBalanceLine {
+        balance
+            .lines
+            .iter()
+            .find(|l| l.ledger_account == ledger_account && l.debits.currency() == currency)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_rejects_unbalanced_entries() {
+        let usd = |minor| Money::new(minor, Currency::Usd);
+        let a = Posting {
+            ledger_account: "A",
+            amount: usd(100),
+        };
+        assert!(check_balanced(&[a]).is_err());
+        let b = Posting {
+            ledger_account: DEPOSITS,
+            amount: usd(-99),
+        };
+        assert!(check_balanced(&[a, b]).is_err());
+        let b = Posting {
+            amount: Money::new(-100, Currency::Eur),
+            ..b
+        };
+        assert!(check_balanced(&[a, b]).is_err());
+        let b = Posting {
+            amount: usd(-100),
+            ..b
+        };
+        assert!(check_balanced(&[a, b]).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_transactions_post_balanced_entries() {
+        let (db, usd, eur) = setup_db().await;
+        post(&db, &usd, "Employer", Money::new(-10_000, Currency::Usd)).await;
+        post(&db, &usd, "Grocer", Money::new(2_500, Currency::Usd)).await;
+        post(&db, &eur, "Employer", Money::new(-5_000, Currency::Eur)).await;
+        transfer_service::create_transfer(
+            &db,
+            1,
+            TransferCreation {
+                source_account_number: usd.clone(),
+                destination_account_number: eur.clone(),
+                amount: Money::new(1_000, Currency::Usd),
+                rate: Some("0.9".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+
+        let balance = trial_balance(&db).await.unwrap();
+        assert!(balance.balanced);
+        assert_eq!(balance.totals.len(), 2);
+        assert_eq!(balance.unreconciled_accounts, 0);
+        let deposits = line(&balance, DEPOSITS, Currency::Usd);
+        assert_eq!(deposits.debits, Money::new(10_000, Currency::Usd));
+        let sellers = line(&balance, EXTERNAL_SELLERS, Currency::Usd);
+        assert_eq!(sellers.credits, Money::new(2_500, Currency::Usd));
+        let customers = line(&balance, CUSTOMER_ACCOUNTS, Currency::Eur);
+        assert_eq!(customers.credits, Money::new(5_900, Currency::Eur));
+        // the clearing account holds the currency position of the conversion
+        let clearing = line(&balance, TRANSFER_CLEARING, Currency::Eur);
+        assert_eq!(clearing.debits, Money::new(900, Currency::Eur));
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_reports_drift() {
+        let (db, usd, _) = setup_db().await;
+        post(&db, &usd, "Employer", Money::new(-10_000, Currency::Usd)).await;
+        assert!(reconcile(&db).await.unwrap().is_empty());
+        sqlx::query("UPDATE ACCOUNTS SET balance = balance + 1 WHERE account_number = ?;")
+            .bind(&usd)
+            .execute(&db)
+            .await
+            .unwrap();
+        let discrepancies = reconcile(&db).await.unwrap();
+        assert_eq!(
+            discrepancies,
+            vec![models::ledger::Discrepancy {
+                account_number: usd,
+                balance: Money::new(10_001, Currency::Usd),
+                ledger_balance: Money::new(10_000, Currency::Usd),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_postings_are_append_only() {
+        let (db, usd, _) = setup_db().await;
+        post(&db, &usd, "Employer", Money::new(-100, Currency::Usd)).await;
+        assert!(
+            sqlx::query("UPDATE POSTINGS SET amount = 0;")
+                .execute(&db)
+                .await
+                .is_err()
+        );
+        assert!(
+            sqlx::query("DELETE FROM POSTINGS;")
+                .execute(&db)
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_migration_backfills_existing_balances() {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        // schema as it was before the ledger, with one transaction and an
+        // account balance that predates its transactions
+        let before: Vec<_> = migrations::MIGRATIONS
+            .iter()
+            .take_while(|m| m.name != "ledger")
+            .collect();
+        for migration in &before {
+            sqlx::raw_sql(migration.sql).execute(&pool).await.unwrap();
+        }
+        sqlx::raw_sql(
+            "INSERT INTO USERS (username, password) VALUES ('alice', 'x');
+             INSERT INTO ACCOUNTS (account_number, user_id, balance, currency) VALUES ('1', 1, 7000, 'USD');
+             INSERT INTO TRANSACTIONS (account_number, seller, amount, currency) VALUES ('1', 'Grocer', 3000, 'USD');",
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        let ledger = migrations::MIGRATIONS
+            .iter()
+            .find(|m| m.name == "ledger")
+            .unwrap();
+        sqlx::raw_sql(ledger.sql).execute(&pool).await.unwrap();
+
+        assert!(reconcile(&pool).await.unwrap().is_empty());
+        let balance = trial_balance(&pool).await.unwrap();
+        assert!(balance.balanced);
+        assert_eq!(
+            line(&balance, DEPOSITS, Currency::Usd).debits,
+            Money::new(10_000, Currency::Usd)
+        );
+    }
+}
@@ -0,0 +1,557 @@
+use std::collections::HashMap;
+
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::error::AppError;
+use crate::models::merchant::{Merchant, MerchantCreation, MerchantUpdate};
+use crate::models::transaction::{TransactionPage, TransactionQuery};
+use crate::services::transaction_service;
+
+/// Words that say nothing about who the merchant is, dropped when normalizing.
+pub const NOISE_WORDS: [&str; 10] = [
+    "com", "net", "org", "www", "inc", "llc", "ltd", "co", "corp", "gmbh",
+];
+
+/// The key a seller is matched on: lowercase words, without punctuation, noise
+/// words or store and reference numbers (words of three or more characters
+/// with a digit in them). "AMAZON.COM*1A2B3C" and "Amazon" both become "amazon".
+pub fn normalize(seller: &str) -> String {
+    seller
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty() && !NOISE_WORDS.contains(word))
+        .filter(|word| word.chars().count() < 3 || !word.chars().any(|c| c.is_ascii_digit()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn validate_category(category: &str) -> Result<(), AppError> {
+    if category.len() != 4 || !category.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(AppError::Validation(format!(
+            "Category {} is not a four-digit merchant category code",
+            category
+        )));
+    }
+    Ok(())
+}
+
+fn key_of(name: &str) -> Result<String, AppError> {
+    let key = normalize(name);
+    if key.is_empty() {
+        return Err(AppError::Validation(format!(
+            "{:?} has nothing to match sellers on",
+            name
+        )));
+    }
+    Ok(key)
+}
+
+async fn load_aliases(
+    conn: &mut SqliteConnection,
+    merchants: &mut [Merchant],
+) -> Result<(), AppError> {
+    let aliases: Vec<(i64, String)> =
+        sqlx::query_as("SELECT merchant_id, alias FROM MERCHANT_ALIASES ORDER BY id;")
+            .fetch_all(conn)
+            .await?;
+    let mut by_merchant: HashMap<i64, Vec<String>> = HashMap::new();
+    for (merchant_id, alias) in aliases {
+        by_merchant.entry(merchant_id).or_default().push(alias);
+    }
+    for merchant in merchants {
+        merchant.aliases = by_merchant.remove(&merchant.id).unwrap_or_default();
+    }
+    Ok(())
+}
+
+pub async fn get_merchants(db: &SqlitePool) -> Result<Vec<Merchant>, AppError> {
+    tracing::info!("Invocation to `get_merchants`");
+    let mut conn = db.acquire().await?;
+    let mut merchants: Vec<Merchant> =
+        sqlx::query_as("SELECT id, name, category, created_at FROM MERCHANTS ORDER BY name;")
+            .fetch_all(&mut *conn)
+            .await?;
+    load_aliases(&mut conn, &mut merchants).await?;
+    Ok(merchants)
+}
+
+pub(crate) async fn get_merchant_in(
+    conn: &mut SqliteConnection,
+    id: i64,
+) -> Result<Merchant, AppError> {
+    let merchant: Option<Merchant> =
+        sqlx::query_as("SELECT id, name, category, created_at FROM MERCHANTS WHERE id = ?;")
+            .bind(id)
+            .fetch_optional(&mut *conn)
+            .await?;
+    let mut merchants: Vec<Merchant> = merchant.into_iter().collect();
+    load_aliases(conn, &mut merchants).await?;
+    merchants
+        .pop()
+        .ok_or_else(|| AppError::NotFound(format!("Merchant {} not found", id)))
+}
+
+pub async fn get_merchant(db: &SqlitePool, id: i64) -> Result<Merchant, AppError> {
+    tracing::info!("Invocation to `get_merchant`");
+    let mut conn = db.acquire().await?;
+    get_merchant_in(&mut conn, id).await
+}
+
+/// Fails with Conflict if another merchant already goes by one of `keys`.
+async fn ensure_keys_free(
+    conn: &mut SqliteConnection,
+    keys: &[String],
+    merchant_id: Option<i64>,
+) -> Result<(), AppError> {
+    for key in keys {
+        let owner: Option<i64> = sqlx::query_scalar(
+            "SELECT id FROM MERCHANTS WHERE name_key = ?
+             UNION SELECT merchant_id FROM MERCHANT_ALIASES WHERE alias_key = ?;",
+        )
+        .bind(key)
+        .bind(key)
+        .fetch_optional(&mut *conn)
+        .await?;
+        if let Some(owner) = owner
+            && Some(owner) != merchant_id
+        {
+            return Err(AppError::Conflict(format!(
+                "Merchant {} already matches {:?}",
+                owner, key
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Replaces a merchant's aliases, skipping any that match its name or an
+/// earlier alias.
+async fn set_aliases(
+    conn: &mut SqliteConnection,
+    merchant_id: i64,
+    name_key: &str,
+    aliases: &[String],
+) -> Result<(), AppError> {
+    let mut keys = vec![name_key.to_string()];
+    let mut kept = Vec::new();
+    for alias in aliases {
+        let key = key_of(alias)?;
+        if !keys.contains(&key) {
+            keys.push(key.clone());
+            kept.push((alias.trim(), key));
+        }
+    }
+    ensure_keys_free(&mut *conn, &keys, Some(merchant_id)).await?;
+    sqlx::query("DELETE FROM MERCHANT_ALIASES WHERE merchant_id = ?;")
+        .bind(merchant_id)
+        .execute(&mut *conn)
+        .await?;
+    for (alias, key) in kept {
+        sqlx::query(
+            "INSERT INTO MERCHANT_ALIASES (merchant_id, alias, alias_key) VALUES (?, ?, ?);",
+        )
+        .bind(merchant_id)
+        .bind(alias)
+        .bind(key)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Adds a merchant and links the transactions it matches that were not linked
+/// to any merchant yet.
+pub async fn create_merchant(
+    db: &SqlitePool,
+    merchant_creation: MerchantCreation,
+) -> Result<Merchant, AppError> {
+    tracing::info!("Invocation to `create_merchant`");
+    let name = merchant_creation.name.trim();
+    let name_key = key_of(name)?;
+    validate_category(&merchant_creation.category)?;
+    let mut tx = transaction_service::begin_write(db).await?;
+    ensure_keys_free(&mut tx, std::slice::from_ref(&name_key), None).await?;
+    let id = sqlx::query("INSERT INTO MERCHANTS (name, name_key, category) VALUES (?, ?, ?);")
+        .bind(name)
+        .bind(&name_key)
+        .bind(&merchant_creation.category)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
+    set_aliases(&mut tx, id, &name_key, &merchant_creation.aliases).await?;
+    link_unmatched(&mut tx).await?;
+    let merchant = get_merchant_in(&mut tx, id).await?;
+    tx.commit().await?;
+    Ok(merchant)
+}
+
+/// Renames, recategorizes or re-aliases a merchant. Transactions already
+/// linked to it stay linked; newly matching ones are linked.
+pub async fn update_merchant(
+    db: &SqlitePool,
+    id: i64,
+    merchant_update: MerchantUpdate,
+) -> Result<Merchant, AppError> {
+    tracing::info!("Invocation to `update_merchant`");
+    let mut tx = transaction_service::begin_write(db).await?;
+    let current = get_merchant_in(&mut tx, id).await?;
+    let name = merchant_update
+        .name
+        .as_deref()
+        .unwrap_or(&current.name)
+        .trim()
+        .to_string();
+    let name_key = key_of(&name)?;
+    let category = merchant_update.category.unwrap_or(current.category);
+    validate_category(&category)?;
+    ensure_keys_free(&mut tx, std::slice::from_ref(&name_key), Some(id)).await?;
+    sqlx::query(
+        "UPDATE MERCHANTS SET name = ?, name_key = ?, category = ?, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ?;",
+    )
+    .bind(&name)
+    .bind(&name_key)
+    .bind(&category)
+    .bind(id)
+    .execute(&mut *tx)
+    .await?;
+    let aliases = merchant_update.aliases.unwrap_or(current.aliases);
+    set_aliases(&mut tx, id, &name_key, &aliases).await?;
+    link_unmatched(&mut tx).await?;
+    let merchant = get_merchant_in(&mut tx, id).await?;
+    tx.commit().await?;
+    Ok(merchant)
+}
+
+/// Removes a merchant; its transactions keep their seller but lose the link.
+pub async fn delete_merchant(db: &SqlitePool, id: i64) -> Result<Merchant, AppError> {
+    tracing::info!("Invocation to `delete_merchant`");
+    let mut tx = transaction_service::begin_write(db).await?;
+    let merchant = get_merchant_in(&mut tx, id).await?;
+    // unlinked one by one, so each is audited rather than left to ON DELETE SET NULL
+    let linked: Vec<i64> = sqlx::query_scalar("SELECT id FROM TRANSACTIONS WHERE merchant_id = ?;")
+        .bind(id)
+        .fetch_all(&mut *tx)
+        .await?;
+    for transaction_id in linked {
+        transaction_service::set_merchant(&mut tx, transaction_id, None).await?;
+    }
+    sqlx::query("DELETE FROM MERCHANTS WHERE id = ?;")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(merchant)
+}
+
+/// The caller's transactions linked to a merchant, filtered and paged like
+/// `GET /transactions`.
+pub async fn get_merchant_transactions(
+    db: &SqlitePool,
+    user_id: i64,
+    id: i64,
+    query: TransactionQuery,
+) -> Result<TransactionPage, AppError> {
+    tracing::info!("Invocation to `get_merchant_transactions`");
+    get_merchant(db, id).await?;
+    let query = TransactionQuery {
+        merchant_id: Some(id),
+        ..query
+    };
+    transaction_service::get_transactions(db, user_id, &query).await
+}
+
+/// The merchant whose name or one of whose aliases `seller` normalizes to.
+pub async fn match_seller(
+    conn: &mut SqliteConnection,
+    seller: &str,
+) -> Result<Option<i64>, AppError> {
+    let key = normalize(seller);
+    if key.is_empty() {
+        return Ok(None);
+    }
+    let merchant_id: Option<i64> = sqlx::query_scalar(
+        "SELECT id FROM MERCHANTS WHERE name_key = ?
+         UNION SELECT merchant_id FROM MERCHANT_ALIASES WHERE alias_key = ?;",
+    )
+    .bind(&key)
+    .bind(&key)
+    .fetch_optional(conn)
+    .await?;
+    Ok(merchant_id)
+}
+
+/// Links every unlinked payment whose seller matches a merchant, and refunds
+/// to the merchant of what they refund. Transfers and fees are never linked.
+async fn link_unmatched(conn: &mut SqliteConnection) -> Result<u64, AppError> {
+    let unlinked: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT id, seller FROM TRANSACTIONS
+         WHERE merchant_id IS NULL AND transfer_id IS NULL AND fee_kind IS NULL
+         AND original_transaction_id IS NULL;",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut linked = 0;
+    for (id, seller) in unlinked {
+        if let Some(merchant_id) = match_seller(&mut *conn, &seller).await? {
+            transaction_service::set_merchant(&mut *conn, id, Some(merchant_id)).await?;
+            linked += 1;
+        }
+    }
+    let refunds: Vec<(i64, i64)> = sqlx::query_as(
+        "SELECT r.id, o.merchant_id FROM TRANSACTIONS r
+         JOIN TRANSACTIONS o ON o.id = r.original_transaction_id
+         WHERE r.merchant_id IS NULL AND o.merchant_id IS NOT NULL;",
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+    for (id, merchant_id) in refunds {
+        transaction_service::set_merchant(&mut *conn, id, Some(merchant_id)).await?;
+    }
+    Ok(linked)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models;
+    use crate::models::audit::{AuditAction, AuditQuery};
+    use crate::models::money::{Currency, Money};
+    use crate::models::transaction::{RefundCreation, TransactionCreation};
+    use crate::services::{account_service, audit_service, user_service};
+    use serde_json::Value;
+
+    use super::*;
+
+    async fn setup_db() -> (SqlitePool, String) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "alice".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account = account_service::create_account(
+            &pool,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap()
+        .account_number;
+        (pool, account)
+    }
+
+    async fn pay(db: &SqlitePool, account_number: &str, seller: &str, minor: i64) -> i64 {
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account_number.to_string(),
+                seller: seller.to_string(),
+                amount: Money::new(minor, Currency::Usd),
+            },
+        )
+        .await
+        .unwrap()
+        .id
+    }
+
+    fn amazon() -> MerchantCreation {
+        MerchantCreation {
+            name: "Amazon".to_string(),
+            category: "5942".to_string(),
+            aliases: vec!["amzn mktp".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        for (seller, key) in [
+            ("Amazon", "amazon"),
+            ("AMAZON.COM*1A2B3C", "amazon"),
+            ("  amzn  Mktp US*2K4 ", "amzn mktp us"),
+            ("Joe's Coffee, Inc.", "joe s coffee"),
+            ("7-Eleven #40213", "7 eleven"),
+            ("www.shop.co", "shop"),
+            ("#123456", ""),
+        ] {
+            assert_eq!(normalize(seller), key, "{}", seller);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_links_payments_to_merchants() {
+        let (db, account) = setup_db().await;
+        pay(&db, &account, "Employer", -10_000).await;
+        let before = pay(&db, &account, "AMAZON.COM*1A2B3C", 1_000).await;
+        let merchant = create_merchant(&db, amazon()).await.unwrap();
+        assert_eq!(merchant.aliases, vec!["amzn mktp"]);
+
+        let receipt = transaction_service::create_transaction(
+            &db,
+            1,
+            TransactionCreation {
+                account_number: account.clone(),
+                seller: "amzn mktp".to_string(),
+                amount: Money::new(2_000, Currency::Usd),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(receipt.merchant_id, Some(merchant.id));
+        pay(&db, &account, "Grocer", 500).await;
+        transaction_service::refund_transaction(
+            &db,
+            1,
+            before,
+            RefundCreation {
+                amount: Some(Money::new(300, Currency::Usd)),
+            },
+        )
+        .await
+        .unwrap();
+
+        let page = get_merchant_transactions(&db, 1, merchant.id, TransactionQuery::default())
+            .await
+            .unwrap();
+        let ids: Vec<i64> = page
+            .items
+            .iter()
+            .map(|t| t.id.unwrap_or_default() as i64)
+            .collect();
+        assert_eq!(ids.len(), 3);
+        assert_eq!(ids[..2], [before, receipt.id]);
+        assert_eq!(page.items[2].original_transaction_id, Some(before));
+        // another user sees none of them
+        let page = get_merchant_transactions(&db, 2, merchant.id, TransactionQuery::default())
+            .await
+            .unwrap();
+        assert!(page.items.is_empty());
+        let res = get_merchant_transactions(&db, 1, 99, TransactionQuery::default()).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_names_and_aliases_are_unique() {
+        let (db, _) = setup_db().await;
+        let merchant = create_merchant(&db, amazon()).await.unwrap();
+        for creation in [
+            MerchantCreation {
+                name: "AMAZON.COM".to_string(),
+                ..amazon()
+            },
+            MerchantCreation {
+                name: "Amazon Marketplace".to_string(),
+                category: "5942".to_string(),
+                aliases: vec!["AMZN Mktp".to_string()],
+            },
+        ] {
+            let res = create_merchant(&db, creation).await;
+            assert!(matches!(res, Err(AppError::Conflict(_))));
+        }
+        for creation in [
+            MerchantCreation {
+                name: "Grocer".to_string(),
+                category: "541".to_string(),
+                aliases: vec![],
+            },
+            MerchantCreation {
+                name: "#1234".to_string(),
+                category: "5411".to_string(),
+                aliases: vec![],
+            },
+        ] {
+            let res = create_merchant(&db, creation).await;
+            assert!(matches!(res, Err(AppError::Validation(_))));
+        }
+        // its own name and aliases may be reused when updating
+        let updated = update_merchant(
+            &db,
+            merchant.id,
+            MerchantUpdate {
+                name: Some("amazon.com".to_string()),
+                aliases: Some(vec!["Amazon".to_string(), "AMZN".to_string()]),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.name, "amazon.com");
+        assert_eq!(updated.category, "5942");
+        assert_eq!(updated.aliases, vec!["AMZN"]);
+        assert_eq!(get_merchants(&db).await.unwrap(), vec![updated]);
+    }
+
+    #[tokio::test]
+    async fn test_deleting_a_merchant_unlinks_its_transactions() {
+        let (db, account) = setup_db().await;
+        pay(&db, &account, "Employer", -10_000).await;
+        let merchant = create_merchant(&db, amazon()).await.unwrap();
+        let id = pay(&db, &account, "Amazon", 1_000).await;
+        delete_merchant(&db, merchant.id).await.unwrap();
+        let res = get_merchant(&db, merchant.id).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+        let merchant_id: Option<i64> =
+            sqlx::query_scalar("SELECT merchant_id FROM TRANSACTIONS WHERE id = ?;")
+                .bind(id)
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(merchant_id, None);
+        let aliases: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM MERCHANT_ALIASES;")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(aliases, 0);
+    }
+
+    #[tokio::test]
+    async fn test_relinking_and_unlinking_are_audited() {
+        let (db, account) = setup_db().await;
+        pay(&db, &account, "Employer", -10_000).await;
+        let id = pay(&db, &account, "Amazon", 1_000).await;
+        let refund = transaction_service::refund_transaction(&db, 1, id, Default::default())
+            .await
+            .unwrap()
+            .id
+            .unwrap() as i64;
+        let set_merchant = async || {
+            audit_service::get_audit_log(
+                &db,
+                &AuditQuery {
+                    action: Some(AuditAction::TransactionSetMerchant),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap()
+            .items
+        };
+
+        let merchant = create_merchant(&db, amazon()).await.unwrap();
+        let linked = set_merchant().await;
+        let entities: Vec<&str> = linked.iter().map(|e| e.entity_id.as_str()).collect();
+        assert_eq!(entities, [refund.to_string(), id.to_string()]);
+        for entry in &linked {
+            assert_eq!(entry.before.as_ref().unwrap()["merchant_id"], Value::Null);
+            assert_eq!(entry.after.as_ref().unwrap()["merchant_id"], merchant.id);
+        }
+
+        delete_merchant(&db, merchant.id).await.unwrap();
+        let entries = set_merchant().await;
+        assert_eq!(entries.len(), 4);
+        for entry in &entries[..2] {
+            assert_eq!(entry.before.as_ref().unwrap()["merchant_id"], merchant.id);
+            assert_eq!(entry.after.as_ref().unwrap()["merchant_id"], Value::Null);
+        }
+    }
+}
@@ -1,4 +1,20 @@
 pub mod account_service;
-mod generation_service;
+pub mod analytics_service;
+pub mod audit_service;
+pub mod auth_service;
+pub mod budget_service;
+pub mod fee_service;
+pub mod generation_service;
+pub mod hold_service;
+pub mod idempotency_service;
+pub mod interest_service;
+pub mod ledger_service;
+pub mod merchant_service;
+pub mod outbox_service;
+pub mod schedule_service;
+pub mod stream_service;
+pub mod token_service;
 pub mod transaction_service;
+pub mod transfer_service;
 pub mod user_service;
+pub mod webhook_service;
@@ -0,0 +1,24 @@
+use serde::Serialize;
+use sqlx::SqliteConnection;
+
+use crate::error::AppError;
+use crate::models::webhook::EventType;
+
+/// Records an event in the outbox inside the caller's database transaction, so
+/// it exists if and only if the change it describes is committed. The webhook
+/// dispatcher picks it up from there. Returns the event id.
+pub async fn record(
+    conn: &mut SqliteConnection,
+    event_type: EventType,
+    payload: &impl Serialize,
+) -> Result<i64, AppError> {
+    let payload = serde_json::to_string(payload)
+        .map_err(|err| AppError::Internal(format!("Could not serialize event: {}", err)))?;
+    let id = sqlx::query("INSERT INTO OUTBOX_EVENTS (event_type, payload) VALUES (?, ?);")
+        .bind(event_type)
+        .bind(payload)
+        .execute(conn)
+        .await?
+        .last_insert_rowid();
+    Ok(id)
+}
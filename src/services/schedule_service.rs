@@ -0,0 +1,717 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use chrono::{Months, NaiveDateTime, Timelike};
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::config::Config;
+use crate::error::{AppError, ErrorBody};
+use crate::models;
+use crate::models::money::{Currency, Money};
+use crate::models::schedule::{
+    InsufficientFundsPolicy, Schedule, ScheduleFrequency, ScheduleRunOutcome, ScheduleStatus,
+};
+use crate::models::transaction::TransactionCreation;
+use crate::services::audit_service::{self, AuditContext};
+use crate::services::{account_service, idempotency_service, transaction_service};
+
+const SCHEDULE_COLUMNS: &str = "s.id, s.user_id, s.account_number, s.seller, s.amount, s.currency,
+    s.frequency, s.cron, s.start_at, s.end_at, s.max_runs, s.on_insufficient_funds, s.occurrences,
+    s.retry_count, s.next_run_at, s.due_at, s.status, s.created_at";
+
+/// How the worker treats an occurrence that runs into insufficient funds under
+/// the retry policy: try again every `retry_interval_secs`, and skip the
+/// occurrence after `max_retries` retries.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SchedulePolicy {
+    pub retry_interval_secs: i64,
+    pub max_retries: i64,
+}
+
+impl SchedulePolicy {
+    pub fn from_config(config: &Config) -> Self {
+        SchedulePolicy {
+            retry_interval_secs: config.schedule_retry_interval_secs,
+            max_retries: config.schedule_max_retries,
+        }
+    }
+}
+
+fn now() -> NaiveDateTime {
+    truncate(chrono::Utc::now().naive_utc())
+}
+
+// stored times carry whole seconds, so they compare correctly as TEXT
+fn truncate(at: NaiveDateTime) -> NaiveDateTime {
+    at.with_nanosecond(0).unwrap_or(at)
+}
+
+/// Accepts the usual five cron fields (minute to day of week) as well as the
+/// six or seven of the `cron` crate, which add seconds and years.
+fn parse_cron(expression: &str) -> Result<cron::Schedule, AppError> {
+    let fields = expression.split_whitespace().count();
+    let expression = match fields {
+        5 => format!("0 {}", expression.trim()),
+        6 | 7 => expression.trim().to_string(),
+        _ => {
+            return Err(AppError::Validation(
+                "cron must have 5 fields (minute hour day month weekday)".to_string(),
+            ));
+        }
+    };
+    cron::Schedule::from_str(&expression)
+        .map_err(|err| AppError::Validation(format!("Invalid cron expression: {}", err)))
+}
+
+/// The parts of a schedule that decide when it runs.
+struct Recurrence {
+    frequency: ScheduleFrequency,
+    cron: Option<cron::Schedule>,
+    start_at: NaiveDateTime,
+    end_at: Option<NaiveDateTime>,
+    max_runs: Option<i64>,
+}
+
+impl Recurrence {
+    fn of(schedule: &Schedule) -> Result<Self, AppError> {
+        Ok(Recurrence {
+            frequency: schedule.frequency,
+            cron: schedule.cron.as_deref().map(parse_cron).transpose()?,
+            start_at: schedule.start_at,
+            end_at: schedule.end_at,
+            max_runs: schedule.max_runs,
+        })
+    }
+
+    /// When occurrence `n` (counting from 0) falls, given when occurrence
+    /// `n - 1` fell, or None once the schedule has run its course.
+    fn occurrence(&self, n: i64, previous: NaiveDateTime) -> Option<NaiveDateTime> {
+        if self.max_runs.is_some_and(|max_runs| n >= max_runs) {
+            return None;
+        }
+        let start = self.start_at;
+        let at = match (self.frequency, &self.cron) {
+            (ScheduleFrequency::Once, _) => (n == 0).then_some(start),
+            (ScheduleFrequency::Daily, _) => start.checked_add_signed(chrono::Duration::days(n)),
+            (ScheduleFrequency::Weekly, _) => start.checked_add_signed(chrono::Duration::weeks(n)),
+            // counted from the start rather than the previous occurrence, so
+            // that the 31st comes back after a 30th
+            (ScheduleFrequency::Monthly, _) => u32::try_from(n)
+                .ok()
+                .and_then(|n| start.checked_add_months(Months::new(n))),
+            (ScheduleFrequency::Cron, Some(cron)) => {
+                // the first occurrence may fall on the start itself
+                let after = if n == 0 {
+                    start - chrono::Duration::seconds(1)
+                } else {
+                    previous
+                };
+                cron.after(&after.and_utc()).next().map(|at| at.naive_utc())
+            }
+            (ScheduleFrequency::Cron, None) => None,
+        };
+        at.filter(|at| self.end_at.is_none_or(|end_at| *at <= end_at))
+    }
+}
+
+async fn get_schedule_in(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    id: i64,
+) -> Result<Schedule, AppError> {
+    let schedule: Option<Schedule> = sqlx::query_as(&format!(
+        "SELECT {} FROM SCHEDULES s WHERE s.id = ? AND s.user_id = ?;",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?;
+    schedule.ok_or_else(|| AppError::NotFound(format!("Schedule {} not found", id)))
+}
+
+pub async fn get_schedules(db: &SqlitePool, user_id: i64) -> Result<Vec<Schedule>, AppError> {
+    tracing::info!("Invocation to `get_schedules`");
+    let schedules: Vec<Schedule> = sqlx::query_as(&format!(
+        "SELECT {} FROM SCHEDULES s WHERE s.user_id = ? ORDER BY s.id;",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(user_id)
+    .fetch_all(db)
+    .await?;
+    Ok(schedules)
+}
+
+pub async fn get_schedule(db: &SqlitePool, user_id: i64, id: i64) -> Result<Schedule, AppError> {
+    tracing::info!("Invocation to `get_schedule`");
+    let mut conn = db.acquire().await?;
+    get_schedule_in(&mut conn, user_id, id).await
+}
+
+pub async fn create_schedule(
+    db: &SqlitePool,
+    user_id: i64,
+    schedule_creation: models::schedule::ScheduleCreation,
+) -> Result<Schedule, AppError> {
+    tracing::info!("Invocation to `create_schedule`");
+    let account_number =
+        account_service::resolve_account_number(db, &schedule_creation.account_number).await?;
+    let amount = schedule_creation.amount;
+    if !amount.is_positive() {
+        return Err(AppError::Validation(
+            "Scheduled amount must be positive".to_string(),
+        ));
+    }
+    let cron = match (schedule_creation.frequency, &schedule_creation.cron) {
+        (ScheduleFrequency::Cron, Some(expression)) => Some(parse_cron(expression)?),
+        (ScheduleFrequency::Cron, None) => {
+            return Err(AppError::Validation(
+                "cron is required for the cron frequency".to_string(),
+            ));
+        }
+        (_, Some(_)) => {
+            return Err(AppError::Validation(
+                "cron is only allowed for the cron frequency".to_string(),
+            ));
+        }
+        (_, None) => None,
+    };
+    let start_at = truncate(schedule_creation.start_at);
+    let end_at = schedule_creation.end_at.map(truncate);
+    if start_at < now() {
+        return Err(AppError::Validation(
+            "start_at must not be in the past".to_string(),
+        ));
+    }
+    if end_at.is_some_and(|end_at| end_at < start_at) {
+        return Err(AppError::Validation(
+            "end_at must not be before start_at".to_string(),
+        ));
+    }
+    if schedule_creation
+        .max_runs
+        .is_some_and(|max_runs| max_runs < 1)
+    {
+        return Err(AppError::Validation(
+            "max_runs must be at least 1".to_string(),
+        ));
+    }
+    let recurrence = Recurrence {
+        frequency: schedule_creation.frequency,
+        cron,
+        start_at,
+        end_at,
+        max_runs: schedule_creation.max_runs,
+    };
+    let first = recurrence.occurrence(0, start_at).ok_or_else(|| {
+        AppError::Validation("The schedule would never run before end_at".to_string())
+    })?;
+
+    let mut tx = transaction_service::begin_write(db).await?;
+    account_service::ensure_owned(&mut tx, user_id, &account_number).await?;
+    let currency: Currency =
+        sqlx::query_scalar("SELECT currency FROM ACCOUNTS WHERE account_number = ?;")
+            .bind(&account_number)
+            .fetch_one(&mut *tx)
+            .await?;
+    // a schedule in the wrong currency would fail on every occurrence
+    Money::zero(currency).checked_sub(amount)?;
+    let id = sqlx::query(
+        "INSERT INTO SCHEDULES (user_id, account_number, seller, amount, currency, frequency, cron,
+         start_at, end_at, max_runs, on_insufficient_funds, next_run_at, due_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+    )
+    .bind(user_id)
+    .bind(&account_number)
+    .bind(&schedule_creation.seller)
+    .bind(amount.minor_units())
+    .bind(amount.currency())
+    .bind(schedule_creation.frequency)
+    .bind(&schedule_creation.cron)
+    .bind(start_at)
+    .bind(end_at)
+    .bind(schedule_creation.max_runs)
+    .bind(schedule_creation.on_insufficient_funds)
+    .bind(first)
+    .bind(first)
+    .execute(&mut *tx)
+    .await?
+    .last_insert_rowid();
+    let schedule = get_schedule_in(&mut tx, user_id, id).await?;
+    tx.commit().await?;
+    Ok(schedule)
+}
+
+/// Stops a schedule for good. Occurrences already run are not undone.
+pub async fn cancel_schedule(db: &SqlitePool, user_id: i64, id: i64) -> Result<Schedule, AppError> {
+    tracing::info!("Invocation to `cancel_schedule`");
+    let mut tx = transaction_service::begin_write(db).await?;
+    get_schedule_in(&mut tx, user_id, id).await?;
+    let cancelled = sqlx::query(
+        "UPDATE SCHEDULES SET status = 'cancelled', next_run_at = NULL, due_at = NULL,
+         updated_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND status = 'active';",
+    )
+    .bind(id)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if cancelled == 0 {
+        return Err(AppError::Conflict(format!(
+            "Schedule {} is no longer active",
+            id
+        )));
+    }
+    let schedule = get_schedule_in(&mut tx, user_id, id).await?;
+    tx.commit().await?;
+    Ok(schedule)
+}
+
+pub async fn get_schedule_runs(
+    db: &SqlitePool,
+    user_id: i64,
+    id: i64,
+) -> Result<Vec<models::schedule::ScheduleRun>, AppError> {
+    tracing::info!("Invocation to `get_schedule_runs`");
+    let mut conn = db.acquire().await?;
+    get_schedule_in(&mut conn, user_id, id).await?;
+    let runs: Vec<models::schedule::ScheduleRun> = sqlx::query_as(
+        "SELECT id, schedule_id, occurrence, scheduled_for, outcome, message, created_at
+         FROM SCHEDULE_RUNS WHERE schedule_id = ? ORDER BY id;",
+    )
+    .bind(id)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(runs)
+}
+
+/// Runs the current occurrence of every schedule due at `now`. A schedule
+/// behind by several occurrences catches up one per call. Returns how many
+/// occurrences were attempted.
+pub async fn run_due_schedules(
+    db: &SqlitePool,
+    policy: &SchedulePolicy,
+    now: NaiveDateTime,
+) -> Result<u64, AppError> {
+    let now = truncate(now);
+    let due: Vec<Schedule> = sqlx::query_as(&format!(
+        "SELECT {} FROM SCHEDULES s WHERE s.status = 'active' AND s.due_at <= ?
+         ORDER BY s.due_at, s.id;",
+        SCHEDULE_COLUMNS
+    ))
+    .bind(now)
+    .fetch_all(db)
+    .await?;
+    let mut attempted = 0;
+    for schedule in &due {
+        match run_occurrence(db, policy, schedule, now).await {
+            Ok(()) => attempted += 1,
+            // left due, so the next pass tries again
+            Err(err) => tracing::error!("Running schedule {} failed: {}", schedule.id, err),
+        }
+    }
+    Ok(attempted)
+}
+
+async fn run_occurrence(
+    db: &SqlitePool,
+    policy: &SchedulePolicy,
+    schedule: &Schedule,
+    now: NaiveDateTime,
+) -> Result<(), AppError> {
+    let scheduled_for = schedule
+        .next_run_at
+        .ok_or_else(|| AppError::Internal(format!("Schedule {} has no next run", schedule.id)))?;
+    let request = TransactionCreation {
+        account_number: schedule.account_number.clone(),
+        seller: schedule.seller.clone(),
+        amount: schedule.amount,
+    };
+    // a crash between posting and recording the run must not post twice
+    let key = format!(
+        "{}{}-{}-{}",
+        idempotency_service::SCHEDULE_KEY_PREFIX,
+        schedule.id,
+        schedule.occurrences,
+        schedule.retry_count
+    );
+    // audited as the schedule's owner, under the run's idempotency key
+    let context = AuditContext {
+        actor_user_id: Some(schedule.user_id),
+        request_id: Some(key.clone()),
+    };
+    let stored = audit_service::scope(
+        context,
+        idempotency_service::run(db, schedule.user_id, &key, &request, async |conn| {
+            transaction_service::create_transaction_in(conn, schedule.user_id, request.clone())
+                .await
+        }),
+    )
+    .await;
+    let (outcome, message) = match stored {
+        Ok(stored) if stored.status == 200 => (ScheduleRunOutcome::Succeeded, None),
+        Ok(stored) => {
+            let message = serde_json::from_str::<ErrorBody>(&stored.body)
+                .map(|body| body.message)
+                .unwrap_or(stored.body);
+            let outcome = if stored.status != AppError::InsufficientFunds.status().as_u16() {
+                ScheduleRunOutcome::Failed
+            } else if schedule.on_insufficient_funds == InsufficientFundsPolicy::Retry
+                && schedule.retry_count < policy.max_retries
+            {
+                ScheduleRunOutcome::Retrying
+            } else {
+                ScheduleRunOutcome::Skipped
+            };
+            (outcome, Some(message))
+        }
+        // the idempotency key itself was refused, which retrying will not fix
+        Err(err @ (AppError::Validation(_) | AppError::NotFound(_))) => {
+            (ScheduleRunOutcome::Failed, Some(err.to_string()))
+        }
+        Err(err) => return Err(err),
+    };
+
+    let mut tx = transaction_service::begin_write(db).await?;
+    sqlx::query(
+        "INSERT INTO SCHEDULE_RUNS (schedule_id, occurrence, scheduled_for, outcome, message)
+         VALUES (?, ?, ?, ?, ?);",
+    )
+    .bind(schedule.id)
+    .bind(schedule.occurrences)
+    .bind(scheduled_for)
+    .bind(outcome)
+    .bind(&message)
+    .execute(&mut *tx)
+    .await?;
+    if outcome == ScheduleRunOutcome::Retrying {
+        let retry_at = now + chrono::Duration::seconds(policy.retry_interval_secs);
+        sqlx::query(
+            "UPDATE SCHEDULES SET retry_count = retry_count + 1, due_at = ?,
+             updated_at = CURRENT_TIMESTAMP
+             WHERE id = ? AND status = 'active' AND occurrences = ? AND retry_count = ?;",
+        )
+        .bind(retry_at)
+        .bind(schedule.id)
+        .bind(schedule.occurrences)
+        .bind(schedule.retry_count)
+        .execute(&mut *tx)
+        .await?;
+    } else {
+        let next = Recurrence::of(schedule)?.occurrence(schedule.occurrences + 1, scheduled_for);
+        let status = if next.is_some() {
+            ScheduleStatus::Active
+        } else {
+            ScheduleStatus::Completed
+        };
+        // guarded so that a concurrent cancel stays cancelled
+        sqlx::query(
+            "UPDATE SCHEDULES SET occurrences = occurrences + 1, retry_count = 0,
+             next_run_at = ?, due_at = ?, status = ?, updated_at = CURRENT_TIMESTAMP
+             WHERE id = ? AND status = 'active' AND occurrences = ? AND retry_count = ?;",
+        )
+        .bind(next)
+        .bind(next)
+        .bind(status)
+        .bind(schedule.id)
+        .bind(schedule.occurrences)
+        .bind(schedule.retry_count)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Background task that runs due schedules every `poll_interval_secs`.
+pub async fn run_worker(db: SqlitePool, policy: SchedulePolicy, poll_interval_secs: u64) {
+    let mut interval = tokio::time::interval(Duration::from_secs(poll_interval_secs));
+    loop {
+        interval.tick().await;
+        match run_due_schedules(&db, &policy, chrono::Utc::now().naive_utc()).await {
+            Ok(0) => {}
+            Ok(attempted) => tracing::info!("Ran {} scheduled payments", attempted),
+            Err(err) => tracing::error!("Running schedules failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use crate::migrations;
+    use crate::models::schedule::ScheduleCreation;
+    use crate::services::user_service;
+
+    use super::*;
+
+    const POLICY: SchedulePolicy = SchedulePolicy {
+        retry_interval_secs: 60,
+        max_retries: 1,
+    };
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::Usd)
+    }
+
+    fn at(y: i32, m: u32, d: u32, h: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, 0, 0)
+            .unwrap()
+    }
+
+    async fn setup_db(funds: i64) -> (SqlitePool, String) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        for username in ["alice", "bob"] {
+            user_service::create_user(
+                &pool,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let account = account_service::create_account(
+            &pool,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        transaction_service::create_transaction(
+            &pool,
+            1,
+            TransactionCreation {
+                account_number: account.account_number.clone(),
+                seller: "Employer".to_string(),
+                amount: usd(-funds),
+            },
+        )
+        .await
+        .unwrap();
+        (pool, account.account_number)
+    }
+
+    fn creation(account_number: &str, frequency: ScheduleFrequency) -> ScheduleCreation {
+        ScheduleCreation {
+            account_number: account_number.to_string(),
+            seller: "Landlord".to_string(),
+            amount: usd(4_000),
+            frequency,
+            cron: None,
+            start_at: now() + chrono::Duration::hours(1),
+            end_at: None,
+            max_runs: None,
+            on_insufficient_funds: InsufficientFundsPolicy::Retry,
+        }
+    }
+
+    async fn balance(db: &SqlitePool, account_number: &str) -> Money {
+        account_service::get_account_by_account_number(db, account_number.to_string())
+            .await
+            .unwrap()
+            .balance
+    }
+
+    #[test]
+    fn test_monthly_occurrences_keep_the_day_of_the_month() {
+        let recurrence = Recurrence {
+            frequency: ScheduleFrequency::Monthly,
+            cron: None,
+            start_at: at(2026, 1, 31, 9),
+            end_at: Some(at(2026, 4, 30, 9)),
+            max_runs: None,
+        };
+        let mut previous = recurrence.start_at;
+        let mut occurrences = vec![];
+        for n in 0.. {
+            let Some(next) = recurrence.occurrence(n, previous) else {
+                break;
+            };
+            occurrences.push(next);
+            previous = next;
+        }
+        assert_eq!(
+            occurrences,
+            vec![
+                at(2026, 1, 31, 9),
+                at(2026, 2, 28, 9),
+                at(2026, 3, 31, 9),
+                at(2026, 4, 30, 9),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cron_accepts_five_fields() {
+        let recurrence = Recurrence {
+            frequency: ScheduleFrequency::Cron,
+            // 09:00 on the 1st and 15th
+            cron: Some(parse_cron("0 9 1,15 * *").unwrap()),
+            start_at: at(2026, 3, 1, 9),
+            end_at: None,
+            max_runs: Some(3),
+        };
+        let first = recurrence.occurrence(0, recurrence.start_at).unwrap();
+        assert_eq!(first, at(2026, 3, 1, 9));
+        let second = recurrence.occurrence(1, first).unwrap();
+        assert_eq!(second, at(2026, 3, 15, 9));
+        let third = recurrence.occurrence(2, second).unwrap();
+        assert_eq!(third, at(2026, 4, 1, 9));
+        assert_eq!(recurrence.occurrence(3, third), None);
+        assert!(matches!(parse_cron("* * *"), Err(AppError::Validation(_))));
+        assert!(matches!(
+            parse_cron("0 25 * * *"),
+            Err(AppError::Validation(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_create_schedule_validates() {
+        let (db, anumber) = setup_db(10_000).await;
+        let mut past = creation(&anumber, ScheduleFrequency::Once);
+        past.start_at = now() - chrono::Duration::hours(1);
+        let mut no_cron = creation(&anumber, ScheduleFrequency::Cron);
+        no_cron.cron = None;
+        let mut stray_cron = creation(&anumber, ScheduleFrequency::Daily);
+        stray_cron.cron = Some("0 9 * * *".to_string());
+        let mut euros = creation(&anumber, ScheduleFrequency::Daily);
+        euros.amount = Money::new(4_000, Currency::Eur);
+        let mut never = creation(&anumber, ScheduleFrequency::Cron);
+        // February 30th
+        never.cron = Some("0 9 30 2 *".to_string());
+        never.end_at = Some(never.start_at + chrono::Duration::days(400));
+        for invalid in [past, no_cron, stray_cron, euros, never] {
+            let res = create_schedule(&db, 1, invalid).await;
+            assert!(matches!(res, Err(AppError::Validation(_))), "{:?}", res);
+        }
+        let res = create_schedule(&db, 2, creation(&anumber, ScheduleFrequency::Once)).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_due_occurrences_post_and_advance() {
+        let (db, anumber) = setup_db(10_000).await;
+        let mut daily = creation(&anumber, ScheduleFrequency::Daily);
+        daily.max_runs = Some(2);
+        let schedule = create_schedule(&db, 1, daily).await.unwrap();
+        let first = schedule.start_at;
+        assert_eq!(schedule.next_run_at, Some(first));
+
+        // nothing is due yet
+        assert_eq!(run_due_schedules(&db, &POLICY, now()).await.unwrap(), 0);
+        assert_eq!(run_due_schedules(&db, &POLICY, first).await.unwrap(), 1);
+        assert_eq!(balance(&db, &anumber).await, usd(6_000));
+        let schedule = get_schedule(&db, 1, schedule.id).await.unwrap();
+        assert_eq!(schedule.occurrences, 1);
+        assert_eq!(
+            schedule.next_run_at,
+            Some(first + chrono::Duration::days(1))
+        );
+
+        // a worker running late catches up one occurrence at a time
+        let late = first + chrono::Duration::days(5);
+        assert_eq!(run_due_schedules(&db, &POLICY, late).await.unwrap(), 1);
+        assert_eq!(run_due_schedules(&db, &POLICY, late).await.unwrap(), 0);
+        let schedule = get_schedule(&db, 1, schedule.id).await.unwrap();
+        assert_eq!(schedule.status, ScheduleStatus::Completed);
+        assert_eq!(schedule.next_run_at, None);
+        assert_eq!(balance(&db, &anumber).await, usd(2_000));
+        let runs = get_schedule_runs(&db, 1, schedule.id).await.unwrap();
+        assert_eq!(runs.len(), 2);
+        assert!(
+            runs.iter()
+                .all(|run| run.outcome == ScheduleRunOutcome::Succeeded)
+        );
+        assert!(matches!(
+            get_schedule_runs(&db, 2, schedule.id).await,
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_insufficient_funds_retries_then_skips() {
+        let (db, anumber) = setup_db(3_000).await;
+        let mut weekly = creation(&anumber, ScheduleFrequency::Weekly);
+        weekly.max_runs = Some(2);
+        let schedule = create_schedule(&db, 1, weekly).await.unwrap();
+        let first = schedule.start_at;
+
+        run_due_schedules(&db, &POLICY, first).await.unwrap();
+        let retrying = get_schedule(&db, 1, schedule.id).await.unwrap();
+        assert_eq!(retrying.retry_count, 1);
+        assert_eq!(retrying.next_run_at, Some(first));
+        assert_eq!(retrying.due_at, Some(first + chrono::Duration::seconds(60)));
+        // not due again until the retry interval has passed
+        assert_eq!(run_due_schedules(&db, &POLICY, first).await.unwrap(), 0);
+
+        let retry_at = first + chrono::Duration::seconds(60);
+        run_due_schedules(&db, &POLICY, retry_at).await.unwrap();
+        let skipped = get_schedule(&db, 1, schedule.id).await.unwrap();
+        assert_eq!(skipped.occurrences, 1);
+        assert_eq!(skipped.retry_count, 0);
+        assert_eq!(
+            skipped.next_run_at,
+            Some(first + chrono::Duration::weeks(1))
+        );
+
+        let outcomes: Vec<ScheduleRunOutcome> = get_schedule_runs(&db, 1, schedule.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|run| run.outcome)
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![ScheduleRunOutcome::Retrying, ScheduleRunOutcome::Skipped]
+        );
+        assert_eq!(balance(&db, &anumber).await, usd(3_000));
+    }
+
+    #[tokio::test]
+    async fn test_skip_policy_does_not_retry() {
+        let (db, anumber) = setup_db(3_000).await;
+        let mut once = creation(&anumber, ScheduleFrequency::Once);
+        once.on_insufficient_funds = InsufficientFundsPolicy::Skip;
+        let schedule = create_schedule(&db, 1, once).await.unwrap();
+        run_due_schedules(&db, &POLICY, schedule.start_at)
+            .await
+            .unwrap();
+        let schedule = get_schedule(&db, 1, schedule.id).await.unwrap();
+        assert_eq!(schedule.status, ScheduleStatus::Completed);
+        let runs = get_schedule_runs(&db, 1, schedule.id).await.unwrap();
+        assert_eq!(runs.len(), 1);
+        assert_eq!(runs[0].outcome, ScheduleRunOutcome::Skipped);
+        assert!(runs[0].message.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_schedules_stop_running() {
+        let (db, anumber) = setup_db(10_000).await;
+        let schedule = create_schedule(&db, 1, creation(&anumber, ScheduleFrequency::Daily))
+            .await
+            .unwrap();
+        assert!(matches!(
+            cancel_schedule(&db, 2, schedule.id).await,
+            Err(AppError::NotFound(_))
+        ));
+        let cancelled = cancel_schedule(&db, 1, schedule.id).await.unwrap();
+        assert_eq!(cancelled.status, ScheduleStatus::Cancelled);
+        assert!(matches!(
+            cancel_schedule(&db, 1, schedule.id).await,
+            Err(AppError::Conflict(_))
+        ));
+        let later = schedule.start_at + chrono::Duration::days(3);
+        assert_eq!(run_due_schedules(&db, &POLICY, later).await.unwrap(), 0);
+        assert_eq!(balance(&db, &anumber).await, usd(10_000));
+    }
+}
@@ -0,0 +1,263 @@
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+use tokio::sync::broadcast;
+
+use crate::error::AppError;
+use crate::models::webhook::{Event, EventType};
+use crate::services::account_service;
+
+/// Events a subscriber may fall behind by before it is disconnected.
+pub const EVENT_BUFFER: usize = 1024;
+/// How often the outbox is checked for events to publish.
+pub const PUBLISH_INTERVAL_MILLIS: u64 = 250;
+/// The outbox events that are streamed to account subscribers.
+pub const STREAMED_EVENTS: [EventType; 2] =
+    [EventType::TransactionPosted, EventType::BalanceChanged];
+
+/// In-process broadcast of account events to open SSE and WebSocket streams.
+/// Events come from the outbox, so a stream only ever shows committed changes.
+#[derive(Debug, Clone)]
+pub struct EventHub {
+    sender: broadcast::Sender<Event>,
+}
+
+impl Default for EventHub {
+    fn default() -> Self {
+        EventHub {
+            sender: broadcast::channel(EVENT_BUFFER).0,
+        }
+    }
+}
+
+impl EventHub {
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+/// Whether a streamed event is about `account_number`.
+pub fn concerns(event: &Event, account_number: &str) -> bool {
+    event.data["account_number"].as_str() == Some(account_number)
+}
+
+/// Subscribes the caller to the events of one of their accounts. Returns the
+/// resolved account number, which is what events carry, and the receiver.
+pub async fn subscribe(
+    db: &SqlitePool,
+    hub: &EventHub,
+    user_id: i64,
+    account_number: &str,
+) -> Result<(String, broadcast::Receiver<Event>), AppError> {
+    tracing::info!("Invocation to `subscribe`");
+    let account_number = account_service::resolve_account_number(db, account_number).await?;
+    let mut conn = db.acquire().await?;
+    account_service::ensure_owned(&mut conn, user_id, &account_number).await?;
+    // subscribed before returning, so nothing committed from here on is missed
+    Ok((account_number, hub.subscribe()))
+}
+
+/// The id of the newest outbox event; publishing starts after it.
+pub async fn latest_event_id(db: &SqlitePool) -> Result<i64, AppError> {
+    let id: Option<i64> = sqlx::query_scalar("SELECT MAX(id) FROM OUTBOX_EVENTS;")
+        .fetch_one(db)
+        .await?;
+    Ok(id.unwrap_or(0))
+}
+
+/// Broadcasts the streamed outbox events recorded after `after`, in order.
+/// Returns the id to continue after.
+pub async fn publish_new(db: &SqlitePool, hub: &EventHub, after: i64) -> Result<i64, AppError> {
+    let events: Vec<Event> = sqlx::query_as(
+        "SELECT id AS event_id, event_type, payload, created_at AS event_created_at
+         FROM OUTBOX_EVENTS WHERE id > ? ORDER BY id LIMIT ?;",
+    )
+    .bind(after)
+    .bind(EVENT_BUFFER as i64)
+    .fetch_all(db)
+    .await?;
+    let mut last = after;
+    for event in events {
+        last = event.id;
+        if STREAMED_EVENTS.contains(&event.event_type) {
+            // fails only when nobody is listening
+            let _ = hub.sender.send(event);
+        }
+    }
+    Ok(last)
+}
+
+/// Background task that feeds the hub from the outbox every `PUBLISH_INTERVAL_MILLIS`.
+pub async fn run_publisher(db: SqlitePool, hub: EventHub) {
+    let mut after = match latest_event_id(&db).await {
+        Ok(id) => id,
+        Err(err) => {
+            tracing::error!("Could not start the event publisher: {}", err);
+            return;
+        }
+    };
+    let mut interval = tokio::time::interval(Duration::from_millis(PUBLISH_INTERVAL_MILLIS));
+    loop {
+        interval.tick().await;
+        match publish_new(&db, &hub, after).await {
+            Ok(last) => after = last,
+            Err(err) => tracing::error!("Publishing account events failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models;
+    use crate::models::hold::HoldCreation;
+    use crate::models::money::{Currency, Money};
+    use crate::models::transaction::TransactionCreation;
+    use crate::services::{hold_service, transaction_service, user_service};
+
+    use super::*;
+
+    fn usd(minor: i64) -> Money {
+        Money::new(minor, Currency::Usd)
+    }
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        for username in ["alice", "bob"] {
+            user_service::create_user(
+                &pool,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        pool
+    }
+
+    async fn open(db: &SqlitePool, user_id: i64) -> String {
+        account_service::create_account(
+            db,
+            &Default::default(),
+            user_id,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap()
+        .account_number
+    }
+
+    async fn post(db: &SqlitePool, account_number: &str, minor: i64) -> Result<(), AppError> {
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account_number.to_string(),
+                seller: "Shop".to_string(),
+                amount: usd(minor),
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
+    fn drain(receiver: &mut broadcast::Receiver<Event>) -> Vec<Event> {
+        std::iter::from_fn(|| receiver.try_recv().ok()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_publishes_committed_account_events_in_order() {
+        let db = setup_db().await;
+        let hub = EventHub::default();
+        let account = open(&db, 1).await;
+        let after = latest_event_id(&db).await.unwrap();
+        let (_, mut receiver) = subscribe(&db, &hub, 1, &account).await.unwrap();
+
+        post(&db, &account, -1_000).await.unwrap();
+        // rolled back, so never published
+        assert!(post(&db, &account, 5_000).await.is_err());
+        let last = publish_new(&db, &hub, after).await.unwrap();
+        assert_eq!(last, latest_event_id(&db).await.unwrap());
+
+        let events = drain(&mut receiver);
+        let types: Vec<EventType> = events.iter().map(|event| event.event_type).collect();
+        assert_eq!(
+            types,
+            vec![EventType::TransactionPosted, EventType::BalanceChanged]
+        );
+        assert!(events.iter().all(|event| concerns(event, &account)));
+        assert_eq!(events[1].data["balance"]["amount"], "10.00");
+        assert_eq!(events[1].data["available_balance"]["amount"], "10.00");
+
+        // nothing new, nothing sent twice
+        assert_eq!(publish_new(&db, &hub, last).await.unwrap(), last);
+        assert!(drain(&mut receiver).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_holds_change_the_available_balance() {
+        let db = setup_db().await;
+        let hub = EventHub::default();
+        let account = open(&db, 1).await;
+        post(&db, &account, -1_000).await.unwrap();
+        let after = latest_event_id(&db).await.unwrap();
+        let (_, mut receiver) = subscribe(&db, &hub, 1, &account).await.unwrap();
+        let hold = hold_service::authorize(
+            &db,
+            1,
+            HoldCreation {
+                account_number: account.clone(),
+                seller: "Hotel".to_string(),
+                amount: usd(400),
+                expires_in_secs: None,
+            },
+        )
+        .await
+        .unwrap();
+        hold_service::void(&db, 1, hold.id).await.unwrap();
+        publish_new(&db, &hub, after).await.unwrap();
+
+        let available: Vec<serde_json::Value> = drain(&mut receiver)
+            .into_iter()
+            .map(|event| {
+                assert_eq!(event.event_type, EventType::BalanceChanged);
+                event.data["available_balance"]["amount"].clone()
+            })
+            .collect();
+        assert_eq!(available, vec!["6.00", "10.00"]);
+    }
+
+    #[tokio::test]
+    async fn test_only_owners_can_subscribe() {
+        let db = setup_db().await;
+        let hub = EventHub::default();
+        let account = open(&db, 1).await;
+        let res = subscribe(&db, &hub, 2, &account).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+        let other = open(&db, 2).await;
+        let after = latest_event_id(&db).await.unwrap();
+        let (_, mut receiver) = subscribe(&db, &hub, 1, &account).await.unwrap();
+        transaction_service::create_transaction(
+            &db,
+            2,
+            TransactionCreation {
+                account_number: other.clone(),
+                seller: "Employer".to_string(),
+                amount: usd(-1_000),
+            },
+        )
+        .await
+        .unwrap();
+        publish_new(&db, &hub, after).await.unwrap();
+        // the hub carries every account's events; streams keep their own
+        let events = drain(&mut receiver);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|event| !concerns(event, &account)));
+    }
+}
@@ -0,0 +1,185 @@
+use std::sync::Arc;
+
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sqlx::SqlitePool;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models;
+use crate::services::user_service;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenKind {
+    Access,
+    Refresh,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    pub sub: i64, // USERS.id
+    pub typ: TokenKind,
+    pub iat: i64,
+    pub exp: i64,
+}
+
+/// HMAC-SHA256 signing keys plus token lifetimes, shared across handlers.
+#[derive(Clone)]
+pub struct TokenKeys {
+    inner: Arc<Keys>,
+}
+
+struct Keys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    access_ttl_secs: i64,
+    refresh_ttl_secs: i64,
+}
+
+impl TokenKeys {
+    pub fn new(secret: &[u8], access_ttl_secs: i64, refresh_ttl_secs: i64) -> Self {
+        TokenKeys {
+            inner: Arc::new(Keys {
+                encoding: EncodingKey::from_secret(secret),
+                decoding: DecodingKey::from_secret(secret),
+                access_ttl_secs,
+                refresh_ttl_secs,
+            }),
+        }
+    }
+    pub fn from_config(config: &Config) -> Self {
+        let secret = match &config.token_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                tracing::warn!("AUTH_TOKEN_SECRET is not set; tokens will not survive a restart");
+                rand::thread_rng().r#gen::<[u8; 32]>().to_vec()
+            }
+        };
+        TokenKeys::new(
+            &secret,
+            config.access_token_ttl_secs,
+            config.refresh_token_ttl_secs,
+        )
+    }
+
+    pub fn issue(&self, user_id: i64, kind: TokenKind) -> Result<String, AppError> {
+        let now = chrono::Utc::now().timestamp();
+        let ttl = match kind {
+            TokenKind::Access => self.inner.access_ttl_secs,
+            TokenKind::Refresh => self.inner.refresh_ttl_secs,
+        };
+        let claims = Claims {
+            sub: user_id,
+            typ: kind,
+            iat: now,
+            exp: now + ttl,
+        };
+        jsonwebtoken::encode(&Header::default(), &claims, &self.inner.encoding)
+            .map_err(|err| AppError::Internal(format!("Token signing failed: {}", err)))
+    }
+
+    /// Checks signature, expiry and that the token is of the expected kind,
+    /// so a refresh token can never be used as an access token or vice versa.
+    pub fn verify(&self, token: &str, kind: TokenKind) -> Result<Claims, AppError> {
+        let mut validation = Validation::default();
+        validation.leeway = 0;
+        let data = jsonwebtoken::decode::<Claims>(token, &self.inner.decoding, &validation)
+            .map_err(|err| AppError::Unauthorized(format!("Invalid token: {}", err)))?;
+        if data.claims.typ != kind {
+            return Err(AppError::Unauthorized("Wrong token type".to_string()));
+        }
+        Ok(data.claims)
+    }
+
+    pub fn issue_pair(
+        &self,
+        user: models::user::User,
+    ) -> Result<models::auth::TokenResponse, AppError> {
+        let user_id = user
+            .id
+            .ok_or_else(|| AppError::Internal("User has no id".to_string()))?
+            as i64;
+        Ok(models::auth::TokenResponse {
+            access_token: self.issue(user_id, TokenKind::Access)?,
+            refresh_token: self.issue(user_id, TokenKind::Refresh)?,
+            token_type: "Bearer".to_string(),
+            expires_in: self.inner.access_ttl_secs,
+            user,
+        })
+    }
+}
+
+/// Exchanges a valid refresh token for a fresh token pair.
+pub async fn refresh(
+    pool: &SqlitePool,
+    keys: &TokenKeys,
+    request: models::auth::RefreshRequest,
+) -> Result<models::auth::TokenResponse, AppError> {
+    tracing::info!("Invocation to `refresh`");
+    let claims = keys.verify(&request.refresh_token, TokenKind::Refresh)?;
+    // the user may have been deleted since the token was issued
+    let user = user_service::get_user(pool, claims.sub)
+        .await
+        .map_err(|err| match err {
+            AppError::NotFound(_) => AppError::Unauthorized("Unknown user".to_string()),
+            other => other,
+        })?;
+    keys.issue_pair(user)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys() -> TokenKeys {
+        TokenKeys::new(b"test secret", 60, 600)
+    }
+
+    #[test]
+    fn test_issue_and_verify() {
+        let keys = keys();
+        let token = keys.issue(7, TokenKind::Access).unwrap();
+        let claims = keys.verify(&token, TokenKind::Access).unwrap();
+        assert_eq!(claims.sub, 7);
+        assert_eq!(claims.exp - claims.iat, 60);
+    }
+
+    #[test]
+    fn test_token_kinds_are_not_interchangeable() {
+        let keys = keys();
+        let refresh = keys.issue(7, TokenKind::Refresh).unwrap();
+        assert!(matches!(
+            keys.verify(&refresh, TokenKind::Access),
+            Err(AppError::Unauthorized(_))
+        ));
+        let access = keys.issue(7, TokenKind::Access).unwrap();
+        assert!(keys.verify(&access, TokenKind::Refresh).is_err());
+    }
+
+    #[test]
+    fn test_rejects_other_secret() {
+        let token = keys().issue(7, TokenKind::Access).unwrap();
+        let other = TokenKeys::new(b"another secret", 60, 600);
+        assert!(other.verify(&token, TokenKind::Access).is_err());
+    }
+
+    #[test]
+    fn test_rejects_expired() {
+        let keys = TokenKeys::new(b"test secret", -10, 600);
+        let token = keys.issue(7, TokenKind::Access).unwrap();
+        assert!(keys.verify(&token, TokenKind::Access).is_err());
+    }
+
+    #[test]
+    fn test_rejects_tampered() {
+        let keys = keys();
+        let token = keys.issue(7, TokenKind::Access).unwrap();
+        let mut parts: Vec<&str> = token.split('.').collect();
+        let forged = keys.issue(8, TokenKind::Access).unwrap();
+        let forged_payload = forged.split('.').nth(1).unwrap();
+        parts[1] = forged_payload;
+        assert!(keys.verify(&parts.join("."), TokenKind::Access).is_err());
+    }
+}
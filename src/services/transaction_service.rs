@@ -1,81 +1,744 @@
-use sqlx::SqlitePool;
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite, SqliteConnection, SqlitePool, Transaction};
 
+use crate::error::AppError;
 use crate::models;
-use crate::models::transaction::{TransactionCreation, TransactionGeneral};
-use std::{str::FromStr, sync::Arc};
+use crate::models::audit::AuditAction;
+use crate::models::fee::{FeeCharge, FeeKind, FeePreview};
+use crate::models::money::Money;
+use crate::models::transaction::{TransactionPage, TransactionQuery, TransactionSort};
+use crate::models::webhook::EventType;
+use crate::services::ledger_service::{self, Posting};
+use crate::services::{
+    account_service, audit_service, budget_service, fee_service, merchant_service, outbox_service,
+};
+use sqlx::Row;
+
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+/// What a new TRANSACTIONS row belongs to. Stored in the same INSERT, so the
+/// row is complete when it is audited.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) enum PostingLink {
+    None,
+    /// A payment or deposit, with the merchant its seller matches.
+    Payment {
+        merchant_id: Option<i64>,
+    },
+    Transfer(i64),
+    /// A refund, linked to the same merchant as what it refunds.
+    RefundOf {
+        original_transaction_id: i64,
+        merchant_id: Option<i64>,
+    },
+    Fee {
+        kind: FeeKind,
+        for_transaction_id: Option<i64>,
+    },
+}
+
+/// Position after the last row of a page: the sort it was taken under, that
+/// row's sort key and its id as a tie-breaker. Clients only see it base64-encoded.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+struct Cursor {
+    sort: TransactionSort,
+    key: String,
+    id: i64,
+}
+
+fn encode_cursor(cursor: &Cursor) -> Result<String, AppError> {
+    let json = serde_json::to_vec(cursor)
+        .map_err(|err| AppError::Internal(format!("Could not encode cursor: {}", err)))?;
+    Ok(URL_SAFE_NO_PAD.encode(json))
+}
+
+fn decode_cursor(raw: &str, sort: TransactionSort) -> Result<Cursor, AppError> {
+    let invalid = || AppError::Validation("Invalid cursor".to_string());
+    let bytes = URL_SAFE_NO_PAD.decode(raw).map_err(|_| invalid())?;
+    let cursor: Cursor = serde_json::from_slice(&bytes).map_err(|_| invalid())?;
+    if cursor.sort != sort {
+        return Err(AppError::Validation(
+            "Cursor was issued for a different sort order".to_string(),
+        ));
+    }
+    Ok(cursor)
+}
+
+fn escape_like(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
 
 pub async fn get_transactions(
     db: &SqlitePool,
-) -> Result<Vec<models::transaction::TransactionGeneral>, Box<dyn std::error::Error>> {
+    user_id: i64,
+    query: &TransactionQuery,
+) -> Result<TransactionPage, AppError> {
     tracing::info!("Invocation to `get_transactions`");
-    let transactions: Vec<models::transaction::TransactionGeneral> =
-        sqlx::query_as("SELECT id, account_number, seller, amount FROM TRANSACTIONS;")
-            .fetch_all(db)
-            .await?;
-    Ok(transactions)
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let (column, descending) = match query.sort {
+        TransactionSort::CreatedAtAsc => ("t.created_at", false),
+        TransactionSort::CreatedAtDesc => ("t.created_at", true),
+        TransactionSort::AmountAsc => ("t.amount", false),
+        TransactionSort::AmountDesc => ("t.amount", true),
+    };
+    if let (Some(from), Some(to)) = (query.from, query.to)
+        && from > to
+    {
+        return Err(AppError::Validation("`from` is after `to`".to_string()));
+    }
+    let account_number = match &query.account_number {
+        Some(input) => Some(account_service::resolve_account_number(db, input).await?),
+        None => None,
+    };
+
+    let mut builder: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT t.id, t.account_number, t.seller, t.amount, t.currency, t.transfer_id,
+         t.original_transaction_id, t.refunded_amount, t.fee_kind, t.fee_for_transaction_id,
+         t.merchant_id, t.created_at
+         FROM TRANSACTIONS t JOIN ACCOUNTS a ON a.account_number = t.account_number
+         WHERE a.user_id = ",
+    );
+    builder.push_bind(user_id);
+    if let Some(account_number) = &account_number {
+        builder
+            .push(" AND t.account_number = ")
+            .push_bind(account_number.clone());
+    }
+    if let Some(from) = query.from {
+        builder
+            .push(" AND t.created_at >= ")
+            .push_bind(from.format("%Y-%m-%d").to_string());
+    }
+    if let Some(to) = query.to {
+        let end = to
+            .succ_opt()
+            .ok_or_else(|| AppError::Validation("Invalid `to` date".to_string()))?;
+        builder
+            .push(" AND t.created_at < ")
+            .push_bind(end.format("%Y-%m-%d").to_string());
+    }
+    if query.min_amount.is_some() || query.max_amount.is_some() {
+        // amounts are only comparable within one currency
+        let currency = query.currency.unwrap_or_default();
+        builder.push(" AND t.currency = ").push_bind(currency);
+        if let Some(min) = &query.min_amount {
+            let min = Money::parse(min, currency)?;
+            builder
+                .push(" AND t.amount >= ")
+                .push_bind(min.minor_units());
+        }
+        if let Some(max) = &query.max_amount {
+            let max = Money::parse(max, currency)?;
+            builder
+                .push(" AND t.amount <= ")
+                .push_bind(max.minor_units());
+        }
+    } else if let Some(currency) = query.currency {
+        builder.push(" AND t.currency = ").push_bind(currency);
+    }
+    if let Some(seller) = query.seller.as_deref().filter(|s| !s.is_empty()) {
+        // LIKE is case-insensitive for ASCII in SQLite
+        builder
+            .push(" AND t.seller LIKE ")
+            .push_bind(format!("%{}%", escape_like(seller)))
+            .push(" ESCAPE '\\'");
+    }
+    if let Some(merchant_id) = query.merchant_id {
+        builder.push(" AND t.merchant_id = ").push_bind(merchant_id);
+    }
+    if let Some(raw) = &query.cursor {
+        let cursor = decode_cursor(raw, query.sort)?;
+        let op = if descending { "<" } else { ">" };
+        builder.push(format!(" AND ({} {} ", column, op));
+        push_cursor_key(&mut builder, query.sort, &cursor.key)?;
+        builder.push(format!(" OR ({} = ", column));
+        push_cursor_key(&mut builder, query.sort, &cursor.key)?;
+        builder
+            .push(format!(" AND t.id {} ", op))
+            .push_bind(cursor.id)
+            .push("))");
+    }
+    let direction = if descending { "DESC" } else { "ASC" };
+    builder.push(format!(
+        " ORDER BY {} {}, t.id {} LIMIT ",
+        column, direction, direction
+    ));
+    // one extra row tells us whether there is a next page
+    builder.push_bind(limit as i64 + 1);
+
+    let mut items: Vec<models::transaction::TransactionGeneral> =
+        builder.build_query_as().fetch_all(db).await?;
+    let next_cursor = if items.len() > limit as usize {
+        items.truncate(limit as usize);
+        let last = items.last().expect("page is not empty");
+        let key = match query.sort {
+            TransactionSort::CreatedAtAsc | TransactionSort::CreatedAtDesc => {
+                last.created_at.format("%Y-%m-%d %H:%M:%S").to_string()
+            }
+            TransactionSort::AmountAsc | TransactionSort::AmountDesc => {
+                last.amount.minor_units().to_string()
+            }
+        };
+        Some(encode_cursor(&Cursor {
+            sort: query.sort,
+            key,
+            id: last.id.unwrap_or_default() as i64,
+        })?)
+    } else {
+        None
+    };
+    Ok(TransactionPage { items, next_cursor })
+}
+
+fn push_cursor_key(
+    builder: &mut QueryBuilder<Sqlite>,
+    sort: TransactionSort,
+    key: &str,
+) -> Result<(), AppError> {
+    match sort {
+        TransactionSort::CreatedAtAsc | TransactionSort::CreatedAtDesc => {
+            builder.push_bind(key.to_string());
+        }
+        TransactionSort::AmountAsc | TransactionSort::AmountDesc => {
+            let amount: i64 = key
+                .parse()
+                .map_err(|_| AppError::Validation("Invalid cursor".to_string()))?;
+            builder.push_bind(amount);
+        }
+    }
+    Ok(())
 }
 pub async fn create_transaction(
     db: &SqlitePool,
+    user_id: i64,
     transaction_creation: models::transaction::TransactionCreation,
-) -> Result<models::transaction::TransactionCreation, Box<dyn std::error::Error>> {
+) -> Result<models::transaction::TransactionReceipt, AppError> {
     tracing::info!("Invocation to `create_transaction`");
-    let mut tx = db.begin().await?;
-    // get account and checck balance
-    let account_number = transaction_creation.account_number.to_string();
-    let mut balance: f32 =
-        sqlx::query_scalar("SELECT balance FROM ACCOUNTS WHERE account_number = ?;")
-            .bind(&account_number)
-            .fetch_one(&mut *tx)
+    let mut tx = begin_write(db).await?;
+    let receipt = create_transaction_in(&mut tx, user_id, transaction_creation).await?;
+    tx.commit().await?;
+    Ok(receipt)
+}
+
+/// `create_transaction` inside the caller's database transaction, for callers
+/// that must commit other changes together with it.
+pub(crate) async fn create_transaction_in(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    transaction_creation: models::transaction::TransactionCreation,
+) -> Result<models::transaction::TransactionReceipt, AppError> {
+    // respond with the account number even if the client sent an IBAN
+    let account_number =
+        account_service::resolve_account_number_in(conn, &transaction_creation.account_number)
             .await?;
-    if transaction_creation.amount > balance {
-        return Err("Insufficient funds".into());
-    }
-    sqlx::query("INSERT INTO TRANSACTIONS (account_number, seller, amount) VALUES (?, ?, ?);")
+    let amount = transaction_creation.amount;
+    account_service::ensure_owned(conn, user_id, &account_number).await?;
+    account_service::ensure_active(conn, &account_number).await?;
+    post_payment(
+        conn,
+        account_number,
+        transaction_creation.seller,
+        amount,
+        ledger_service::counterparty_for(amount),
+    )
+    .await
+}
+
+/// The fees `create_transaction` would charge for the same request, worked out
+/// without posting anything. Whether the transaction would go through, e.g.
+/// have sufficient funds, is not checked.
+pub async fn preview_transaction(
+    db: &SqlitePool,
+    user_id: i64,
+    transaction_creation: models::transaction::TransactionCreation,
+) -> Result<models::transaction::TransactionPreview, AppError> {
+    tracing::info!("Invocation to `preview_transaction`");
+    let mut conn = db.acquire().await?;
+    let account_number =
+        account_service::resolve_account_number_in(&mut conn, &transaction_creation.account_number)
+            .await?;
+    let amount = transaction_creation.amount;
+    account_service::ensure_owned(&mut conn, user_id, &account_number).await?;
+    account_service::ensure_active(&mut conn, &account_number).await?;
+    let merchant_id =
+        merchant_service::match_seller(&mut conn, &transaction_creation.seller).await?;
+    let row = sqlx::query("SELECT balance, currency FROM ACCOUNTS WHERE account_number = ?;")
         .bind(&account_number)
-        .bind(&transaction_creation.seller)
-        .bind(&transaction_creation.amount.to_string())
+        .fetch_one(&mut *conn)
+        .await?;
+    let balance = Money::new(row.try_get("balance")?, row.try_get("currency")?);
+    // in the order a real posting charges them, each seeing the balance left
+    // by what came before it
+    let mut fees = Vec::new();
+    if amount.is_positive() {
+        let mut pending = amount;
+        if balance.checked_sub(amount)?.is_negative() {
+            for fee in fee_service::fees_due(
+                &mut conn,
+                &account_number,
+                FeeKind::Overdraft,
+                Some(amount),
+                Some(pending),
+            )
+            .await?
+            {
+                fees.push(FeePreview {
+                    kind: FeeKind::Overdraft,
+                    amount: fee,
+                });
+            }
+            for fee in &fees {
+                pending = pending.checked_add(fee.amount)?;
+            }
+        }
+        for fee in fee_service::fees_due(
+            &mut conn,
+            &account_number,
+            FeeKind::PerTransaction,
+            Some(amount),
+            Some(pending),
+        )
+        .await?
+        {
+            fees.push(FeePreview {
+                kind: FeeKind::PerTransaction,
+                amount: fee,
+            });
+        }
+    }
+    Ok(models::transaction::TransactionPreview {
+        account_number,
+        seller: transaction_creation.seller,
+        merchant_id,
+        amount,
+        fees,
+    })
+}
+
+/// Posts a payment or deposit together with everything that follows one: the
+/// link to the merchant its seller matches, budget alerts and per-transaction
+/// fees. Shared by `create_transaction_in` and hold captures, inside the
+/// caller's database transaction and with no ownership or status checks.
+pub(crate) async fn post_payment(
+    conn: &mut SqliteConnection,
+    account_number: String,
+    seller: String,
+    amount: Money,
+    counterparty: &str,
+) -> Result<models::transaction::TransactionReceipt, AppError> {
+    let merchant_id = merchant_service::match_seller(conn, &seller).await?;
+    let id = post_to_account(
+        conn,
+        &account_number,
+        &seller,
+        amount,
+        counterparty,
+        PostingLink::Payment { merchant_id },
+    )
+    .await?;
+    budget_service::evaluate(conn, id).await?;
+    if amount.is_positive() {
+        charge_fees(
+            conn,
+            &account_number,
+            FeeKind::PerTransaction,
+            Some(amount),
+            Some(id),
+        )
+        .await?;
+    }
+    let fees: Vec<FeeCharge> = sqlx::query_as(
+        "SELECT id, fee_kind, amount, currency FROM TRANSACTIONS
+         WHERE fee_for_transaction_id = ? ORDER BY id;",
+    )
+    .bind(id)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(models::transaction::TransactionReceipt {
+        id,
+        account_number,
+        seller,
+        merchant_id,
+        amount,
+        fees,
+    })
+}
+
+async fn get_transaction(
+    conn: &mut SqliteConnection,
+    user_id: i64,
+    id: i64,
+) -> Result<models::transaction::TransactionGeneral, AppError> {
+    let transaction: Option<models::transaction::TransactionGeneral> = sqlx::query_as(
+        "SELECT t.id, t.account_number, t.seller, t.amount, t.currency, t.transfer_id,
+         t.original_transaction_id, t.refunded_amount, t.fee_kind, t.fee_for_transaction_id,
+         t.merchant_id, t.created_at
+         FROM TRANSACTIONS t JOIN ACCOUNTS a ON a.account_number = t.account_number
+         WHERE t.id = ? AND a.user_id = ?;",
+    )
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(conn)
+    .await?;
+    transaction.ok_or_else(|| AppError::NotFound(format!("Transaction {} not found", id)))
+}
+
+/// Undoes all or part of a posted transaction with a linked compensating one.
+/// Refunds of a transaction never add up to more than it.
+pub async fn refund_transaction(
+    db: &SqlitePool,
+    user_id: i64,
+    id: i64,
+    refund_creation: models::transaction::RefundCreation,
+) -> Result<models::transaction::TransactionGeneral, AppError> {
+    tracing::info!("Invocation to `refund_transaction`");
+    let mut tx = begin_write(db).await?;
+    let original = get_transaction(&mut tx, user_id, id).await?;
+    account_service::ensure_active(&mut tx, &original.account_number).await?;
+    if original.original_transaction_id.is_some() {
+        return Err(AppError::Validation(
+            "A refund cannot itself be refunded".to_string(),
+        ));
+    }
+    if original.transfer_id.is_some() {
+        return Err(AppError::Validation(
+            "Transfers cannot be refunded; transfer the money back instead".to_string(),
+        ));
+    }
+    let original_amount = original.amount;
+    let refundable = if original_amount.is_negative() {
+        original_amount.checked_neg()?
+    } else {
+        original_amount
+    }
+    .checked_sub(original.refunded_amount)?;
+    let amount = refund_creation.amount.unwrap_or(refundable);
+    if !amount.is_positive() {
+        return Err(AppError::Validation(
+            "Refund amount must be positive".to_string(),
+        ));
+    }
+    if refundable.checked_sub(amount)?.is_negative() {
+        return Err(AppError::Conflict(format!(
+            "Only {} of transaction {} is left to refund",
+            refundable, id
+        )));
+    }
+    // the original's limit, enforced in the same statement that consumes it
+    let claimed = sqlx::query(
+        "UPDATE TRANSACTIONS SET refunded_amount = refunded_amount + ?, updated_at = CURRENT_TIMESTAMP
+         WHERE id = ? AND refunded_amount + ? <= abs(amount);",
+    )
+    .bind(amount.minor_units())
+    .bind(id)
+    .bind(amount.minor_units())
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    if claimed == 0 {
+        return Err(AppError::Conflict(format!(
+            "Transaction {} is already refunded",
+            id
+        )));
+    }
+    // flows back the way the original came, against the same system account
+    let compensating = if original_amount.is_positive() {
+        amount.checked_neg()?
+    } else {
+        amount
+    };
+    let refund_id = post_to_account(
+        &mut tx,
+        &original.account_number,
+        &original.seller,
+        compensating,
+        ledger_service::counterparty_for(original_amount),
+        PostingLink::RefundOf {
+            original_transaction_id: id,
+            merchant_id: original.merchant_id,
+        },
+    )
+    .await?;
+    let refunded = get_transaction(&mut tx, user_id, id).await?;
+    audit_service::record(
+        &mut tx,
+        AuditAction::TransactionRefund,
+        id,
+        Some(&original),
+        Some(&refunded),
+    )
+    .await?;
+    let refund = get_transaction(&mut tx, user_id, refund_id).await?;
+    tx.commit().await?;
+    Ok(refund)
+}
+
+/// Begins a transaction that takes SQLite's write lock up front. A deferred
+/// transaction that reads before it writes fails with SQLITE_BUSY instead of
+/// waiting if another writer commits in between.
+pub(crate) async fn begin_write(db: &SqlitePool) -> Result<Transaction<'static, Sqlite>, AppError> {
+    let mut tx = db.begin().await?;
+    // a write statement matching no rows still acquires the lock
+    sqlx::query("UPDATE ACCOUNTS SET version = version WHERE 0;")
         .execute(&mut *tx)
         .await?;
-    balance -= transaction_creation.amount;
-    sqlx::query("UPDATE ACCOUNTS SET balance = ? WHERE account_number = ?;")
-        .bind(balance.to_string())
+    Ok(tx)
+}
+
+/// Records one TRANSACTIONS row and moves the account balance by it, inside the
+/// caller's database transaction. Positive amounts debit the account and need
+/// sufficient available funds (pending holds excluded, overdraft limit
+/// included); negative amounts credit it. The matching journal entry posts the
+/// other side against `counterparty`. A debit that leaves the balance below
+/// zero is followed by its overdraft fees, if any. Returns the new row id.
+pub(crate) async fn post_to_account(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+    seller: &str,
+    amount: Money,
+    counterparty: &str,
+    link: PostingLink,
+) -> Result<i64, AppError> {
+    // check and move the balance in one statement, so concurrent postings
+    // cannot both pass the check on the same stale balance
+    let updated = sqlx::query(
+        "UPDATE ACCOUNTS SET balance = balance - ?, version = version + 1, updated_at = CURRENT_TIMESTAMP
+         WHERE account_number = ? AND currency = ? AND balance - held + overdraft_limit >= ? AND balance <= ?
+         RETURNING balance;",
+    )
+    .bind(amount.minor_units())
+    .bind(account_number)
+    .bind(amount.currency())
+    .bind(amount.minor_units())
+    .bind(i64::MAX.saturating_add(amount.minor_units().min(0)))
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(updated) = updated else {
+        // work out why, for the error
+        let row = sqlx::query("SELECT balance, currency FROM ACCOUNTS WHERE account_number = ?;")
+            .bind(account_number)
+            .fetch_optional(&mut *conn)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Account {} not found", account_number)))?;
+        let balance = Money::new(row.try_get("balance")?, row.try_get("currency")?);
+        balance.checked_sub(amount)?;
+        return Err(AppError::InsufficientFunds);
+    };
+    let transaction_id =
+        record_posting(conn, account_number, seller, amount, counterparty, link).await?;
+
+    account_service::record_balance_changed(conn, account_number).await?;
+
+    let balance: i64 = updated.try_get("balance")?;
+    if amount.is_positive() && balance < 0 {
+        charge_fees(
+            conn,
+            account_number,
+            FeeKind::Overdraft,
+            Some(amount),
+            Some(transaction_id),
+        )
+        .await?;
+    }
+    Ok(transaction_id)
+}
+
+/// Charges the fees of `kind` the account owes (see `fee_service::fees_due`)
+/// as separate transactions against `SYS:FEES`, linked to the transaction that
+/// caused them. Fees are charged even where they take the account past its
+/// overdraft limit. Returns the fee transactions' ids.
+pub(crate) async fn charge_fees(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+    kind: FeeKind,
+    base: Option<Money>,
+    for_transaction_id: Option<i64>,
+) -> Result<Vec<i64>, AppError> {
+    let mut charged = Vec::new();
+    for fee in fee_service::fees_due(conn, account_number, kind, base, None).await? {
+        sqlx::query(
+            "UPDATE ACCOUNTS SET balance = balance - ?, version = version + 1, updated_at = CURRENT_TIMESTAMP
+             WHERE account_number = ?;",
+        )
+        .bind(fee.minor_units())
         .bind(account_number)
-        .execute(&mut *tx)
+        .execute(&mut *conn)
         .await?;
-    tx.commit().await?;
-    Ok(transaction_creation)
+        let id = record_posting(
+            conn,
+            account_number,
+            kind.description(),
+            fee,
+            ledger_service::FEES,
+            PostingLink::Fee {
+                kind,
+                for_transaction_id,
+            },
+        )
+        .await?;
+        account_service::record_balance_changed(conn, account_number).await?;
+        charged.push(id);
+    }
+    Ok(charged)
+}
+
+/// Inserts the TRANSACTIONS row, journal entry and audit entry for a balance
+/// change the caller has already made. Every row is created here, so every row
+/// is audited. Returns the new row id.
+async fn record_posting(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+    seller: &str,
+    amount: Money,
+    counterparty: &str,
+    link: PostingLink,
+) -> Result<i64, AppError> {
+    let (transfer_id, original_transaction_id, fee_kind, fee_for_transaction_id, merchant_id) =
+        match link {
+            PostingLink::None => (None, None, None, None, None),
+            PostingLink::Payment { merchant_id } => (None, None, None, None, merchant_id),
+            PostingLink::Transfer(id) => (Some(id), None, None, None, None),
+            PostingLink::RefundOf {
+                original_transaction_id,
+                merchant_id,
+            } => (None, Some(original_transaction_id), None, None, merchant_id),
+            PostingLink::Fee {
+                kind,
+                for_transaction_id,
+            } => (None, None, Some(kind), for_transaction_id, None),
+        };
+    let res = sqlx::query(
+        "INSERT INTO TRANSACTIONS (account_number, seller, amount, currency, transfer_id,
+         original_transaction_id, fee_kind, fee_for_transaction_id, merchant_id)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+    )
+    .bind(account_number)
+    .bind(seller)
+    .bind(amount.minor_units())
+    .bind(amount.currency())
+    .bind(transfer_id)
+    .bind(original_transaction_id)
+    .bind(fee_kind)
+    .bind(fee_for_transaction_id)
+    .bind(merchant_id)
+    .execute(&mut *conn)
+    .await?;
+    let transaction_id = res.last_insert_rowid();
+    ledger_service::post_entry(
+        conn,
+        seller,
+        Some(transaction_id),
+        &[
+            Posting {
+                ledger_account: account_number,
+                amount,
+            },
+            Posting {
+                ledger_account: counterparty,
+                amount: amount.checked_neg()?,
+            },
+        ],
+    )
+    .await?;
+    outbox_service::record(
+        conn,
+        EventType::TransactionPosted,
+        &serde_json::json!({
+            "id": transaction_id,
+            "account_number": account_number,
+            "seller": seller,
+            "amount": amount,
+            "transfer_id": transfer_id,
+            "merchant_id": merchant_id,
+        }),
+    )
+    .await?;
+    let posted = load_transaction(&mut *conn, transaction_id).await?;
+    audit_service::record(
+        conn,
+        AuditAction::TransactionCreate,
+        transaction_id,
+        None,
+        Some(&posted),
+    )
+    .await?;
+    Ok(transaction_id)
+}
+
+/// A transaction by id, whoever owns it.
+async fn load_transaction(
+    conn: &mut SqliteConnection,
+    id: i64,
+) -> Result<models::transaction::TransactionGeneral, AppError> {
+    let transaction: models::transaction::TransactionGeneral = sqlx::query_as(
+        "SELECT id, account_number, seller, amount, currency, transfer_id, original_transaction_id,
+         refunded_amount, fee_kind, fee_for_transaction_id, merchant_id, created_at
+         FROM TRANSACTIONS WHERE id = ?;",
+    )
+    .bind(id)
+    .fetch_one(conn)
+    .await?;
+    Ok(transaction)
+}
+
+/// Links a posted transaction to a merchant, or unlinks it with `None`, inside
+/// the caller's database transaction. The change is audited.
+pub(crate) async fn set_merchant(
+    conn: &mut SqliteConnection,
+    id: i64,
+    merchant_id: Option<i64>,
+) -> Result<(), AppError> {
+    let before = load_transaction(&mut *conn, id).await?;
+    sqlx::query(
+        "UPDATE TRANSACTIONS SET merchant_id = ?, updated_at = CURRENT_TIMESTAMP WHERE id = ?;",
+    )
+    .bind(merchant_id)
+    .bind(id)
+    .execute(&mut *conn)
+    .await?;
+    let after = load_transaction(&mut *conn, id).await?;
+    audit_service::record(
+        conn,
+        AuditAction::TransactionSetMerchant,
+        id,
+        Some(&before),
+        Some(&after),
+    )
+    .await?;
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        queries,
-        services::{account_service, user_service},
+        migrations, models::money::Currency, models::transaction::TransactionCreation,
+        services::user_service,
     };
 
     use super::*;
 
     async fn setup_db() -> SqlitePool {
         let pool = SqlitePool::connect(":memory:").await.unwrap();
-        sqlx::query(queries::CREATE_TABLE_USER)
-            .execute(&pool)
-            .await
-            .unwrap();
-        sqlx::query(queries::CREATE_TABLE_ACCOUNT)
-            .execute(&pool)
-            .await
-            .unwrap();
-        sqlx::query(queries::CREATE_TABLE_TRANSACTION)
-            .execute(&pool)
-            .await
-            .unwrap();
+        migrations::run(&pool).await.unwrap();
         pool
     }
 
     #[tokio::test]
     async fn test_get_transactions_empty() {
         let db = setup_db().await;
-        let result = get_transactions(&db).await.unwrap();
+        let result = get_transactions(&db, 1, &TransactionQuery::default())
+            .await
+            .unwrap()
+            .items;
         assert!(result.is_empty());
     }
 
@@ -91,32 +754,39 @@ mod tests {
         )
         .await
         .unwrap();
-        let account_creation = models::account::AccountCreation { user_id: 1 };
-        let account = account_service::create_account(&db, account_creation.clone())
-            .await
-            .unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let account =
+            account_service::create_account(&db, &Default::default(), 1, account_creation.clone())
+                .await
+                .unwrap();
         let anumber = account.account_number.clone();
         let tx = TransactionCreation {
             account_number: anumber.clone(),
             seller: "TestSeller".to_string(),
-            amount: -50.0,
+            amount: Money::new(-5000, Currency::Usd),
         };
-        let result = create_transaction(&db, tx.clone()).await.unwrap();
+        let result = create_transaction(&db, 1, tx.clone()).await.unwrap();
         assert_eq!(result.account_number, anumber.clone());
         assert_eq!(result.seller, "TestSeller");
-        assert_eq!(result.amount, -50.0);
+        assert_eq!(result.amount, Money::new(-5000, Currency::Usd));
 
-        let transactions = get_transactions(&db).await.unwrap();
+        let transactions = get_transactions(&db, 1, &TransactionQuery::default())
+            .await
+            .unwrap()
+            .items;
         assert_eq!(transactions.len(), 1);
         assert_eq!(transactions[0].account_number, anumber.clone());
         assert_eq!(transactions[0].seller, "TestSeller");
-        assert_eq!(transactions[0].amount, -50.0);
+        assert_eq!(transactions[0].amount, Money::new(-5000, Currency::Usd));
     }
 
     #[tokio::test]
-    async fn test_create_transaction_insufficient_funds() {
+    async fn test_create_transaction_by_iban() {
         let db = setup_db().await;
-        let _ = user_service::create_user(
+        user_service::create_user(
             &db,
             models::user::UserCreation {
                 username: "test_user".to_string(),
@@ -125,17 +795,241 @@ mod tests {
         )
         .await
         .unwrap();
-        let account_creation = models::account::AccountCreation { user_id: 1 };
-        let account = account_service::create_account(&db, account_creation.clone())
+        let account = account_service::create_account(
+            &db,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        let tx = TransactionCreation {
+            account_number: account.iban.clone().unwrap(),
+            seller: "TestSeller".to_string(),
+            amount: Money::new(-5000, Currency::Usd),
+        };
+        let result = create_transaction(&db, 1, tx).await.unwrap();
+        assert_eq!(result.account_number, account.account_number);
+        let page = get_transactions(
+            &db,
+            1,
+            &TransactionQuery {
+                account_number: account.iban,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.items.len(), 1);
+    }
+
+    async fn setup_refunds() -> (SqlitePool, String) {
+        let db = setup_db().await;
+        user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account = account_service::create_account(
+            &db,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        for (seller, minor) in [("Employer", -10_000), ("Grocer", 3_000)] {
+            create_transaction(
+                &db,
+                1,
+                TransactionCreation {
+                    account_number: account.account_number.clone(),
+                    seller: seller.to_string(),
+                    amount: Money::new(minor, Currency::Usd),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        (db, account.account_number)
+    }
+
+    fn refund_of(minor: i64) -> models::transaction::RefundCreation {
+        models::transaction::RefundCreation {
+            amount: Some(Money::new(minor, Currency::Usd)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_refunds_are_linked_and_capped() {
+        let (db, anumber) = setup_refunds().await;
+        let partial = refund_transaction(&db, 1, 2, refund_of(1_000))
+            .await
+            .unwrap();
+        assert_eq!(partial.original_transaction_id, Some(2));
+        assert_eq!(partial.amount, Money::new(-1_000, Currency::Usd));
+        // the rest, by default
+        let rest = refund_transaction(&db, 1, 2, Default::default())
+            .await
+            .unwrap();
+        assert_eq!(rest.amount, Money::new(-2_000, Currency::Usd));
+        let again = refund_transaction(&db, 1, 2, refund_of(1)).await;
+        assert!(matches!(again, Err(AppError::Conflict(_))));
+
+        let account = account_service::get_account_by_account_number(&db, anumber)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, Money::new(10_000, Currency::Usd));
+        let items = get_transactions(&db, 1, &TransactionQuery::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(items[1].refunded_amount, Money::new(3_000, Currency::Usd));
+        assert_eq!(
+            items
+                .iter()
+                .filter(|t| t.original_transaction_id == Some(2))
+                .count(),
+            2
+        );
+        assert!(
+            crate::services::ledger_service::reconcile(&db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_refund_rejections() {
+        let (db, _) = setup_refunds().await;
+        let too_much = refund_transaction(&db, 1, 2, refund_of(3_001)).await;
+        assert!(matches!(too_much, Err(AppError::Conflict(_))));
+        for bad in [
+            refund_of(0),
+            models::transaction::RefundCreation {
+                amount: Some(Money::new(100, Currency::Eur)),
+            },
+        ] {
+            let res = refund_transaction(&db, 1, 2, bad).await;
+            assert!(matches!(res, Err(AppError::Validation(_))));
+        }
+        let refund = refund_transaction(&db, 1, 2, refund_of(100)).await.unwrap();
+        let res = refund_transaction(&db, 1, refund.id.unwrap() as i64, refund_of(100)).await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+        // someone else's transaction is invisible
+        let res = refund_transaction(&db, 2, 2, refund_of(100)).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_reversing_a_deposit_needs_funds() {
+        let (db, _) = setup_refunds().await;
+        // 70.00 left of the 100.00 deposit
+        let res = refund_transaction(&db, 1, 1, Default::default()).await;
+        assert!(matches!(res, Err(AppError::InsufficientFunds)));
+        let reversed = refund_transaction(&db, 1, 1, refund_of(7_000))
+            .await
+            .unwrap();
+        assert_eq!(reversed.amount, Money::new(7_000, Currency::Usd));
+        let items = get_transactions(&db, 1, &TransactionQuery::default())
+            .await
+            .unwrap()
+            .items;
+        assert_eq!(items[0].refunded_amount, Money::new(7_000, Currency::Usd));
+    }
+
+    #[tokio::test]
+    async fn test_overdraft_limit_and_fee() {
+        let (db, anumber) = setup_refunds().await;
+        let usd = |minor| Money::new(minor, Currency::Usd);
+        account_service::set_overdraft(
+            &db,
+            &anumber,
+            models::account::OverdraftSetting {
+                limit: usd(5_000),
+                fee: Some(usd(500)),
+            },
+        )
+        .await
+        .unwrap();
+        let debit = |minor| TransactionCreation {
+            account_number: anumber.clone(),
+            seller: "Garage".to_string(),
+            amount: usd(minor),
+        };
+        // 70.00 in the account; staying above zero costs nothing
+        create_transaction(&db, 1, debit(7_000)).await.unwrap();
+        create_transaction(&db, 1, debit(3_000)).await.unwrap();
+        let account = account_service::get_account_by_account_number(&db, anumber.clone())
+            .await
+            .unwrap();
+        assert_eq!(account.balance, usd(-3_500));
+        assert_eq!(account.available_balance, usd(1_500));
+
+        let res = create_transaction(&db, 1, debit(1_501)).await;
+        assert!(matches!(res, Err(AppError::InsufficientFunds)));
+        // the fee is charged even past the limit
+        create_transaction(&db, 1, debit(1_500)).await.unwrap();
+        let account = account_service::get_account_by_account_number(&db, anumber.clone())
             .await
             .unwrap();
+        assert_eq!(account.balance, usd(-5_500));
+
+        let fees: Vec<Money> = get_transactions(&db, 1, &TransactionQuery::default())
+            .await
+            .unwrap()
+            .items
+            .into_iter()
+            .filter(|t| t.fee_kind == Some(FeeKind::Overdraft))
+            .map(|t| t.amount)
+            .collect();
+        assert_eq!(fees, vec![usd(500), usd(500)]);
+        assert!(
+            crate::services::ledger_service::reconcile(&db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_transaction_insufficient_funds() {
+        let db = setup_db().await;
+        let _ = user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let account =
+            account_service::create_account(&db, &Default::default(), 1, account_creation.clone())
+                .await
+                .unwrap();
         let anumber = account.account_number.clone();
         let tx = TransactionCreation {
             account_number: anumber.clone(),
             seller: "TestSeller".to_string(),
-            amount: 200.0,
+            amount: Money::new(20000, Currency::Usd),
         };
-        let result = create_transaction(&db, tx).await;
+        let result = create_transaction(&db, 1, tx).await;
         assert!(result.is_err());
         assert_eq!(result.unwrap_err().to_string(), "Insufficient funds");
     }
@@ -152,25 +1046,32 @@ mod tests {
         )
         .await
         .unwrap();
-        let account_creation = models::account::AccountCreation { user_id: 1 };
-        let account = account_service::create_account(&db, account_creation.clone())
-            .await
-            .unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let account =
+            account_service::create_account(&db, &Default::default(), 1, account_creation.clone())
+                .await
+                .unwrap();
         let anumber = account.account_number.clone();
         let tx1 = TransactionCreation {
             account_number: anumber.clone(),
             seller: "Employer1".to_string(),
-            amount: -100.0,
+            amount: Money::new(-10000, Currency::Usd),
         };
         let tx2 = TransactionCreation {
             account_number: anumber.clone(),
             seller: "Seller2".to_string(),
-            amount: 20.0,
+            amount: Money::new(2000, Currency::Usd),
         };
-        create_transaction(&db, tx1).await.unwrap();
-        create_transaction(&db, tx2).await.unwrap();
+        create_transaction(&db, 1, tx1).await.unwrap();
+        create_transaction(&db, 1, tx2).await.unwrap();
 
-        let transactions = get_transactions(&db).await.unwrap();
+        let transactions = get_transactions(&db, 1, &TransactionQuery::default())
+            .await
+            .unwrap()
+            .items;
         assert_eq!(transactions.len(), 2);
         assert_eq!(transactions[0].seller, "Employer1");
         assert_eq!(transactions[1].seller, "Seller2");
@@ -188,32 +1089,37 @@ mod tests {
         )
         .await
         .unwrap();
-        let account_creation = models::account::AccountCreation { user_id: 1 };
-        let account = account_service::create_account(&db, account_creation.clone())
-            .await
-            .unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let account =
+            account_service::create_account(&db, &Default::default(), 1, account_creation.clone())
+                .await
+                .unwrap();
         let anumber = account.account_number.clone();
         let tx = TransactionCreation {
             account_number: anumber.clone(),
             seller: "SellerC".to_string(),
-            amount: -100.0,
+            amount: Money::new(-10000, Currency::Usd),
         };
-        let _ = create_transaction(&db, tx.clone()).await.unwrap();
+        let _ = create_transaction(&db, 1, tx.clone()).await.unwrap();
         let result = create_transaction(
             &db,
+            1,
             TransactionCreation {
-                amount: 0f32,
+                amount: Money::zero(Currency::Usd),
                 ..tx.clone()
             },
         )
         .await
         .unwrap();
-        assert_eq!(result.amount, 0.0);
+        assert_eq!(result.amount, Money::zero(Currency::Usd));
 
         let account = account_service::get_account_by_account_number(&db, anumber.clone())
             .await
             .unwrap();
-        assert_eq!(account.balance, 100.0);
+        assert_eq!(account.balance, Money::new(10000, Currency::Usd));
     }
 
     #[tokio::test]
@@ -228,17 +1134,485 @@ mod tests {
         )
         .await
         .unwrap();
-        let account_creation = models::account::AccountCreation { user_id: 1 };
-        let account = account_service::create_account(&db, account_creation.clone())
-            .await
-            .unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let account =
+            account_service::create_account(&db, &Default::default(), 1, account_creation.clone())
+                .await
+                .unwrap();
         let anumber = account.account_number.clone();
         let tx = TransactionCreation {
             account_number: anumber.clone(),
             seller: "SellerD".to_string(),
-            amount: -10.0,
+            amount: Money::new(-1000, Currency::Usd),
         };
-        let result = create_transaction(&db, tx).await;
+        let result = create_transaction(&db, 1, tx).await;
         assert!(result.is_ok());
     }
+
+    #[tokio::test]
+    async fn test_create_transaction_cents_do_not_drift() {
+        let db = setup_db().await;
+        let _ = user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let account =
+            account_service::create_account(&db, &Default::default(), 1, account_creation.clone())
+                .await
+                .unwrap();
+        let anumber = account.account_number.clone();
+        for _ in 0..300 {
+            let tx = TransactionCreation {
+                account_number: anumber.clone(),
+                seller: "Employer".to_string(),
+                amount: Money::parse("-0.10", Currency::Usd).unwrap(),
+            };
+            create_transaction(&db, 1, tx).await.unwrap();
+        }
+        let account = account_service::get_account_by_account_number(&db, anumber.clone())
+            .await
+            .unwrap();
+        assert_eq!(account.balance.format_amount(), "30.00");
+    }
+
+    #[tokio::test]
+    async fn test_create_transaction_currency_mismatch() {
+        let db = setup_db().await;
+        let _ = user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account_creation = models::account::AccountCreation {
+            currency: Currency::Usd,
+            product: None,
+        };
+        let account =
+            account_service::create_account(&db, &Default::default(), 1, account_creation.clone())
+                .await
+                .unwrap();
+        let tx = TransactionCreation {
+            account_number: account.account_number.clone(),
+            seller: "TestSeller".to_string(),
+            amount: Money::new(-100, Currency::Eur),
+        };
+        let result = create_transaction(&db, 1, tx).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+        assert!(
+            get_transactions(&db, 1, &TransactionQuery::default())
+                .await
+                .unwrap()
+                .items
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_transaction_unknown_account() {
+        let db = setup_db().await;
+        let tx = TransactionCreation {
+            account_number: "1000123456789013".to_string(),
+            seller: "TestSeller".to_string(),
+            amount: Money::new(100, Currency::Usd),
+        };
+        let result = create_transaction(&db, 1, tx.clone()).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+        // one digit off fails the check digit before any lookup
+        let tx = TransactionCreation {
+            account_number: "1000123456789014".to_string(),
+            ..tx
+        };
+        let result = create_transaction(&db, 1, tx).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_transactions_are_scoped_to_owner() {
+        let db = setup_db().await;
+        for username in ["owner", "intruder"] {
+            user_service::create_user(
+                &db,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let account = account_service::create_account(
+            &db,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        let tx = TransactionCreation {
+            account_number: account.account_number.clone(),
+            seller: "Employer".to_string(),
+            amount: Money::new(-1000, Currency::Usd),
+        };
+        create_transaction(&db, 1, tx.clone()).await.unwrap();
+
+        // the other user can neither post against the account nor see its history
+        let result = create_transaction(&db, 2, tx).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+        assert!(
+            get_transactions(&db, 2, &TransactionQuery::default())
+                .await
+                .unwrap()
+                .items
+                .is_empty()
+        );
+        assert_eq!(
+            get_transactions(&db, 1, &TransactionQuery::default())
+                .await
+                .unwrap()
+                .items
+                .len(),
+            1
+        );
+    }
+
+    async fn setup_listing() -> (SqlitePool, String, String) {
+        let db = setup_db().await;
+        user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let mut numbers = Vec::new();
+        for _ in 0..2 {
+            let account = account_service::create_account(
+                &db,
+                &Default::default(),
+                1,
+                models::account::AccountCreation {
+                    currency: Currency::Usd,
+                    product: None,
+                },
+            )
+            .await
+            .unwrap();
+            numbers.push(account.account_number);
+        }
+        // ten rows over ten days, alternating accounts, amounts 1.00 ... 10.00
+        for i in 1..=10i64 {
+            sqlx::query(
+                "INSERT INTO TRANSACTIONS (account_number, seller, amount, currency, created_at)
+                 VALUES (?, ?, ?, 'USD', ?);",
+            )
+            .bind(&numbers[(i % 2) as usize])
+            .bind(if i % 3 == 0 {
+                "AMAZON.COM"
+            } else {
+                "Corner 100% Shop"
+            })
+            .bind(i * 100)
+            .bind(format!("2025-01-{:02} 12:00:00", i))
+            .execute(&db)
+            .await
+            .unwrap();
+        }
+        (db, numbers[0].clone(), numbers[1].clone())
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_filters() {
+        let (db, even, _) = setup_listing().await;
+        let page = |query: TransactionQuery| {
+            let db = db.clone();
+            async move { get_transactions(&db, 1, &query).await.unwrap().items }
+        };
+        let by_account = page(TransactionQuery {
+            account_number: Some(even.clone()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(by_account.len(), 5);
+        assert!(by_account.iter().all(|t| t.account_number == even));
+
+        let by_date = page(TransactionQuery {
+            from: Some(chrono::NaiveDate::from_ymd_opt(2025, 1, 3).unwrap()),
+            to: Some(chrono::NaiveDate::from_ymd_opt(2025, 1, 5).unwrap()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(by_date.len(), 3);
+
+        let by_amount = page(TransactionQuery {
+            min_amount: Some("2.50".to_string()),
+            max_amount: Some("5".to_string()),
+            ..Default::default()
+        })
+        .await;
+        let amounts: Vec<i64> = by_amount.iter().map(|t| t.amount.minor_units()).collect();
+        assert_eq!(amounts, vec![300, 400, 500]);
+
+        let by_seller = page(TransactionQuery {
+            seller: Some("amazon".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(by_seller.len(), 3);
+        // wildcards in the search text are matched literally
+        let literal = page(TransactionQuery {
+            seller: Some("100%".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert_eq!(literal.len(), 7);
+        let none = page(TransactionQuery {
+            seller: Some("_".to_string()),
+            ..Default::default()
+        })
+        .await;
+        assert!(none.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_sorting() {
+        let (db, _, _) = setup_listing().await;
+        let items = get_transactions(
+            &db,
+            1,
+            &TransactionQuery {
+                sort: TransactionSort::AmountDesc,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap()
+        .items;
+        let amounts: Vec<i64> = items.iter().map(|t| t.amount.minor_units()).collect();
+        assert_eq!(amounts, (1..=10).rev().map(|i| i * 100).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_cursor_pagination() {
+        let (db, _, _) = setup_listing().await;
+        for sort in [
+            TransactionSort::CreatedAtAsc,
+            TransactionSort::CreatedAtDesc,
+            TransactionSort::AmountAsc,
+            TransactionSort::AmountDesc,
+        ] {
+            let mut seen = Vec::new();
+            let mut cursor = None;
+            loop {
+                let page = get_transactions(
+                    &db,
+                    1,
+                    &TransactionQuery {
+                        sort,
+                        limit: Some(3),
+                        cursor: cursor.clone(),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+                assert!(page.items.len() <= 3);
+                seen.extend(page.items.iter().map(|t| t.id.unwrap()));
+                match page.next_cursor {
+                    Some(next) => cursor = Some(next),
+                    None => break,
+                }
+            }
+            let mut expected: Vec<i32> = (1..=10).collect();
+            if matches!(
+                sort,
+                TransactionSort::CreatedAtDesc | TransactionSort::AmountDesc
+            ) {
+                expected.reverse();
+            }
+            assert_eq!(seen, expected, "{:?}", sort);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_rejects_bad_cursor() {
+        let (db, _, _) = setup_listing().await;
+        let page = get_transactions(
+            &db,
+            1,
+            &TransactionQuery {
+                limit: Some(2),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        let res = get_transactions(
+            &db,
+            1,
+            &TransactionQuery {
+                sort: TransactionSort::AmountDesc,
+                cursor: page.next_cursor,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+        let res = get_transactions(
+            &db,
+            1,
+            &TransactionQuery {
+                cursor: Some("garbage".to_string()),
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_transactions_page_size_is_capped() {
+        let (db, account, _) = setup_listing().await;
+        let page = get_transactions(
+            &db,
+            1,
+            &TransactionQuery {
+                limit: Some(0),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert!(page.next_cursor.is_some());
+
+        // 510 rows in all, more than the largest page
+        sqlx::query(
+            "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n WHERE i < 500)
+             INSERT INTO TRANSACTIONS (account_number, seller, amount, currency, created_at)
+             SELECT ?, 'Shop', 100, 'USD', '2025-02-01 12:00:00' FROM n;",
+        )
+        .bind(&account)
+        .execute(&db)
+        .await
+        .unwrap();
+        let page = get_transactions(
+            &db,
+            1,
+            &TransactionQuery {
+                limit: Some(MAX_PAGE_SIZE + 1),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(page.items.len(), MAX_PAGE_SIZE as usize);
+        assert!(page.next_cursor.is_some());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_postings_do_not_lose_updates() {
+        use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions};
+        use std::str::FromStr;
+
+        // a file, so the pool really has several connections racing each other
+        let path = std::env::temp_dir().join(format!(
+            "crustacean-capital-stress-{}-{}.db",
+            std::process::id(),
+            chrono::Utc::now().timestamp_nanos_opt().unwrap()
+        ));
+        let options = SqliteConnectOptions::from_str(&format!("sqlite://{}", path.display()))
+            .unwrap()
+            .create_if_missing(true)
+            .journal_mode(SqliteJournalMode::Wal);
+        let db = SqlitePoolOptions::new()
+            .max_connections(8)
+            .connect_with(options)
+            .await
+            .unwrap();
+        migrations::run(&db).await.unwrap();
+        user_service::create_user(
+            &db,
+            models::user::UserCreation {
+                username: "test_user".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account_number = account_service::create_account(
+            &db,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap()
+        .account_number;
+        let post = |minor: i64| {
+            let db = db.clone();
+            let creation = TransactionCreation {
+                account_number: account_number.clone(),
+                seller: "Seller".to_string(),
+                amount: Money::new(minor, Currency::Usd),
+            };
+            tokio::spawn(async move { create_transaction(&db, 1, creation).await })
+        };
+        post(-10_000).await.unwrap().unwrap();
+
+        // 100 debits of 3.00 against 100.00 and 20 deposits of 1.00, all at once
+        let handles: Vec<_> = (0..120)
+            .map(|i| post(if i % 6 == 0 { -100 } else { 300 }))
+            .collect();
+        let mut debited = 0;
+        for (i, handle) in handles.into_iter().enumerate() {
+            match handle.await.unwrap() {
+                Ok(_) if i % 6 == 0 => {}
+                Ok(_) => debited += 300,
+                Err(AppError::InsufficientFunds) if i % 6 != 0 => {}
+                Err(err) => panic!("posting {} failed: {:?}", i, err),
+            }
+        }
+        let account = account_service::get_account_by_account_number(&db, account_number)
+            .await
+            .unwrap();
+        assert_eq!(
+            account.balance,
+            Money::new(10_000 + 20 * 100 - debited, Currency::Usd)
+        );
+        assert!(!account.balance.is_negative());
+        assert!(
+            crate::services::ledger_service::reconcile(&db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+        db.close().await;
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+    }
 }
@@ -0,0 +1,355 @@
+use sqlx::{SqliteConnection, SqlitePool};
+
+use crate::error::AppError;
+use crate::models;
+use crate::models::fee::FeeKind;
+use crate::models::money::{Currency, Money};
+use crate::models::webhook::EventType;
+use crate::services::transaction_service::PostingLink;
+use crate::services::{account_service, ledger_service, outbox_service, transaction_service};
+
+pub async fn get_transfer(
+    conn: &mut SqliteConnection,
+    id: i64,
+) -> Result<models::transfer::Transfer, AppError> {
+    tracing::info!("Invocation to `get_transfer`");
+    let transfer: Option<models::transfer::Transfer> = sqlx::query_as(
+        "SELECT id, source_account_number, destination_account_number, amount, currency,
+         credited_amount, credited_currency, rate, created_at FROM TRANSFERS WHERE id = ?;",
+    )
+    .bind(id)
+    .fetch_optional(conn)
+    .await?;
+    transfer.ok_or_else(|| AppError::NotFound(format!("Transfer {} not found", id)))
+}
+
+async fn account_currency(
+    conn: &mut SqliteConnection,
+    account_number: &str,
+) -> Result<Currency, AppError> {
+    let currency: Option<Currency> =
+        sqlx::query_scalar("SELECT currency FROM ACCOUNTS WHERE account_number = ?;")
+            .bind(account_number)
+            .fetch_optional(conn)
+            .await?;
+    currency.ok_or_else(|| AppError::NotFound(format!("Account {} not found", account_number)))
+}
+
+/// Moves money from one of the caller's accounts to any other account. Both
+/// legs and the transfer record commit together or not at all.
+pub async fn create_transfer(
+    db: &SqlitePool,
+    user_id: i64,
+    transfer_creation: models::transfer::TransferCreation,
+) -> Result<models::transfer::Transfer, AppError> {
+    tracing::info!("Invocation to `create_transfer`");
+    let source =
+        account_service::resolve_account_number(db, &transfer_creation.source_account_number)
+            .await?;
+    let destination =
+        account_service::resolve_account_number(db, &transfer_creation.destination_account_number)
+            .await?;
+    let (source, destination) = (source.as_str(), destination.as_str());
+    let amount = transfer_creation.amount;
+    if source == destination {
+        return Err(AppError::Validation(
+            "Cannot transfer to the same account".to_string(),
+        ));
+    }
+    if !amount.is_positive() {
+        return Err(AppError::Validation(
+            "Transfer amount must be positive".to_string(),
+        ));
+    }
+
+    let mut tx = transaction_service::begin_write(db).await?;
+    account_service::ensure_owned(&mut tx, user_id, source).await?;
+    account_service::ensure_active(&mut tx, source).await?;
+    account_service::ensure_active(&mut tx, destination).await?;
+    let transfer = post_transfer(
+        &mut tx,
+        source,
+        destination,
+        amount,
+        transfer_creation.rate.as_deref(),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(transfer)
+}
+
+/// Records a transfer and posts both of its legs inside the caller's database
+/// transaction, with no ownership or status checks. `rate` is required
+/// between currencies and refused within one.
+pub(crate) async fn post_transfer(
+    conn: &mut SqliteConnection,
+    source: &str,
+    destination: &str,
+    amount: Money,
+    rate: Option<&str>,
+) -> Result<models::transfer::Transfer, AppError> {
+    let source_currency = account_currency(&mut *conn, source).await?;
+    let destination_currency = account_currency(&mut *conn, destination).await?;
+    if amount.currency() != source_currency {
+        return Err(AppError::Validation(format!(
+            "Amount must be in the source account's currency ({})",
+            source_currency
+        )));
+    }
+    let credited = match (rate, source_currency == destination_currency) {
+        (None, true) => amount,
+        (Some(_), true) => {
+            return Err(AppError::Validation(
+                "A rate only applies to cross-currency transfers".to_string(),
+            ));
+        }
+        (None, false) => {
+            return Err(AppError::Validation(format!(
+                "Transfers from {} to {} need an explicit rate",
+                source_currency, destination_currency
+            )));
+        }
+        (Some(rate), false) => amount.convert(rate, destination_currency)?,
+    };
+    if !credited.is_positive() {
+        return Err(AppError::Validation(
+            "Converted amount rounds to zero".to_string(),
+        ));
+    }
+
+    let res = sqlx::query(
+        "INSERT INTO TRANSFERS (source_account_number, destination_account_number, amount, currency,
+         credited_amount, credited_currency, rate) VALUES (?, ?, ?, ?, ?, ?, ?);",
+    )
+    .bind(source)
+    .bind(destination)
+    .bind(amount.minor_units())
+    .bind(amount.currency())
+    .bind(credited.minor_units())
+    .bind(credited.currency())
+    .bind(rate)
+    .execute(&mut *conn)
+    .await?;
+    let transfer_id = res.last_insert_rowid();
+    let debit_id = transaction_service::post_to_account(
+        &mut *conn,
+        source,
+        &format!("Transfer to {}", destination),
+        amount,
+        ledger_service::TRANSFER_CLEARING,
+        PostingLink::Transfer(transfer_id),
+    )
+    .await?;
+    if source_currency != destination_currency {
+        transaction_service::charge_fees(
+            &mut *conn,
+            source,
+            FeeKind::ForeignCurrency,
+            Some(amount),
+            Some(debit_id),
+        )
+        .await?;
+    }
+    transaction_service::post_to_account(
+        &mut *conn,
+        destination,
+        &format!("Transfer from {}", source),
+        credited.checked_neg()?,
+        ledger_service::TRANSFER_CLEARING,
+        PostingLink::Transfer(transfer_id),
+    )
+    .await?;
+    let transfer = get_transfer(&mut *conn, transfer_id).await?;
+    outbox_service::record(conn, EventType::TransferCompleted, &transfer).await?;
+    Ok(transfer)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::migrations;
+    use crate::models::money::Money;
+    use crate::models::transaction::TransactionCreation;
+    use crate::models::transfer::TransferCreation;
+    use crate::services::user_service;
+
+    use super::*;
+
+    async fn setup_db() -> SqlitePool {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        for username in ["alice", "bob"] {
+            user_service::create_user(
+                &pool,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        pool
+    }
+
+    async fn funded_account(
+        db: &SqlitePool,
+        user_id: i64,
+        currency: Currency,
+        minor: i64,
+    ) -> String {
+        let account = account_service::create_account(
+            db,
+            &Default::default(),
+            user_id,
+            models::account::AccountCreation {
+                currency,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        if minor > 0 {
+            transaction_service::create_transaction(
+                db,
+                user_id,
+                TransactionCreation {
+                    account_number: account.account_number.clone(),
+                    seller: "Deposit".to_string(),
+                    amount: Money::new(-minor, currency),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        account.account_number
+    }
+
+    async fn balance(db: &SqlitePool, account_number: &str) -> Money {
+        account_service::get_account_by_account_number(db, account_number.to_string())
+            .await
+            .unwrap()
+            .balance
+    }
+
+    #[tokio::test]
+    async fn test_transfer_moves_money_and_links_legs() {
+        let db = setup_db().await;
+        let from = funded_account(&db, 1, Currency::Usd, 10_000).await;
+        let to = funded_account(&db, 2, Currency::Usd, 0).await;
+        let transfer = create_transfer(
+            &db,
+            1,
+            TransferCreation {
+                source_account_number: from.clone(),
+                destination_account_number: to.clone(),
+                amount: Money::new(2_500, Currency::Usd),
+                rate: None,
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(transfer.credited_amount, Money::new(2_500, Currency::Usd));
+        assert_eq!(balance(&db, &from).await, Money::new(7_500, Currency::Usd));
+        assert_eq!(balance(&db, &to).await, Money::new(2_500, Currency::Usd));
+
+        let legs: Vec<(String, i64)> = sqlx::query_as(
+            "SELECT account_number, amount FROM TRANSACTIONS WHERE transfer_id = ? ORDER BY id;",
+        )
+        .bind(transfer.id)
+        .fetch_all(&db)
+        .await
+        .unwrap();
+        assert_eq!(legs, vec![(from, 2_500), (to, -2_500)]);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_insufficient_funds_rolls_back() {
+        let db = setup_db().await;
+        let from = funded_account(&db, 1, Currency::Usd, 1_000).await;
+        let to = funded_account(&db, 2, Currency::Usd, 0).await;
+        let res = create_transfer(
+            &db,
+            1,
+            TransferCreation {
+                source_account_number: from.clone(),
+                destination_account_number: to.clone(),
+                amount: Money::new(1_001, Currency::Usd),
+                rate: None,
+            },
+        )
+        .await;
+        assert!(matches!(res, Err(AppError::InsufficientFunds)));
+        assert_eq!(balance(&db, &from).await, Money::new(1_000, Currency::Usd));
+        assert_eq!(balance(&db, &to).await, Money::zero(Currency::Usd));
+        let transfers: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM TRANSFERS;")
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        assert_eq!(transfers, 0);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_rejects_invalid_requests() {
+        let db = setup_db().await;
+        let from = funded_account(&db, 1, Currency::Usd, 1_000).await;
+        let euros = funded_account(&db, 2, Currency::Eur, 0).await;
+        let dollars = funded_account(&db, 2, Currency::Usd, 0).await;
+        let request = |destination: &str, amount: Money, rate: Option<&str>| TransferCreation {
+            source_account_number: from.clone(),
+            destination_account_number: destination.to_string(),
+            amount,
+            rate: rate.map(str::to_string),
+        };
+        let usd = |minor| Money::new(minor, Currency::Usd);
+        for (creation, expect_not_found) in [
+            (request(&from, usd(100), None), false),
+            (request(&dollars, usd(0), None), false),
+            (request(&dollars, usd(-100), None), false),
+            (
+                request(&dollars, Money::new(100, Currency::Eur), None),
+                false,
+            ),
+            (request(&dollars, usd(100), Some("1.1")), false),
+            (request(&euros, usd(100), None), false),
+            (request(&euros, usd(1), Some("0.4")), false),
+            (request("0000000000000000", usd(100), None), true),
+        ] {
+            let res = create_transfer(&db, 1, creation.clone()).await;
+            if expect_not_found {
+                assert!(matches!(res, Err(AppError::NotFound(_))), "{:?}", creation);
+            } else {
+                assert!(
+                    matches!(res, Err(AppError::Validation(_))),
+                    "{:?}",
+                    creation
+                );
+            }
+        }
+        // the source must belong to the caller
+        let res = create_transfer(&db, 2, request(&dollars, usd(100), None)).await;
+        assert!(matches!(res, Err(AppError::NotFound(_))));
+        assert_eq!(balance(&db, &from).await, usd(1_000));
+    }
+
+    #[tokio::test]
+    async fn test_cross_currency_transfer_with_rate() {
+        let db = setup_db().await;
+        let from = funded_account(&db, 1, Currency::Usd, 10_000).await;
+        let to = funded_account(&db, 2, Currency::Eur, 0).await;
+        let transfer = create_transfer(
+            &db,
+            1,
+            TransferCreation {
+                source_account_number: from.clone(),
+                destination_account_number: to.clone(),
+                amount: Money::new(10_000, Currency::Usd),
+                rate: Some("0.9215".to_string()),
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(transfer.rate.as_deref(), Some("0.9215"));
+        assert_eq!(balance(&db, &from).await, Money::zero(Currency::Usd));
+        assert_eq!(balance(&db, &to).await, Money::new(9_215, Currency::Eur));
+    }
+}
@@ -1,9 +1,12 @@
+use crate::error::AppError;
 use crate::models;
+use crate::models::audit::AuditAction;
+use crate::models::webhook::EventType;
+use crate::services::{audit_service, auth_service, outbox_service};
+use serde_json::json;
 use sqlx::SqlitePool;
 
-pub async fn get_users(
-    pool: &SqlitePool,
-) -> Result<Vec<models::user::User>, Box<dyn std::error::Error>> {
+pub async fn get_users(pool: &SqlitePool) -> Result<Vec<models::user::User>, AppError> {
     tracing::info!("Invocation to `get_users`");
     let users: Vec<models::user::User> =
         sqlx::query_as("SELECT id, username, created_at, updated_at FROM USERS;")
@@ -11,48 +14,119 @@ pub async fn get_users(
             .await?;
     Ok(users)
 }
-pub async fn get_user(
-    pool: &SqlitePool,
-    id: i64,
-) -> Result<models::user::User, Box<dyn std::error::Error>> {
+pub async fn get_user(pool: &SqlitePool, id: i64) -> Result<models::user::User, AppError> {
     tracing::info!("Invocation to `get_user`");
-    let user: models::user::User =
+    let user: Option<models::user::User> =
         sqlx::query_as("SELECT id, username, created_at, updated_at FROM USERS WHERE id = ?;")
-            .bind(&id)
-            .fetch_one(pool)
+            .bind(id)
+            .fetch_optional(pool)
             .await?;
-    Ok(user)
+    user.ok_or_else(|| AppError::NotFound(format!("User {} not found", id)))
 }
 pub async fn create_user(
     pool: &SqlitePool,
     user: models::user::UserCreation,
-) -> Result<models::user::User, Box<dyn std::error::Error>> {
+) -> Result<models::user::User, AppError> {
     tracing::info!("Invocation to `create_user`");
     if user.username.is_empty() || user.password.is_empty() {
-        return Err("Missing required fields".into());
+        return Err(AppError::Validation("Missing required fields".to_string()));
     }
+    let password = user.password.clone();
+    let password_hash =
+        auth_service::run_blocking(move || auth_service::hash_password(&password)).await??;
+    let mut tx = pool.begin().await?;
     let res = sqlx::query("INSERT INTO USERS (username, password) VALUES (?, ?);")
         .bind(user.username.as_str())
-        .bind(user.password.as_str())
-        .execute(pool)
-        .await?;
+        .bind(password_hash)
+        .execute(&mut *tx)
+        .await
+        .map_err(|err| match AppError::from(err) {
+            AppError::Conflict(_) => {
+                AppError::Conflict(format!("Username {} is already taken", user.username))
+            }
+            other => other,
+        })?;
 
-    let created = get_user(pool, res.last_insert_rowid()).await?;
+    let id = res.last_insert_rowid();
+    let created: models::user::User =
+        sqlx::query_as("SELECT id, username, created_at, updated_at FROM USERS WHERE id = ?;")
+            .bind(id)
+            .fetch_one(&mut *tx)
+            .await?;
+    outbox_service::record(&mut tx, EventType::UserCreated, &created).await?;
+    audit_service::record(&mut tx, AuditAction::UserCreate, id, None, Some(&created)).await?;
+    tx.commit().await?;
     Ok(created)
 }
 
+pub async fn is_admin(pool: &SqlitePool, id: i64) -> Result<bool, AppError> {
+    let role: Option<String> = sqlx::query_scalar("SELECT role FROM USERS WHERE id = ?;")
+        .bind(id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(role.as_deref() == Some("admin"))
+}
+
+/// Gives the listed users the admin role and takes it from everyone else, so
+/// `ADMIN_USER_IDS` is the whole truth. Returns how many admins there are.
+///
+/// Admins are listed by id rather than username: an id is only known once its
+/// user has signed up, whereas anyone could register a listed name first. Ids
+/// nobody holds are skipped with a warning.
+pub async fn sync_admins(pool: &SqlitePool, ids: &[i64]) -> Result<usize, AppError> {
+    tracing::info!("Invocation to `sync_admins`");
+    let mut tx = pool.begin().await?;
+    let users: Vec<(i64, String, String)> =
+        sqlx::query_as("SELECT id, username, role FROM USERS ORDER BY id;")
+            .fetch_all(&mut *tx)
+            .await?;
+    for wanted in ids {
+        if !users.iter().any(|(id, _, _)| id == wanted) {
+            tracing::warn!(
+                "ADMIN_USER_IDS lists user {} who does not exist; skipping",
+                wanted
+            );
+        }
+    }
+    let mut admins = 0;
+    for (id, username, role) in users {
+        let wanted = if ids.contains(&id) {
+            admins += 1;
+            "admin"
+        } else {
+            "customer"
+        };
+        if role == wanted {
+            continue;
+        }
+        sqlx::query("UPDATE USERS SET role = ? WHERE id = ?;")
+            .bind(wanted)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+        // only actual changes are audited, so a restart leaves no trace
+        audit_service::record(
+            &mut tx,
+            AuditAction::UserChangeRole,
+            id,
+            Some(&json!({ "id": id, "username": username, "role": role })),
+            Some(&json!({ "id": id, "username": username, "role": wanted })),
+        )
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(admins)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::queries;
+    use crate::migrations;
 
     use super::*;
 
     async fn setup_pool() -> SqlitePool {
         let pool = SqlitePool::connect(":memory:").await.unwrap();
-        sqlx::query(queries::CREATE_TABLE_USER)
-            .execute(&pool)
-            .await
-            .unwrap();
+        migrations::run(&pool).await.unwrap();
         pool
     }
 
@@ -112,7 +186,7 @@ mod tests {
             password: "pass2".to_string(),
         };
         let result = create_user(&pool, duplicate_user.clone()).await;
-        assert!(result.is_err());
+        assert!(matches!(result, Err(AppError::Conflict(_))));
     }
 
     #[tokio::test]
@@ -124,6 +198,68 @@ mod tests {
         };
         let result = create_user(&pool, user.clone()).await;
         // Should fail because username and password are NOT NULL
-        assert!(result.is_err());
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_get_user_not_found() {
+        let pool = setup_pool().await;
+        let result = get_user(&pool, 42).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_sync_admins_replaces_the_admin_set() {
+        let pool = setup_pool().await;
+        for username in ["alice", "bob"] {
+            create_user(
+                &pool,
+                models::user::UserCreation {
+                    username: username.to_string(),
+                    password: "password".to_string(),
+                },
+            )
+            .await
+            .unwrap();
+        }
+        let admins = sync_admins(&pool, &[1]).await.unwrap();
+        assert_eq!(admins, 1);
+        assert!(is_admin(&pool, 1).await.unwrap());
+        assert!(!is_admin(&pool, 2).await.unwrap());
+
+        sync_admins(&pool, &[2]).await.unwrap();
+        assert!(!is_admin(&pool, 1).await.unwrap());
+        assert!(is_admin(&pool, 2).await.unwrap());
+        assert!(!is_admin(&pool, 42).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_sync_admins_skips_ids_nobody_holds() {
+        let pool = setup_pool().await;
+        create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "alice".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let admins = sync_admins(&pool, &[1, 2]).await.unwrap();
+        assert_eq!(admins, 1);
+        assert!(is_admin(&pool, 1).await.unwrap());
+
+        // whoever signs up into the missing id later is not an admin until the
+        // next sync
+        create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "mallory".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        assert!(!is_admin(&pool, 2).await.unwrap());
     }
 }
@@ -0,0 +1,721 @@
+use std::time::Duration;
+
+use chrono::{NaiveDateTime, Timelike};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use sqlx::{FromRow, Row, SqlitePool};
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models;
+use crate::models::webhook::{DeliveryStatus, EventType, Webhook, WebhookDelivery};
+
+/// Headers sent with every delivery.
+pub const EVENT_ID_HEADER: &str = "X-Webhook-Id";
+pub const EVENT_TYPE_HEADER: &str = "X-Webhook-Event";
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+/// How long a webhook has to answer before the attempt counts as failed.
+pub const DELIVERY_TIMEOUT_SECS: u64 = 10;
+/// Upper bound of the wait between two attempts.
+pub const MAX_BACKOFF_SECS: i64 = 24 * 60 * 60;
+/// Deliveries attempted per pass of the dispatcher.
+const DELIVERY_BATCH_SIZE: i64 = 100;
+
+const WEBHOOK_COLUMNS: &str = "id, url, event_types, created_at";
+const DELIVERY_COLUMNS: &str = "d.id, d.webhook_id, d.event_id, e.event_type, e.payload,
+    e.created_at AS event_created_at, d.status, d.attempts, d.next_attempt_at,
+    d.last_status_code, d.last_error, d.delivered_at, d.created_at";
+
+/// How often a failing delivery is retried and how long it waits in between.
+#[derive(Debug, Clone)]
+pub struct WebhookPolicy {
+    pub max_attempts: i64,
+    pub backoff_base_secs: i64,
+}
+
+impl WebhookPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        WebhookPolicy {
+            max_attempts: config.webhook_max_attempts,
+            backoff_base_secs: config.webhook_backoff_base_secs,
+        }
+    }
+
+    /// The wait after the `attempts`th failed attempt: the base, doubled for
+    /// every attempt before it, capped at `MAX_BACKOFF_SECS`.
+    fn backoff(&self, attempts: i64) -> chrono::Duration {
+        let doublings = attempts.saturating_sub(1).clamp(0, 32) as u32;
+        let secs = self
+            .backoff_base_secs
+            .saturating_mul(1i64 << doublings)
+            .min(MAX_BACKOFF_SECS);
+        chrono::Duration::seconds(secs)
+    }
+}
+
+// stored times carry whole seconds, so they compare correctly as TEXT
+fn truncate(at: NaiveDateTime) -> NaiveDateTime {
+    at.with_nanosecond(0).unwrap_or(at)
+}
+
+fn hmac_sha256_hex(key: &[u8], message: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any size");
+    mac.update(message);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Value of the signature header: the Unix time of the attempt and the
+/// HMAC-SHA256 of `"{timestamp}.{body}"` under the webhook's secret, in hex.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let signature = hmac_sha256_hex(
+        secret.as_bytes(),
+        format!("{}.{}", timestamp, body).as_bytes(),
+    );
+    format!("t={},v1={}", timestamp, signature)
+}
+
+pub async fn get_webhooks(db: &SqlitePool) -> Result<Vec<Webhook>, AppError> {
+    tracing::info!("Invocation to `get_webhooks`");
+    let webhooks: Vec<Webhook> = sqlx::query_as(&format!(
+        "SELECT {} FROM WEBHOOKS ORDER BY id;",
+        WEBHOOK_COLUMNS
+    ))
+    .fetch_all(db)
+    .await?;
+    Ok(webhooks)
+}
+
+/// Registers a webhook for events recorded from now on, generating the secret
+/// its deliveries are signed with.
+pub async fn create_webhook(
+    db: &SqlitePool,
+    webhook_creation: models::webhook::WebhookCreation,
+) -> Result<models::webhook::WebhookRegistration, AppError> {
+    tracing::info!("Invocation to `create_webhook`");
+    let url = reqwest::Url::parse(&webhook_creation.url)
+        .map_err(|err| AppError::Validation(format!("Invalid webhook url: {}", err)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::Validation(
+            "Webhook url must be http or https".to_string(),
+        ));
+    }
+    let secret: String = rand::thread_rng()
+        .r#gen::<[u8; 32]>()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let mut event_types: Vec<&str> = Vec::new();
+    for event_type in &webhook_creation.event_types {
+        if !event_types.contains(&event_type.as_str()) {
+            event_types.push(event_type.as_str());
+        }
+    }
+    let webhook: Webhook = sqlx::query_as(&format!(
+        "INSERT INTO WEBHOOKS (url, secret, event_types) VALUES (?, ?, ?) RETURNING {};",
+        WEBHOOK_COLUMNS
+    ))
+    .bind(url.as_str())
+    .bind(&secret)
+    .bind(Some(event_types.join(",")).filter(|joined| !joined.is_empty()))
+    .fetch_one(db)
+    .await?;
+    Ok(models::webhook::WebhookRegistration { webhook, secret })
+}
+
+/// Removes a webhook along with its deliveries, pending or not.
+pub async fn delete_webhook(db: &SqlitePool, id: i64) -> Result<Webhook, AppError> {
+    tracing::info!("Invocation to `delete_webhook`");
+    let webhook: Option<Webhook> = sqlx::query_as(&format!(
+        "DELETE FROM WEBHOOKS WHERE id = ? RETURNING {};",
+        WEBHOOK_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+    webhook.ok_or_else(|| AppError::NotFound(format!("Webhook {} not found", id)))
+}
+
+async fn get_delivery(db: &SqlitePool, id: i64) -> Result<WebhookDelivery, AppError> {
+    let delivery: Option<WebhookDelivery> = sqlx::query_as(&format!(
+        "SELECT {} FROM WEBHOOK_DELIVERIES d JOIN OUTBOX_EVENTS e ON e.id = d.event_id
+         WHERE d.id = ?;",
+        DELIVERY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+    delivery.ok_or_else(|| AppError::NotFound(format!("Delivery {} not found", id)))
+}
+
+/// Deliveries that ran out of attempts, oldest first.
+pub async fn get_dead_letters(db: &SqlitePool) -> Result<Vec<WebhookDelivery>, AppError> {
+    tracing::info!("Invocation to `get_dead_letters`");
+    let deliveries: Vec<WebhookDelivery> = sqlx::query_as(&format!(
+        "SELECT {} FROM WEBHOOK_DELIVERIES d JOIN OUTBOX_EVENTS e ON e.id = d.event_id
+         WHERE d.status = 'dead' ORDER BY d.id;",
+        DELIVERY_COLUMNS
+    ))
+    .fetch_all(db)
+    .await?;
+    Ok(deliveries)
+}
+
+/// Puts a dead delivery back in the queue with a fresh set of attempts.
+pub async fn retry_delivery(db: &SqlitePool, id: i64) -> Result<WebhookDelivery, AppError> {
+    tracing::info!("Invocation to `retry_delivery`");
+    let delivery = get_delivery(db, id).await?;
+    if delivery.status != DeliveryStatus::Dead {
+        return Err(AppError::Conflict(format!(
+            "Delivery {} is not dead, it is {:?}",
+            id, delivery.status
+        )));
+    }
+    sqlx::query(
+        "UPDATE WEBHOOK_DELIVERIES SET status = 'pending', attempts = 0, next_attempt_at = ?
+         WHERE id = ? AND status = 'dead';",
+    )
+    .bind(truncate(chrono::Utc::now().naive_utc()))
+    .bind(id)
+    .execute(db)
+    .await?;
+    get_delivery(db, id).await
+}
+
+/// Queues a delivery of every outbox event not yet dispatched to each webhook
+/// subscribed to it, due at `now`. Returns how many events were dispatched.
+pub async fn queue_deliveries(db: &SqlitePool, now: NaiveDateTime) -> Result<u64, AppError> {
+    let now = truncate(now);
+    let events: Vec<(i64, EventType)> = sqlx::query_as(
+        "SELECT id, event_type FROM OUTBOX_EVENTS WHERE dispatched_at IS NULL ORDER BY id;",
+    )
+    .fetch_all(db)
+    .await?;
+    if events.is_empty() {
+        return Ok(0);
+    }
+    let webhooks = get_webhooks(db).await?;
+    let mut tx = db.begin().await?;
+    for (event_id, event_type) in &events {
+        for webhook in &webhooks {
+            if !webhook.event_types.is_empty() && !webhook.event_types.contains(event_type) {
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO WEBHOOK_DELIVERIES (webhook_id, event_id, next_attempt_at)
+                 VALUES (?, ?, ?) ON CONFLICT (webhook_id, event_id) DO NOTHING;",
+            )
+            .bind(webhook.id)
+            .bind(event_id)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query("UPDATE OUTBOX_EVENTS SET dispatched_at = ? WHERE id = ?;")
+            .bind(now)
+            .bind(event_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(events.len() as u64)
+}
+
+/// Sends one attempt of a delivery. Anything but a 2xx answer is a failure;
+/// redirects are not followed.
+async fn send(
+    client: &reqwest::Client,
+    url: &str,
+    secret: &str,
+    delivery: &WebhookDelivery,
+    now: NaiveDateTime,
+) -> Result<u16, (Option<u16>, String)> {
+    let body = serde_json::to_string(&delivery.event)
+        .map_err(|err| (None, format!("Could not serialize event: {}", err)))?;
+    let response = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_ID_HEADER, delivery.event.id.to_string())
+        .header(EVENT_TYPE_HEADER, delivery.event.event_type.as_str())
+        .header(
+            SIGNATURE_HEADER,
+            sign(secret, now.and_utc().timestamp(), &body),
+        )
+        .body(body)
+        .send()
+        .await
+        .map_err(|err| (None, err.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        Ok(status.as_u16())
+    } else {
+        Err((Some(status.as_u16()), format!("HTTP {}", status)))
+    }
+}
+
+/// Attempts every pending delivery due at `now`. Failures wait out the
+/// policy's backoff; a delivery out of attempts is dead. Returns how many
+/// deliveries were attempted.
+pub async fn deliver_due(
+    db: &SqlitePool,
+    client: &reqwest::Client,
+    policy: &WebhookPolicy,
+    now: NaiveDateTime,
+) -> Result<u64, AppError> {
+    let now = truncate(now);
+    let due = sqlx::query(&format!(
+        "SELECT {}, w.url, w.secret FROM WEBHOOK_DELIVERIES d
+         JOIN OUTBOX_EVENTS e ON e.id = d.event_id
+         JOIN WEBHOOKS w ON w.id = d.webhook_id
+         WHERE d.status = 'pending' AND d.next_attempt_at <= ?
+         ORDER BY d.next_attempt_at, d.id LIMIT ?;",
+        DELIVERY_COLUMNS
+    ))
+    .bind(now)
+    .bind(DELIVERY_BATCH_SIZE)
+    .fetch_all(db)
+    .await?;
+    for row in &due {
+        let delivery = WebhookDelivery::from_row(row)?;
+        let url: String = row.try_get("url")?;
+        let secret: String = row.try_get("secret")?;
+        let attempts = delivery.attempts + 1;
+        match send(client, &url, &secret, &delivery, now).await {
+            Ok(status_code) => {
+                sqlx::query(
+                    "UPDATE WEBHOOK_DELIVERIES SET status = 'delivered', attempts = ?,
+                     last_status_code = ?, last_error = NULL, delivered_at = ? WHERE id = ?;",
+                )
+                .bind(attempts)
+                .bind(status_code)
+                .bind(now)
+                .bind(delivery.id)
+                .execute(db)
+                .await?;
+            }
+            Err((status_code, error)) => {
+                let status = if attempts >= policy.max_attempts {
+                    tracing::warn!(
+                        "Delivery {} of event {} to webhook {} is dead after {} attempts: {}",
+                        delivery.id,
+                        delivery.event.id,
+                        delivery.webhook_id,
+                        attempts,
+                        error
+                    );
+                    DeliveryStatus::Dead
+                } else {
+                    DeliveryStatus::Pending
+                };
+                sqlx::query(
+                    "UPDATE WEBHOOK_DELIVERIES SET status = ?, attempts = ?, next_attempt_at = ?,
+                     last_status_code = ?, last_error = ? WHERE id = ?;",
+                )
+                .bind(status)
+                .bind(attempts)
+                .bind(now + policy.backoff(attempts))
+                .bind(status_code)
+                .bind(error)
+                .bind(delivery.id)
+                .execute(db)
+                .await?;
+            }
+        }
+    }
+    Ok(due.len() as u64)
+}
+
+/// Background task that dispatches outbox events to webhooks every `poll_interval_secs`.
+pub async fn run_dispatcher(db: SqlitePool, policy: WebhookPolicy, poll_interval_secs: u64) {
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(DELIVERY_TIMEOUT_SECS))
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            tracing::error!("Could not start the webhook dispatcher: {}", err);
+            return;
+        }
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(poll_interval_secs));
+    loop {
+        interval.tick().await;
+        let now = chrono::Utc::now().naive_utc();
+        if let Err(err) = queue_deliveries(&db, now).await {
+            tracing::error!("Queueing webhook deliveries failed: {}", err);
+        }
+        match deliver_due(&db, &client, &policy, now).await {
+            Ok(0) => {}
+            Ok(attempted) => tracing::info!("Attempted {} webhook deliveries", attempted),
+            Err(err) => tracing::error!("Delivering webhooks failed: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU16, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use axum::http::{HeaderMap, StatusCode};
+
+    use crate::migrations;
+    use crate::models::money::{Currency, Money};
+    use crate::models::transaction::TransactionCreation;
+    use crate::models::webhook::{Event, WebhookCreation};
+    use crate::services::{account_service, transaction_service, user_service};
+
+    use super::*;
+
+    const POLICY: WebhookPolicy = WebhookPolicy {
+        max_attempts: 3,
+        backoff_base_secs: 10,
+    };
+
+    /// A local HTTP server standing in for a webhook receiver. It answers with
+    /// `status` and keeps every request it gets.
+    #[derive(Clone, Default)]
+    struct Stub {
+        status: Arc<AtomicU16>,
+        received: Arc<Mutex<Vec<(HeaderMap, String)>>>,
+    }
+
+    impl Stub {
+        async fn start(status: u16) -> (Stub, String) {
+            let stub = Stub::default();
+            stub.status.store(status, Ordering::SeqCst);
+            let app = axum::Router::new()
+                .route(
+                    "/hook",
+                    axum::routing::post(
+                        |axum::extract::State(stub): axum::extract::State<Stub>,
+                         headers: HeaderMap,
+                         body: String| async move {
+                            stub.received.lock().unwrap().push((headers, body));
+                            StatusCode::from_u16(stub.status.load(Ordering::SeqCst)).unwrap()
+                        },
+                    ),
+                )
+                .with_state(stub.clone());
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            (stub, url)
+        }
+
+        fn received(&self) -> Vec<(HeaderMap, String)> {
+            self.received.lock().unwrap().clone()
+        }
+    }
+
+    fn client() -> reqwest::Client {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(DELIVERY_TIMEOUT_SECS))
+            .build()
+            .unwrap()
+    }
+
+    async fn setup_db() -> (SqlitePool, String) {
+        let pool = SqlitePool::connect(":memory:").await.unwrap();
+        migrations::run(&pool).await.unwrap();
+        user_service::create_user(
+            &pool,
+            models::user::UserCreation {
+                username: "alice".to_string(),
+                password: "password".to_string(),
+            },
+        )
+        .await
+        .unwrap();
+        let account = account_service::create_account(
+            &pool,
+            &Default::default(),
+            1,
+            models::account::AccountCreation {
+                currency: Currency::Usd,
+                product: None,
+            },
+        )
+        .await
+        .unwrap();
+        (pool, account.account_number)
+    }
+
+    async fn post(db: &SqlitePool, account_number: &str, minor: i64) -> Result<(), AppError> {
+        transaction_service::create_transaction(
+            db,
+            1,
+            TransactionCreation {
+                account_number: account_number.to_string(),
+                seller: "Shop".to_string(),
+                amount: Money::new(minor, Currency::Usd),
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
+    async fn event_types(db: &SqlitePool) -> Vec<EventType> {
+        sqlx::query_scalar("SELECT event_type FROM OUTBOX_EVENTS ORDER BY id;")
+            .fetch_all(db)
+            .await
+            .unwrap()
+    }
+
+    async fn delivery_status(db: &SqlitePool) -> Vec<(DeliveryStatus, i64)> {
+        sqlx::query_as("SELECT status, attempts FROM WEBHOOK_DELIVERIES ORDER BY id;")
+            .fetch_all(db)
+            .await
+            .unwrap()
+    }
+
+    #[test]
+    fn test_sign() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            hmac_sha256_hex(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        let signature = sign("secret", 1_700_000_000, "{}");
+        assert_eq!(
+            signature,
+            format!(
+                "t=1700000000,v1={}",
+                hmac_sha256_hex(b"secret", b"1700000000.{}")
+            )
+        );
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        assert_eq!(POLICY.backoff(1).num_seconds(), 10);
+        assert_eq!(POLICY.backoff(2).num_seconds(), 20);
+        assert_eq!(POLICY.backoff(4).num_seconds(), 80);
+        assert_eq!(POLICY.backoff(40).num_seconds(), MAX_BACKOFF_SECS);
+    }
+
+    #[tokio::test]
+    async fn test_events_are_recorded_with_the_change() {
+        let (db, account) = setup_db().await;
+        post(&db, &account, -1_000).await.unwrap();
+        // rolled back along with the failed payment
+        assert!(post(&db, &account, 5_000).await.is_err());
+        assert_eq!(
+            event_types(&db).await,
+            vec![
+                EventType::UserCreated,
+                EventType::AccountCreated,
+                EventType::TransactionPosted,
+                EventType::BalanceChanged
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_delivers_signed_events_to_subscribed_webhooks() {
+        let (db, account) = setup_db().await;
+        let (stub, url) = Stub::start(200).await;
+        // recorded before the webhooks existed
+        assert_eq!(
+            queue_deliveries(&db, chrono::Utc::now().naive_utc())
+                .await
+                .unwrap(),
+            2
+        );
+        let posted = create_webhook(
+            &db,
+            WebhookCreation {
+                url: url.clone(),
+                event_types: vec![EventType::TransactionPosted],
+            },
+        )
+        .await
+        .unwrap();
+        create_webhook(
+            &db,
+            WebhookCreation {
+                url: url.clone(),
+                event_types: vec![EventType::TransferCompleted],
+            },
+        )
+        .await
+        .unwrap();
+        post(&db, &account, -1_000).await.unwrap();
+
+        let now = chrono::Utc::now().naive_utc();
+        // the transaction and the balance change; only the former is subscribed to
+        assert_eq!(queue_deliveries(&db, now).await.unwrap(), 2);
+        assert_eq!(deliver_due(&db, &client(), &POLICY, now).await.unwrap(), 1);
+        assert_eq!(
+            delivery_status(&db).await,
+            vec![(DeliveryStatus::Delivered, 1)]
+        );
+
+        let received = stub.received();
+        assert_eq!(received.len(), 1);
+        let (headers, body) = &received[0];
+        let event: Event = serde_json::from_str(body).unwrap();
+        assert_eq!(event.event_type, EventType::TransactionPosted);
+        assert_eq!(event.data["account_number"], account.as_str());
+        assert_eq!(event.data["amount"]["amount"], "-10.00");
+        assert_eq!(headers[EVENT_ID_HEADER], event.id.to_string());
+        assert_eq!(headers[EVENT_TYPE_HEADER], "transaction.posted");
+        let signature = headers[SIGNATURE_HEADER].to_str().unwrap();
+        let timestamp: i64 = signature
+            .strip_prefix("t=")
+            .and_then(|rest| rest.split(',').next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert_eq!(signature, sign(&posted.secret, timestamp, body));
+    }
+
+    #[tokio::test]
+    async fn test_failed_deliveries_back_off_then_go_dead() {
+        let (db, account) = setup_db().await;
+        let (stub, url) = Stub::start(500).await;
+        let now = chrono::Utc::now().naive_utc();
+        queue_deliveries(&db, now).await.unwrap();
+        create_webhook(
+            &db,
+            WebhookCreation {
+                url,
+                event_types: vec![EventType::TransactionPosted],
+            },
+        )
+        .await
+        .unwrap();
+        post(&db, &account, -1_000).await.unwrap();
+        queue_deliveries(&db, now).await.unwrap();
+
+        let at = |secs: i64| now + chrono::Duration::seconds(secs);
+        assert_eq!(
+            deliver_due(&db, &client(), &POLICY, at(0)).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            delivery_status(&db).await,
+            vec![(DeliveryStatus::Pending, 1)]
+        );
+        // still backing off
+        assert_eq!(
+            deliver_due(&db, &client(), &POLICY, at(9)).await.unwrap(),
+            0
+        );
+        assert_eq!(
+            deliver_due(&db, &client(), &POLICY, at(10)).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            deliver_due(&db, &client(), &POLICY, at(29)).await.unwrap(),
+            0
+        );
+        assert_eq!(
+            deliver_due(&db, &client(), &POLICY, at(30)).await.unwrap(),
+            1
+        );
+        assert_eq!(delivery_status(&db).await, vec![(DeliveryStatus::Dead, 3)]);
+        assert_eq!(
+            deliver_due(&db, &client(), &POLICY, at(3600))
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(stub.received().len(), 3);
+
+        let dead = get_dead_letters(&db).await.unwrap();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].last_status_code, Some(500));
+        assert_eq!(dead[0].event.event_type, EventType::TransactionPosted);
+
+        stub.status.store(204, Ordering::SeqCst);
+        let retried = retry_delivery(&db, dead[0].id).await.unwrap();
+        assert_eq!(retried.status, DeliveryStatus::Pending);
+        assert!(matches!(
+            retry_delivery(&db, dead[0].id).await,
+            Err(AppError::Conflict(_))
+        ));
+        let later = chrono::Utc::now().naive_utc() + chrono::Duration::seconds(1);
+        assert_eq!(
+            deliver_due(&db, &client(), &POLICY, later).await.unwrap(),
+            1
+        );
+        assert_eq!(
+            delivery_status(&db).await,
+            vec![(DeliveryStatus::Delivered, 1)]
+        );
+        assert!(get_dead_letters(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_webhook_is_retried() {
+        let (db, account) = setup_db().await;
+        // nothing listens here once the listener is dropped
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+        let now = chrono::Utc::now().naive_utc();
+        queue_deliveries(&db, now).await.unwrap();
+        create_webhook(
+            &db,
+            WebhookCreation {
+                url,
+                event_types: vec![EventType::TransactionPosted],
+            },
+        )
+        .await
+        .unwrap();
+        post(&db, &account, -1_000).await.unwrap();
+        queue_deliveries(&db, now).await.unwrap();
+        deliver_due(&db, &client(), &POLICY, now).await.unwrap();
+        let (status_code, error): (Option<i64>, Option<String>) =
+            sqlx::query_as("SELECT last_status_code, last_error FROM WEBHOOK_DELIVERIES;")
+                .fetch_one(&db)
+                .await
+                .unwrap();
+        assert_eq!(status_code, None);
+        assert!(error.is_some());
+        assert_eq!(
+            delivery_status(&db).await,
+            vec![(DeliveryStatus::Pending, 1)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_webhook_validation() {
+        let (db, _) = setup_db().await;
+        for url in ["not a url", "ftp://example.com/hook"] {
+            let res = create_webhook(
+                &db,
+                WebhookCreation {
+                    url: url.to_string(),
+                    event_types: vec![],
+                },
+            )
+            .await;
+            assert!(matches!(res, Err(AppError::Validation(_))), "{}", url);
+        }
+        let registration = create_webhook(
+            &db,
+            WebhookCreation {
+                url: "https://example.com/hook".to_string(),
+                event_types: vec![EventType::UserCreated, EventType::UserCreated],
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(registration.secret.len(), 64);
+        assert_eq!(
+            registration.webhook.event_types,
+            vec![EventType::UserCreated]
+        );
+        let deleted = delete_webhook(&db, registration.webhook.id).await.unwrap();
+        assert_eq!(deleted, registration.webhook);
+        assert!(get_webhooks(&db).await.unwrap().is_empty());
+    }
+}
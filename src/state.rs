@@ -0,0 +1,16 @@
+// src/state.rs
+// Defines the shared application state handed to every handler
+use axum::extract::FromRef;
+use sqlx::SqlitePool;
+
+use crate::services::generation_service::AccountNumberScheme;
+use crate::services::stream_service::EventHub;
+use crate::services::token_service::TokenKeys;
+
+#[derive(Clone, FromRef)]
+pub struct AppState {
+    pub pool: SqlitePool,
+    pub tokens: TokenKeys,
+    pub account_numbers: AccountNumberScheme,
+    pub events: EventHub,
+}